@@ -5,7 +5,7 @@ use curve25519_dalek::constants;
 use curve25519_dalek::ristretto::CompressedRistretto;
 use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
-use musig::VerificationKey;
+use musig::{Multikey, MusigError, VerificationKey};
 use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 
@@ -129,6 +129,27 @@ impl Xpub {
         (self.pubkey.as_point() + (&f * &constants::RISTRETTO_BASEPOINT_TABLE)).into()
     }
 
+    /// Derives a leaf key from each of the cosigners' `xpubs` along the same path
+    /// (see `Xpub::derive_key`) and aggregates the derived keys into a MuSig `Multikey`.
+    ///
+    /// The derived keys are sorted by `Multikey::new`, so the result does not depend
+    /// on the order of the `xpubs`. Each cosigner signs with the secret scalar
+    /// derived with `Xprv::derive_key` along the same path, at the position of its key
+    /// in the multikey (see `Multikey::position`).
+    /// Fails with `MusigError::DuplicateKey` if two xpubs derive the same key,
+    /// and with `MusigError::BadArguments` if `xpubs` is empty.
+    pub fn derive_multikey(
+        xpubs: &[Xpub],
+        customize: impl Fn(&mut Transcript),
+    ) -> Result<Multikey, MusigError> {
+        Multikey::new(
+            xpubs
+                .iter()
+                .map(|xpub| xpub.derive_key(|prf| customize(prf)))
+                .collect(),
+        )
+    }
+
     /// Serializes this Xpub to a sequence of bytes.
     pub fn to_bytes(&self) -> [u8; 64] {
         let mut buf = [0u8; 64];
@@ -254,6 +254,55 @@ fn random_xpub_leaf_test() {
     );
 }
 
+#[test]
+fn derive_multikey_test() {
+    let mut rng = ChaChaRng::from_seed([0u8; 32]);
+    let xprvs: Vec<Xprv> = (0..3).map(|_| Xprv::random(&mut rng)).collect();
+    let xpubs: Vec<Xpub> = xprvs.iter().map(|xprv| xprv.to_xpub()).collect();
+    let path = |prf: &mut Transcript| prf.append_u64(b"account_id", 34);
+
+    // Aggregation does not depend on the order of the cosigners.
+    let multikey = Xpub::derive_multikey(&xpubs, path).unwrap();
+    let reversed: Vec<Xpub> = xpubs.iter().rev().cloned().collect();
+    let multikey2 = Xpub::derive_multikey(&reversed, path).unwrap();
+    assert_eq!(multikey.aggregated_key(), multikey2.aggregated_key());
+    assert_eq!(multikey.factors(), multikey2.factors());
+
+    let derived: Vec<VerificationKey> = xpubs.iter().map(|xpub| xpub.derive_key(path)).collect();
+    assert_eq!(
+        Multikey::aggregate(derived.clone()),
+        Ok(multikey.aggregated_key())
+    );
+    assert_eq!(
+        Multikey::aggregate(derived.into_iter().rev().collect()),
+        Ok(multikey.aggregated_key())
+    );
+
+    // The aggregated secret key matches the aggregated key.
+    let aggregated_secret: Scalar = xprvs
+        .iter()
+        .map(|xprv| {
+            let secret = xprv.derive_key(path);
+            let position = multikey
+                .position(&VerificationKey::from_secret(&secret))
+                .unwrap();
+            multikey.factor(position) * secret
+        })
+        .sum();
+    assert_eq!(
+        VerificationKey::from_secret(&aggregated_secret),
+        multikey.aggregated_key()
+    );
+
+    // A different path yields a different key.
+    let other = Xpub::derive_multikey(&xpubs, |prf| prf.append_u64(b"account_id", 35)).unwrap();
+    assert_ne!(other.aggregated_key(), multikey.aggregated_key());
+
+    // The same cosigner cannot be included twice.
+    assert!(Xpub::derive_multikey(&[xpubs[0], xpubs[0]], path).is_err());
+    assert!(Xpub::derive_multikey(&[], path).is_err());
+}
+
 fn to_hex_32(input: [u8; 32]) -> String {
     return hex::encode(&input[..]);
 }
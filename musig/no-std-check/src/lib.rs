@@ -0,0 +1,33 @@
+//! Builds the signing and verification paths of `musig` without `std`:
+//! `cargo build --target thumbv7em-none-eabihf`
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec;
+use curve25519_dalek::scalar::Scalar;
+use merlin::Transcript;
+use musig::{DeferredVerification, MusigError, Signature, VerificationKey};
+use rand::{CryptoRng, RngCore};
+
+/// Signs a message with a single key and a multi-message context, and verifies both signatures.
+pub fn sign_and_verify<R: RngCore + CryptoRng>(
+    privkey: Scalar,
+    rng: &mut R,
+) -> Result<(), MusigError> {
+    let pubkey = VerificationKey::from_secret(&privkey);
+
+    let sig = Signature::sign_single_with_rng(&mut Transcript::new(b"no_std"), privkey, rng);
+    let single = sig.verify(&mut Transcript::new(b"no_std"), pubkey);
+
+    let messages = vec![(pubkey, b"message")];
+    let sig = Signature::sign_multi_with_rng(
+        vec![privkey],
+        messages.clone(),
+        &mut Transcript::new(b"no_std"),
+        rng,
+    )?;
+    let multi = sig.verify_multi(&mut Transcript::new(b"no_std"), messages);
+
+    DeferredVerification::verify_batch_with_rng(&[single, multi], rng)
+}
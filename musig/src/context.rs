@@ -1,6 +1,9 @@
 use super::errors::MusigError;
 use super::key::VerificationKey;
 use super::transcript::TranscriptProtocol;
+use alloc::vec::Vec;
+#[cfg(feature = "debug-transcript")]
+use curve25519_dalek::ristretto::CompressedRistretto;
 use curve25519_dalek::ristretto::RistrettoPoint;
 use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
@@ -24,7 +27,7 @@ pub trait MusigContext {
 /// MuSig aggregated key context
 #[derive(Clone)]
 pub struct Multikey {
-    prf: Option<Transcript>,
+    factors: Vec<Scalar>, // delinearization factors a_i, cached for reuse across signing sessions
     aggregated_key: VerificationKey,
     public_keys: Vec<VerificationKey>,
 }
@@ -35,9 +38,29 @@ pub struct Multimessage<M: AsRef<[u8]>> {
     pairs: Vec<(VerificationKey, M)>,
 }
 
+/// Builder of a `Multimessage` context, accumulating the pairs of pubkeys and messages.
+/// Created with `Multimessage::builder`.
+#[derive(Clone)]
+pub struct MultimessageBuilder<M: AsRef<[u8]>> {
+    pairs: Vec<(VerificationKey, M)>,
+}
+
 impl Multikey {
     /// Constructs a new MuSig multikey aggregating the pubkeys.
-    pub fn new(pubkeys: Vec<VerificationKey>) -> Result<Self, MusigError> {
+    ///
+    /// The pubkeys are sorted in lexicographic order of their compressed encodings,
+    /// so all parties derive the same aggregated key regardless of the input order.
+    /// The sort order is part of the protocol: signers must use the position of
+    /// their pubkey in the sorted list (see `Multikey::position`).
+    /// Fails with `MusigError::DuplicateKey` if a pubkey appears more than once.
+    pub fn new(mut pubkeys: Vec<VerificationKey>) -> Result<Self, MusigError> {
+        pubkeys.sort_unstable_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        if let Some(pair) = pubkeys.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(MusigError::DuplicateKey {
+                pubkey: pair[0].to_bytes(),
+            });
+        }
+
         match pubkeys.len() {
             0 => {
                 return Err(MusigError::BadArguments);
@@ -46,7 +69,7 @@ impl Multikey {
                 // Special case: single key can be wrapped in a Multikey type
                 // without a delinearization factor applied.
                 return Ok(Multikey {
-                    prf: None,
+                    factors: alloc::vec![Scalar::one()],
                     aggregated_key: pubkeys[0],
                     public_keys: pubkeys,
                 });
@@ -54,31 +77,46 @@ impl Multikey {
             _ => {}
         }
 
-        // Create transcript for Multikey
-        let mut prf = Transcript::new(b"Musig.aggregated-key");
-        prf.append_u64(b"n", pubkeys.len() as u64);
-
-        // Commit pubkeys into the transcript
-        // <L> = H(X_1 || X_2 || ... || X_n)
-        for X in &pubkeys {
-            prf.commit_point(b"X", X.as_compressed());
-        }
+        let factors = Multikey::compute_factors(&pubkeys);
 
         // aggregated_key = sum_i ( a_i * X_i )
         let mut aggregated_key = RistrettoPoint::default();
-        for (i, X) in pubkeys.iter().enumerate() {
-            let a = Multikey::compute_factor(&prf, i);
-            let X = X.into_point();
-            aggregated_key = aggregated_key + a * X;
+        for (a, X) in factors.iter().zip(pubkeys.iter()) {
+            aggregated_key = aggregated_key + a * X.into_point();
         }
 
         Ok(Multikey {
-            prf: Some(prf),
+            factors,
             aggregated_key: VerificationKey::from(aggregated_key),
             public_keys: pubkeys,
         })
     }
 
+    /// Returns the aggregated key of the `pubkeys`, without keeping the multikey context.
+    /// Same as `Multikey::new(pubkeys)?.aggregated_key()`, so the order of the pubkeys
+    /// does not matter: e.g. keys derived by each cosigner from their own master key
+    /// can be collected in any order.
+    pub fn aggregate(pubkeys: Vec<VerificationKey>) -> Result<VerificationKey, MusigError> {
+        Ok(Multikey::new(pubkeys)?.aggregated_key())
+    }
+
+    /// Computes the `a_i` factors for all the pubkeys, in order.
+    pub(crate) fn compute_factors(pubkeys: &[VerificationKey]) -> Vec<Scalar> {
+        // Create transcript for Multikey
+        let mut prf = Transcript::new(b"Musig.aggregated-key");
+        prf.append_u64(b"n", pubkeys.len() as u64);
+
+        // Commit pubkeys into the transcript
+        // <L> = H(X_1 || X_2 || ... || X_n)
+        for X in pubkeys {
+            prf.commit_point(b"X", X.as_compressed());
+        }
+
+        (0..pubkeys.len())
+            .map(|i| Multikey::compute_factor(&prf, i))
+            .collect()
+    }
+
     /// Returns `a_i` factor for component key in aggregated key.
     /// a_i = H(<L>, X_i). The list of pubkeys, <L>, has already been committed to the transcript.
     fn compute_factor(prf: &Transcript, i: usize) -> Scalar {
@@ -91,6 +129,39 @@ impl Multikey {
     pub fn aggregated_key(&self) -> VerificationKey {
         self.aggregated_key
     }
+
+    /// Returns the position of the `pubkey` in the sorted list of keys,
+    /// or None if the multikey does not contain it.
+    pub fn position(&self, pubkey: &VerificationKey) -> Option<usize> {
+        self.public_keys.iter().position(|key| key == pubkey)
+    }
+
+    /// Returns the delinearization factor `a_i` of the key at `index`,
+    /// so that the aggregated key equals `sum_i(a_i * X_i)`.
+    /// Returns one if the multikey wraps a single key.
+    pub fn factor(&self, index: usize) -> Scalar {
+        self.factors[index]
+    }
+
+    /// Returns the delinearization factors `a_i` of all the keys, in order.
+    /// The factors are computed once, when the multikey is created,
+    /// so a multikey can be reused to sign many messages by the same set of keys.
+    pub fn factors(&self) -> &[Scalar] {
+        &self.factors
+    }
+}
+
+#[cfg(feature = "debug-transcript")]
+impl Multikey {
+    /// Returns the signature challenge `c = H(X, R, m)` for the nonce sum `R`.
+    /// The `transcript` must contain the message, as it does before signing.
+    /// The per-party challenge is `c * a_i`.
+    pub fn signing_challenge(&self, transcript: &Transcript, R: &CompressedRistretto) -> Scalar {
+        let mut transcript = transcript.clone();
+        self.commit(&mut transcript);
+        transcript.commit_point(b"R", R);
+        transcript.challenge_scalar(b"c")
+    }
 }
 
 impl MusigContext for Multikey {
@@ -107,12 +178,7 @@ impl MusigContext for Multikey {
 
         // Make a_i, the per-party factor. a_i = H(<L>, X_i).
         // The list of pubkeys, <L>, has already been committed to self.transcript.
-        let a_i = match &self.prf {
-            Some(t) => Multikey::compute_factor(&t, i),
-            None => Scalar::one(),
-        };
-
-        c * a_i
+        c * self.factor(i)
     }
 
     fn len(&self) -> usize {
@@ -129,6 +195,38 @@ impl<M: AsRef<[u8]>> Multimessage<M> {
     pub fn new(pairs: Vec<(VerificationKey, M)>) -> Self {
         Self { pairs }
     }
+
+    /// Returns a builder for the multimessage context.
+    /// E.g. `Multimessage::builder().add(key1, msg1).add(key2, msg2).build()`.
+    pub fn builder() -> MultimessageBuilder<M> {
+        MultimessageBuilder { pairs: Vec::new() }
+    }
+}
+
+impl<M: AsRef<[u8]>> MultimessageBuilder<M> {
+    /// Adds a message signed by the given pubkey.
+    /// The pubkeys are kept in the order they are added.
+    pub fn add(mut self, pubkey: VerificationKey, message: M) -> Self {
+        self.pairs.push((pubkey, message));
+        self
+    }
+
+    /// Constructs the multimessage context from the added pairs.
+    /// Fails with `MusigError::DuplicateKey` if a pubkey was added more than once,
+    /// and with `MusigError::BadArguments` if no pairs were added.
+    pub fn build(self) -> Result<Multimessage<M>, MusigError> {
+        if self.pairs.is_empty() {
+            return Err(MusigError::BadArguments);
+        }
+        for (i, (key, _)) in self.pairs.iter().enumerate() {
+            if self.pairs[..i].iter().any(|(prev, _)| prev == key) {
+                return Err(MusigError::DuplicateKey {
+                    pubkey: key.to_bytes(),
+                });
+            }
+        }
+        Ok(Multimessage::new(self.pairs))
+    }
 }
 
 impl<M: AsRef<[u8]>> MusigContext for Multimessage<M> {
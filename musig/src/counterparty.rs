@@ -2,6 +2,7 @@ use super::context::MusigContext;
 use super::errors::MusigError;
 use super::key::VerificationKey;
 use crate::transcript::TranscriptProtocol;
+use alloc::vec::Vec;
 use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
 use curve25519_dalek::ristretto::RistrettoPoint;
 use curve25519_dalek::scalar::Scalar;
@@ -90,7 +91,7 @@ impl CounterpartyPrecommitted {
 
 impl CounterpartyCommitted {
     pub(super) fn verify_share<C: MusigContext>(
-        self,
+        &self,
         share: Scalar,
         context: &C,
         transcript: &Transcript,
@@ -1,8 +1,10 @@
 use crate::errors::MusigError;
+use alloc::vec::Vec;
 use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
 use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
-use curve25519_dalek::traits::{Identity, IsIdentity, VartimeMultiscalarMul};
+use curve25519_dalek::traits::{IsIdentity, VartimeMultiscalarMul};
+use rand::{CryptoRng, RngCore};
 
 /// Deferred signature verification
 #[derive(Clone, Debug)]
@@ -23,24 +25,44 @@ impl DeferredVerification {
         Ok(())
     }
 
-    fn compute(self) -> Result<RistrettoPoint, MusigError> {
+    /// Returns the weights and the points of the verification, including the Ristretto base point,
+    /// so it can be merged into a larger multiscalar multiplication.
+    /// The verification succeeds if the weighted sum of the points is the identity.
+    /// Fails with `MusigError::InvalidPoint` if any of the points cannot be decompressed.
+    pub fn into_parts(self) -> Result<(Vec<Scalar>, Vec<RistrettoPoint>), MusigError> {
         let (mut weights, points): (Vec<_>, Vec<_>) =
             self.dynamic_point_weights.into_iter().unzip();
-        let mut points: Vec<_> = points.into_iter().map(|p| p.decompress()).collect();
+        let mut points = points
+            .into_iter()
+            .map(|p| p.decompress().ok_or(MusigError::InvalidPoint))
+            .collect::<Result<Vec<_>, _>>()?;
 
         weights.push(self.static_point_weight);
-        points.push(Some(RISTRETTO_BASEPOINT_POINT));
+        points.push(RISTRETTO_BASEPOINT_POINT);
 
-        if points.len() == 0 {
-            return Ok(RistrettoPoint::identity());
-        }
+        Ok((weights, points))
+    }
 
-        RistrettoPoint::optional_multiscalar_mul(weights, points)
-            .ok_or(MusigError::PointOperationFailed)
+    fn compute(self) -> Result<RistrettoPoint, MusigError> {
+        let (weights, points) = self
+            .into_parts()
+            .map_err(|_| MusigError::PointOperationFailed)?;
+        Ok(RistrettoPoint::vartime_multiscalar_mul(weights, points))
     }
 
     /// Batched evaluation of deferred signature verification.
+    /// Uses `rand::thread_rng` to sample the random weights.
+    #[cfg(feature = "std")]
     pub fn verify_batch(batch: &[DeferredVerification]) -> Result<(), MusigError> {
+        DeferredVerification::verify_batch_with_rng(batch, &mut rand::thread_rng())
+    }
+
+    /// Batched evaluation of deferred signature verification,
+    /// using the provided `rng` to sample the random weights.
+    pub fn verify_batch_with_rng<R: RngCore + CryptoRng>(
+        batch: &[DeferredVerification],
+        rng: &mut R,
+    ) -> Result<(), MusigError> {
         // Get the total number of points in batch
         let dyn_length: usize = batch.iter().map(|p| p.dynamic_point_weights.len()).sum();
         let length = 2 + dyn_length; // include the (B, B_blinding) pair
@@ -52,13 +74,11 @@ impl DeferredVerification {
         points.push(Some(RISTRETTO_BASEPOINT_POINT));
         weights.push(Scalar::zero());
 
-        let mut rng = rand::thread_rng();
-
         // Iterate over every point, adding both weights and points to
         // our arrays
         for p in batch.iter() {
             // Sample free variable e
-            let e = Scalar::random(&mut rng);
+            let e = Scalar::random(&mut *rng);
 
             weights[0] = weights[0] + e * p.static_point_weight;
 
@@ -79,10 +99,72 @@ impl DeferredVerification {
     }
 }
 
+/// Queue of deferred verifications accumulated over time
+/// and checked together in one batched multiscalar multiplication.
+#[derive(Clone, Debug, Default)]
+pub struct VerificationQueue {
+    verifications: Vec<DeferredVerification>,
+}
+
+impl VerificationQueue {
+    /// Creates an empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a deferred verification to the queue.
+    pub fn push(&mut self, verification: DeferredVerification) {
+        self.verifications.push(verification);
+    }
+
+    /// Returns the number of queued verifications.
+    pub fn len(&self) -> usize {
+        self.verifications.len()
+    }
+
+    /// Returns true if the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.verifications.is_empty()
+    }
+
+    /// Removes all queued verifications.
+    pub fn clear(&mut self) {
+        self.verifications.clear();
+    }
+
+    /// Verifies all queued verifications in one batch.
+    /// Succeeds only if every queued verification succeeds individually.
+    /// Uses `rand::thread_rng` to sample the random weights.
+    #[cfg(feature = "std")]
+    pub fn verify_all(&self) -> Result<(), MusigError> {
+        DeferredVerification::verify_batch(&self.verifications)
+    }
+
+    /// Verifies all queued verifications in one batch,
+    /// using the provided `rng` to sample the random weights.
+    pub fn verify_all_with_rng<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+    ) -> Result<(), MusigError> {
+        DeferredVerification::verify_batch_with_rng(&self.verifications, rng)
+    }
+}
+
+impl Extend<DeferredVerification> for VerificationQueue {
+    fn extend<T: IntoIterator<Item = DeferredVerification>>(&mut self, iter: T) {
+        self.verifications.extend(iter);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::key::VerificationKey;
+    use crate::signature::Signature;
     use curve25519_dalek::constants::RISTRETTO_BASEPOINT_COMPRESSED;
+    use merlin::Transcript;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
     #[test]
     fn empty() {
@@ -97,13 +179,13 @@ mod tests {
     fn primary_generator() {
         let v = DeferredVerification {
             static_point_weight: Scalar::one(),
-            dynamic_point_weights: vec![(-Scalar::one(), RISTRETTO_BASEPOINT_COMPRESSED)],
+            dynamic_point_weights: alloc::vec![(-Scalar::one(), RISTRETTO_BASEPOINT_COMPRESSED)],
         };
         assert!(v.verify().is_ok());
 
         let v = DeferredVerification {
             static_point_weight: Scalar::one(),
-            dynamic_point_weights: vec![(
+            dynamic_point_weights: alloc::vec![(
                 -Scalar::one(),
                 (RISTRETTO_BASEPOINT_POINT * Scalar::from(4u8)).compress(),
             )],
@@ -112,7 +194,7 @@ mod tests {
 
         let v = DeferredVerification {
             static_point_weight: Scalar::one(),
-            dynamic_point_weights: vec![(Scalar::one(), RISTRETTO_BASEPOINT_COMPRESSED)],
+            dynamic_point_weights: alloc::vec![(Scalar::one(), RISTRETTO_BASEPOINT_COMPRESSED)],
         };
         assert!(v.verify().is_err());
     }
@@ -121,11 +203,74 @@ mod tests {
     fn no_generators() {
         let v = DeferredVerification {
             static_point_weight: Scalar::zero(),
-            dynamic_point_weights: vec![
+            dynamic_point_weights: alloc::vec![
                 (-Scalar::one(), RISTRETTO_BASEPOINT_COMPRESSED),
                 (Scalar::one(), RISTRETTO_BASEPOINT_COMPRESSED),
             ],
         };
         assert!(v.verify().is_ok());
     }
+
+    #[test]
+    fn into_parts() {
+        let privkey = Scalar::from(1u64);
+        let pubkey = VerificationKey::from_secret(&privkey);
+        let signature = Signature::sign_single_with_rng(
+            &mut Transcript::new(b"example transcript"),
+            privkey,
+            &mut StdRng::seed_from_u64(1),
+        );
+        let check = |v: DeferredVerification| {
+            let (weights, points) = v.into_parts().unwrap();
+            assert_eq!(weights.len(), points.len());
+            RistrettoPoint::vartime_multiscalar_mul(weights, points).is_identity()
+        };
+
+        let v = signature.verify(&mut Transcript::new(b"example transcript"), pubkey);
+        assert!(check(v.clone()));
+        assert!(v.verify().is_ok());
+
+        let v = signature.verify(&mut Transcript::new(b"other transcript"), pubkey);
+        assert!(!check(v.clone()));
+        assert!(v.verify().is_err());
+
+        let v = DeferredVerification {
+            static_point_weight: Scalar::one(),
+            dynamic_point_weights: alloc::vec![(Scalar::one(), CompressedRistretto([0xff; 32]))],
+        };
+        assert_eq!(v.clone().into_parts().err(), Some(MusigError::InvalidPoint));
+        assert_eq!(v.verify().err(), Some(MusigError::PointOperationFailed));
+    }
+
+    #[test]
+    fn queue() {
+        let valid = |n: u64| DeferredVerification {
+            static_point_weight: Scalar::from(n),
+            dynamic_point_weights: alloc::vec![(
+                -Scalar::one(),
+                (RISTRETTO_BASEPOINT_POINT * Scalar::from(n)).compress(),
+            )],
+        };
+        let invalid = DeferredVerification {
+            static_point_weight: Scalar::one(),
+            dynamic_point_weights: alloc::vec![(Scalar::one(), RISTRETTO_BASEPOINT_COMPRESSED)],
+        };
+
+        let mut queue = VerificationQueue::new();
+        assert!(queue.is_empty());
+        assert!(queue.verify_all().is_ok());
+
+        queue.extend((1..4).map(valid));
+        assert_eq!(queue.len(), 3);
+        assert!(queue.verify_all().is_ok());
+
+        queue.push(invalid.clone());
+        queue.push(valid(4));
+        assert!(invalid.verify().is_err());
+        assert!(queue.verify_all().is_err());
+
+        queue.clear();
+        queue.push(valid(5));
+        assert!(queue.verify_all().is_ok());
+    }
 }
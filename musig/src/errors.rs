@@ -1,22 +1,88 @@
+use alloc::vec::Vec;
+use core::fmt;
+
 /// Represents an error in key aggregation, signing, or verification.
-#[derive(Fail, Clone, Debug, Eq, PartialEq)]
+///
+/// Implements `std::error::Error` when the `std` feature is enabled,
+/// so it can be boxed as `dyn Error` or converted into other error types with `?`.
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum MusigError {
     /// This error occurs when a point is not a valid compressed Ristretto point
-    #[fail(display = "Point decoding failed")]
     InvalidPoint,
 
     /// This error occurs when a signature share fails to verify
-    #[fail(display = "Share #{:?} failed to verify correctly", pubkey)]
     ShareError {
         /// The pubkey corresponding to the share that failed fo verify correctly
         pubkey: [u8; 32],
     },
 
     /// This error occurs when an individual point operation failed.
-    #[fail(display = "Point operation failed")]
     PointOperationFailed,
 
+    /// This error occurs when a signing round is aborted or finalized
+    /// before all the parties responded.
+    IncompleteRound {
+        /// The indices of the parties that did not respond
+        missing: Vec<usize>,
+    },
+
+    /// This error occurs when a pubkey appears more than once in a `Multikey`.
+    DuplicateKey {
+        /// The pubkey that appears more than once
+        pubkey: [u8; 32],
+    },
+
     /// This error occurs when a function is called with bad arguments.
-    #[fail(display = "Bad arguments")]
     BadArguments,
 }
+
+impl fmt::Display for MusigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MusigError::InvalidPoint => write!(f, "Point decoding failed"),
+            MusigError::ShareError { pubkey } => {
+                write!(f, "Share of pubkey {:?} failed to verify correctly", pubkey)
+            }
+            MusigError::PointOperationFailed => write!(f, "Point operation failed"),
+            MusigError::IncompleteRound { missing } => {
+                write!(f, "Round is incomplete, missing parties {:?}", missing)
+            }
+            MusigError::DuplicateKey { pubkey } => write!(f, "Duplicate pubkey {:?}", pubkey),
+            MusigError::BadArguments => write!(f, "Bad arguments"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MusigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn boxed_error() {
+        let result: Result<(), Box<dyn Error>> = Err(MusigError::IncompleteRound {
+            missing: alloc::vec![3],
+        }
+        .into());
+        let err = result.unwrap_err();
+        assert_eq!(err.to_string(), "Round is incomplete, missing parties [3]");
+
+        let errors = [
+            MusigError::InvalidPoint,
+            MusigError::ShareError { pubkey: [0u8; 32] },
+            MusigError::PointOperationFailed,
+            MusigError::IncompleteRound {
+                missing: alloc::vec![1, 2],
+            },
+            MusigError::DuplicateKey { pubkey: [1u8; 32] },
+            MusigError::BadArguments,
+        ];
+        for err in errors.iter() {
+            let boxed: Box<dyn Error> = Box::new(err.clone());
+            assert!(!boxed.to_string().is_empty());
+        }
+    }
+}
@@ -1,6 +1,9 @@
+use super::errors::MusigError;
+use core::ops::Add;
 use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
 use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::IsIdentity;
 use serde::{Deserialize, Serialize};
 
 /// Verification key (aka "pubkey") is a wrapper type around a Ristretto point
@@ -26,6 +29,23 @@ impl VerificationKey {
         })
     }
 
+    /// Decodes a key from its 32-byte compressed form.
+    /// Fails with `MusigError::InvalidPoint` if the encoding is not canonical,
+    /// or if the key is the identity point, which is insecure to aggregate or sign with.
+    pub fn from_bytes(bytes: [u8; 32]) -> Result<Self, MusigError> {
+        let key =
+            Self::from_compressed(CompressedRistretto(bytes)).ok_or(MusigError::InvalidPoint)?;
+        if !key.is_valid() {
+            return Err(MusigError::InvalidPoint);
+        }
+        Ok(key)
+    }
+
+    /// Returns false if the key is the identity point (a weak key).
+    pub fn is_valid(&self) -> bool {
+        !self.point.is_identity()
+    }
+
     /// Converts the Verification key to a compressed point
     pub fn into_compressed(self) -> CompressedRistretto {
         self.precompressed
@@ -55,6 +75,25 @@ impl VerificationKey {
     pub fn to_bytes(&self) -> [u8; 32] {
         self.as_compressed().to_bytes()
     }
+
+    /// Returns the sum of the keys, which is the key for the sum of their private keys.
+    /// The sum of no keys is the identity point, which is not a valid key (see `is_valid`).
+    ///
+    /// Note: unlike `Multikey`, a plain sum is vulnerable to rogue-key attacks,
+    /// so it should only be used with keys known to be independently generated.
+    pub fn sum(keys: &[VerificationKey]) -> VerificationKey {
+        keys.iter()
+            .fold(RistrettoPoint::default(), |sum, key| sum + key.point)
+            .into()
+    }
+}
+
+impl Add for VerificationKey {
+    type Output = VerificationKey;
+
+    fn add(self, rhs: VerificationKey) -> VerificationKey {
+        (self.point + rhs.point).into()
+    }
 }
 
 impl From<RistrettoPoint> for VerificationKey {
@@ -71,3 +110,53 @@ impl Into<RistrettoPoint> for VerificationKey {
         self.into_point()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_sum() {
+        let (a, b, c) = (Scalar::from(3u64), Scalar::from(5u64), Scalar::from(11u64));
+        let (A, B, C) = (
+            VerificationKey::from_secret(&a),
+            VerificationKey::from_secret(&b),
+            VerificationKey::from_secret(&c),
+        );
+
+        let sum = A + B;
+        assert_eq!(sum.into_point(), A.into_point() + B.into_point());
+        assert_eq!(sum, VerificationKey::from_secret(&(a + b)));
+        assert_eq!(
+            VerificationKey::from_compressed(sum.into_compressed()),
+            Some(sum)
+        );
+
+        assert_eq!(VerificationKey::sum(&[A, B, C]), A + B + C);
+        assert_eq!(VerificationKey::sum(&[A]), A);
+        assert!(!VerificationKey::sum(&[]).is_valid());
+    }
+
+    #[test]
+    fn key_from_bytes() {
+        let key = VerificationKey::from_secret(&Scalar::from(7u64));
+        assert_eq!(VerificationKey::from_bytes(key.to_bytes()), Ok(key));
+        assert!(key.is_valid());
+
+        // Identity point
+        assert_eq!(
+            VerificationKey::from_bytes([0u8; 32]),
+            Err(MusigError::InvalidPoint)
+        );
+        assert!(!VerificationKey::from_secret(&Scalar::zero()).is_valid());
+
+        // Non-canonical encoding: the field element p = 2^255 - 19 is not reduced.
+        let mut p = [0xffu8; 32];
+        p[0] = 0xed;
+        p[31] = 0x7f;
+        assert_eq!(
+            VerificationKey::from_bytes(p),
+            Err(MusigError::InvalidPoint)
+        );
+    }
+}
@@ -1,9 +1,13 @@
 #![deny(missing_docs)]
 #![allow(non_snake_case)]
+#![cfg_attr(not(feature = "std"), no_std)]
 //! Musig implementation
+//!
+//! The crate supports `no_std` environments with `alloc`:
+//! disable the default `std` feature and provide an RNG explicitly
+//! via the `*_with_rng` signing and batch verification methods.
 
-#[macro_use]
-extern crate failure;
+extern crate alloc;
 
 mod context;
 mod counterparty;
@@ -15,11 +19,12 @@ mod signer;
 mod errors;
 mod transcript;
 
-pub use self::context::{Multikey, Multimessage, MusigContext};
-pub use self::deferred_verification::DeferredVerification;
+pub use self::context::{Multikey, Multimessage, MultimessageBuilder, MusigContext};
+pub use self::deferred_verification::{DeferredVerification, VerificationQueue};
 pub use self::errors::MusigError;
 pub use self::key::VerificationKey;
 pub use self::signature::Signature;
 pub use self::signer::{
-    Signer, SignerAwaitingCommitments, SignerAwaitingPrecommitments, SignerAwaitingShares,
+    AggregatingSignature, Signer, SignerAwaitingCommitments, SignerAwaitingPrecommitments,
+    SignerAwaitingShares,
 };
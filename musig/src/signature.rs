@@ -3,11 +3,13 @@ use super::deferred_verification::DeferredVerification;
 use super::errors::MusigError;
 use super::key::VerificationKey;
 use super::transcript::TranscriptProtocol;
+use alloc::vec::Vec;
 use core::borrow::Borrow;
 use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
 use curve25519_dalek::ristretto::CompressedRistretto;
 use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
+use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 
 /// A Schnorr signature.
@@ -21,13 +23,24 @@ pub struct Signature {
 
 impl Signature {
     /// Creates a signature for a single private key and single message
+    #[cfg(feature = "std")]
     pub fn sign_single(transcript: &mut Transcript, privkey: Scalar) -> Signature {
+        Signature::sign_single_with_rng(transcript, privkey, &mut rand::thread_rng())
+    }
+
+    /// Creates a signature for a single private key and single message,
+    /// using the provided `rng` as a source of entropy for the nonce.
+    pub fn sign_single_with_rng<R: RngCore + CryptoRng>(
+        transcript: &mut Transcript,
+        privkey: Scalar,
+        rng: &mut R,
+    ) -> Signature {
         let X = VerificationKey::from_secret(&privkey); // pubkey
 
         let mut rng = transcript
             .build_rng()
             .rekey_with_witness_bytes(b"x", &privkey.to_bytes())
-            .finalize(&mut rand::thread_rng());
+            .finalize(rng);
 
         // Generate ephemeral keypair (r, R). r is a random nonce.
         let r = Scalar::random(&mut rng);
@@ -47,6 +60,7 @@ impl Signature {
     }
 
     /// Creates a signature for multiple private keys and multiple messages
+    #[cfg(feature = "std")]
     pub fn sign_multi<P, M>(
         privkeys: P,
         messages: Vec<(VerificationKey, M)>,
@@ -57,6 +71,24 @@ impl Signature {
         P: IntoIterator,
         P::Item: Borrow<Scalar>,
         P::IntoIter: ExactSizeIterator,
+    {
+        Signature::sign_multi_with_rng(privkeys, messages, transcript, &mut rand::thread_rng())
+    }
+
+    /// Creates a signature for multiple private keys and multiple messages,
+    /// using the provided `rng` as a source of entropy for the nonce.
+    pub fn sign_multi_with_rng<P, M, R>(
+        privkeys: P,
+        messages: Vec<(VerificationKey, M)>,
+        transcript: &mut Transcript,
+        rng: &mut R,
+    ) -> Result<Signature, MusigError>
+    where
+        R: RngCore + CryptoRng,
+        M: AsRef<[u8]>,
+        P: IntoIterator,
+        P::Item: Borrow<Scalar>,
+        P::IntoIter: ExactSizeIterator,
     {
         let mut privkeys = privkeys.into_iter().peekable();
 
@@ -74,7 +106,7 @@ impl Signature {
             // Use one key that has enough entropy to seed the RNG.
             // We can call unwrap because we know that the privkeys length is > 0.
             .rekey_with_witness_bytes(b"x_i", privkeys.peek().unwrap().borrow().as_bytes())
-            .finalize(&mut rand::thread_rng());
+            .finalize(rng);
 
         // Generate ephemeral keypair (r, R). r is a random nonce.
         let r = Scalar::random(&mut rng);
@@ -148,14 +180,51 @@ impl Signature {
         result
     }
 
+    /// Checks that the signature's nonce `R` commits to the `contract` data
+    /// (see `SignerAwaitingCommitments::receive_commitments_with_contract`):
+    /// `R == R' + H(R', contract)·G`, where `R'` is the original aggregated nonce.
+    /// This does not verify the signature itself.
+    pub fn verify_contract(&self, original_nonce: &CompressedRistretto, contract: &[u8]) -> bool {
+        let original_point = match original_nonce.decompress() {
+            Some(p) => p,
+            None => return false,
+        };
+        let t = Signature::contract_tweak(original_nonce, contract);
+        (original_point + t * RISTRETTO_BASEPOINT_POINT).compress() == self.R
+    }
+
+    /// Computes the sign-to-contract tweak `t = H(R', contract)`.
+    pub(crate) fn contract_tweak(original_nonce: &CompressedRistretto, contract: &[u8]) -> Scalar {
+        let mut transcript = Transcript::new(b"Musig.sign-to-contract");
+        transcript.commit_point(b"R", original_nonce);
+        transcript.append_message(b"contract", contract);
+        transcript.challenge_scalar(b"t")
+    }
+
+    /// Returns true if the signature is in its canonical form:
+    /// the scalar `s` is reduced modulo the group order and `R` is a valid Ristretto encoding.
+    /// A non-canonical `s` verifies the same as its reduced form,
+    /// so only canonical signatures should be accepted to prevent malleability.
+    /// Signatures created by this crate and decoded with `from_bytes` are always canonical.
+    pub fn is_canonical(&self) -> bool {
+        Scalar::from_canonical_bytes(self.s.to_bytes()).is_some() && self.R.decompress().is_some()
+    }
+
     /// Decodes a signature from 64-byte array.
+    /// Fails with `MusigError::BadArguments` if the scalar `s` is not canonical
+    /// and with `MusigError::InvalidPoint` if `R` is not a valid point,
+    /// so that a signature has exactly one accepted encoding.
     pub fn from_bytes(sig: [u8; 64]) -> Result<Self, MusigError> {
         let mut Rbuf = [0u8; 32];
         let mut sbuf = [0u8; 32];
         Rbuf[..].copy_from_slice(&sig[..32]);
         sbuf[..].copy_from_slice(&sig[32..]);
+        let R = CompressedRistretto(Rbuf);
+        if R.decompress().is_none() {
+            return Err(MusigError::InvalidPoint);
+        }
         Ok(Signature {
-            R: CompressedRistretto(Rbuf),
+            R,
             s: Scalar::from_canonical_bytes(sbuf).ok_or(MusigError::BadArguments)?,
         })
     }
@@ -177,6 +246,8 @@ mod tests {
     use crate::key::VerificationKey;
     use crate::signer::Signer;
     use curve25519_dalek::ristretto::CompressedRistretto;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
     #[test]
     fn sign_verify_single_pubkey() {
@@ -202,6 +273,47 @@ mod tests {
             .is_err());
     }
 
+    #[test]
+    fn canonical_signature() {
+        let privkey = Scalar::from(1u64);
+        let sig = Signature::sign_single(&mut Transcript::new(b"example transcript"), privkey);
+        assert!(sig.is_canonical());
+
+        let bytes = sig.to_bytes();
+        let decoded = Signature::from_bytes(bytes).unwrap();
+        assert!(decoded.is_canonical());
+        assert_eq!(decoded.to_bytes(), bytes);
+
+        // Malleated s' = s + l verifies the same, but is not canonical.
+        let order = curve25519_dalek::constants::BASEPOINT_ORDER.to_bytes();
+        let mut s_bytes = [0u8; 32];
+        let mut carry = 0u16;
+        for i in 0..32 {
+            let sum = bytes[32 + i] as u16 + order[i] as u16 + carry;
+            s_bytes[i] = sum as u8;
+            carry = sum >> 8;
+        }
+        let mut malleated = bytes;
+        malleated[32..].copy_from_slice(&s_bytes);
+        assert_eq!(
+            Signature::from_bytes(malleated).err(),
+            Some(MusigError::BadArguments)
+        );
+        let malleated = Signature {
+            s: Scalar::from_bits(s_bytes),
+            R: sig.R,
+        };
+        assert!(!malleated.is_canonical());
+
+        // R is not a valid point encoding.
+        let mut bad_point = bytes;
+        bad_point[..32].copy_from_slice(&[0xff; 32]);
+        assert_eq!(
+            Signature::from_bytes(bad_point).err(),
+            Some(MusigError::InvalidPoint)
+        );
+    }
+
     #[test]
     fn sign_verify_single_multikey() {
         let privkey = Scalar::from(1u64);
@@ -235,13 +347,114 @@ mod tests {
         let multikey = multikey_helper(&priv_keys);
 
         let expected_pubkey = CompressedRistretto::from_slice(&[
-            224, 55, 123, 145, 179, 165, 49, 222, 32, 55, 98, 22, 171, 85, 86, 8, 136, 50, 15, 199,
-            239, 6, 119, 17, 228, 9, 231, 89, 28, 228, 113, 87,
+            24, 101, 109, 154, 40, 186, 236, 133, 217, 234, 220, 120, 148, 243, 129, 149, 90, 69,
+            61, 124, 2, 212, 145, 98, 81, 209, 167, 143, 52, 211, 1, 48,
         ]);
 
         assert_eq!(expected_pubkey, multikey.aggregated_key().into_compressed());
     }
 
+    #[test]
+    fn multikey_factors() {
+        let priv_keys = vec![Scalar::from(1u64), Scalar::from(2u64), Scalar::from(3u64)];
+        let multikey = multikey_helper(&priv_keys);
+        let priv_keys = ordered_privkeys(&priv_keys, &multikey);
+
+        // Aggregated secret key sum_i(a_i * x_i) matches the aggregated key.
+        let aggregated_secret: Scalar = priv_keys
+            .iter()
+            .enumerate()
+            .map(|(i, x_i)| multikey.factor(i) * x_i)
+            .sum();
+        assert_eq!(
+            VerificationKey::from_secret(&aggregated_secret),
+            multikey.aggregated_key()
+        );
+
+        // Single key is not delinearized.
+        let single = multikey_helper(&vec![Scalar::from(1u64)]);
+        assert_eq!(single.factor(0), Scalar::one());
+    }
+
+    #[test]
+    fn multikey_reuses_cached_factors() {
+        let priv_keys = vec![Scalar::from(1u64), Scalar::from(2u64), Scalar::from(3u64)];
+        let multikey = multikey_helper(&priv_keys);
+        let pubkeys: Vec<_> = (0..multikey.len()).map(|i| multikey.key(i)).collect();
+        let expected = Multikey::compute_factors(&pubkeys);
+
+        for i in 0..100u64 {
+            let mut transcript = Transcript::new(b"example transcript");
+            transcript.append_u64(b"message", i);
+            let (sig, _) = sign_with_mpc(&priv_keys, multikey.clone(), transcript.clone()).unwrap();
+            assert!(sig
+                .verify(&mut transcript, multikey.aggregated_key())
+                .verify()
+                .is_ok());
+
+            // Cached factors are identical to the ones computed on demand.
+            assert_eq!(multikey.factors(), &expected[..]);
+            for (j, a_j) in expected.iter().enumerate() {
+                assert_eq!(multikey.factor(j), *a_j);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "debug-transcript")]
+    fn transcript_values_are_deterministic() {
+        let priv_keys = vec![Scalar::from(1u64), Scalar::from(2u64), Scalar::from(3u64)];
+        let multikey1 = multikey_helper(&priv_keys);
+        let multikey2 = multikey_helper(&priv_keys);
+        assert_eq!(multikey1.factors(), multikey2.factors());
+        assert_eq!(multikey1.factors().len(), priv_keys.len());
+
+        // The challenge satisfies the Schnorr equation `s * G = R + c * X`.
+        let sig = sign_with_seeded_rngs(&priv_keys, 0);
+        let transcript = Transcript::new(b"example transcript");
+        let c1 = multikey1.signing_challenge(&transcript, &sig.R);
+        let c2 = multikey2.signing_challenge(&transcript, &sig.R);
+        assert_eq!(c1, c2);
+        assert_eq!(
+            sig.s * RISTRETTO_BASEPOINT_POINT,
+            sig.R.decompress().unwrap() + c1 * multikey1.aggregated_key().into_point()
+        );
+    }
+
+    #[test]
+    fn multikey_sorts_keys() {
+        let priv_keys = vec![Scalar::from(1u64), Scalar::from(2u64), Scalar::from(3u64)];
+        let multikey = multikey_helper(&priv_keys);
+
+        // Any order of the same keys yields the same aggregated key and positions.
+        let reversed = priv_keys.iter().rev().cloned().collect();
+        let multikey2 = multikey_helper(&reversed);
+        assert_eq!(multikey.aggregated_key(), multikey2.aggregated_key());
+        for x in &priv_keys {
+            let key = VerificationKey::from_secret(x);
+            assert_eq!(multikey.position(&key), multikey2.position(&key));
+        }
+        assert_eq!(
+            multikey.position(&VerificationKey::from_secret(&Scalar::from(4u64))),
+            None
+        );
+
+        // Duplicate keys are rejected.
+        let duplicate = VerificationKey::from_secret(&priv_keys[1]);
+        assert_eq!(
+            Multikey::new(vec![
+                VerificationKey::from_secret(&priv_keys[0]),
+                duplicate,
+                VerificationKey::from_secret(&priv_keys[2]),
+                duplicate,
+            ])
+            .unwrap_err(),
+            MusigError::DuplicateKey {
+                pubkey: duplicate.to_bytes()
+            }
+        );
+    }
+
     fn multikey_helper(priv_keys: &Vec<Scalar>) -> Multikey {
         Multikey::new(
             priv_keys
@@ -268,11 +481,24 @@ mod tests {
         );
     }
 
+    /// Orders the secret keys by the positions of their pubkeys in the context.
+    fn ordered_privkeys<C: MusigContext>(privkeys: &Vec<Scalar>, context: &C) -> Vec<Scalar> {
+        (0..context.len())
+            .map(|i| {
+                *privkeys
+                    .iter()
+                    .find(|x| VerificationKey::from_secret(x) == context.key(i))
+                    .unwrap()
+            })
+            .collect()
+    }
+
     fn sign_with_mpc<C: MusigContext + Clone>(
         privkeys: &Vec<Scalar>,
         context: C,
         transcript: Transcript,
     ) -> Result<(Signature, Scalar), MusigError> {
+        let privkeys = &ordered_privkeys(privkeys, &context);
         let pubkeys: Vec<_> = privkeys
             .iter()
             .map(|privkey| VerificationKey::from_secret(privkey))
@@ -320,6 +546,242 @@ mod tests {
         Ok((signatures[0].clone(), cmp_challenge))
     }
 
+    fn sign_with_seeded_rngs(privkeys: &Vec<Scalar>, seed: u8) -> Signature {
+        let multikey = multikey_helper(privkeys);
+        let privkeys = &ordered_privkeys(privkeys, &multikey);
+        let transcript = Transcript::new(b"example transcript");
+        let mut transcripts: Vec<_> = privkeys.iter().map(|_| transcript.clone()).collect();
+
+        let (parties, precomms): (Vec<_>, Vec<_>) = privkeys
+            .clone()
+            .into_iter()
+            .zip(transcripts.iter_mut())
+            .enumerate()
+            .map(|(i, (x_i, transcript))| {
+                let mut rng = StdRng::from_seed([seed + i as u8; 32]);
+                Signer::new_with_rng(transcript, i, x_i, multikey.clone(), &mut rng)
+            })
+            .unzip();
+
+        let (parties, comms): (Vec<_>, Vec<_>) = parties
+            .into_iter()
+            .map(|p| p.receive_precommitments(precomms.clone()))
+            .unzip();
+
+        let (parties, shares): (Vec<_>, Vec<_>) = parties
+            .into_iter()
+            .map(|p| p.receive_commitments(comms.clone()).unwrap())
+            .unzip();
+
+        let party = parties.into_iter().next().unwrap();
+        party.receive_shares(shares).unwrap()
+    }
+
+    #[test]
+    fn sign_with_seeded_rng() {
+        let privkeys = vec![Scalar::from(1u64), Scalar::from(2u64), Scalar::from(3u64)];
+
+        // Same seed yields the same signature
+        let sig1 = sign_with_seeded_rngs(&privkeys, 0);
+        let sig2 = sign_with_seeded_rngs(&privkeys, 0);
+        assert_eq!(sig1.s, sig2.s);
+        assert_eq!(sig1.R, sig2.R);
+
+        // Different seed yields a different signature
+        let sig3 = sign_with_seeded_rngs(&privkeys, 10);
+        assert_ne!(sig1.R, sig3.R);
+
+        // Both are valid signatures
+        for sig in &[sig1, sig3] {
+            assert!(sig
+                .verify(
+                    &mut Transcript::new(b"example transcript"),
+                    multikey_helper(&privkeys).aggregated_key()
+                )
+                .verify()
+                .is_ok());
+        }
+    }
+
+    #[test]
+    fn aggregate_shares_out_of_order() {
+        let privkeys = vec![Scalar::from(1u64), Scalar::from(2u64), Scalar::from(3u64)];
+        let multikey = multikey_helper(&privkeys);
+        let privkeys = ordered_privkeys(&privkeys, &multikey);
+
+        let transcript = Transcript::new(b"example transcript");
+        let mut transcripts: Vec<_> = privkeys.iter().map(|_| transcript.clone()).collect();
+
+        let (parties, precomms): (Vec<_>, Vec<_>) = privkeys
+            .clone()
+            .into_iter()
+            .zip(transcripts.iter_mut())
+            .enumerate()
+            .map(|(i, (x_i, transcript))| Signer::new(transcript, i, x_i, multikey.clone()))
+            .unzip();
+
+        let (parties, comms): (Vec<_>, Vec<_>) = parties
+            .into_iter()
+            .map(|p| p.receive_precommitments(precomms.clone()))
+            .unzip();
+
+        let (parties, shares): (Vec<_>, Vec<_>) = parties
+            .into_iter()
+            .map(|p| p.receive_commitments(comms.clone()).unwrap())
+            .unzip();
+
+        let mut parties = parties.into_iter();
+        let expected = parties
+            .next()
+            .unwrap()
+            .receive_shares(shares.clone())
+            .unwrap();
+
+        // Missing shares
+        let mut aggregator = parties.next().unwrap().into_aggregating_signature();
+        aggregator.add_share(2, shares[2]).unwrap();
+        assert!(!aggregator.is_complete());
+        assert_eq!(
+            aggregator.finalize().unwrap_err(),
+            MusigError::IncompleteRound {
+                missing: vec![0, 1]
+            }
+        );
+
+        // Invalid and out-of-range shares are rejected
+        let mut aggregator = parties.next().unwrap().into_aggregating_signature();
+        assert!(aggregator.add_share(0, shares[1]).is_err());
+        assert_eq!(
+            aggregator.add_share(3, shares[0]),
+            Err(MusigError::BadArguments)
+        );
+
+        // Shares added out of order
+        aggregator.add_share(2, shares[2]).unwrap();
+        aggregator.add_share(0, shares[0]).unwrap();
+        aggregator.add_share(1, shares[1]).unwrap();
+        assert!(aggregator.is_complete());
+        let sig = aggregator.finalize().unwrap();
+
+        assert_eq!(sig.s, expected.s);
+        assert_eq!(sig.R, expected.R);
+        assert!(sig
+            .verify(
+                &mut Transcript::new(b"example transcript"),
+                multikey.aggregated_key()
+            )
+            .verify()
+            .is_ok());
+    }
+
+    #[test]
+    fn abort_incomplete_round() {
+        let privkeys = vec![Scalar::from(1u64), Scalar::from(2u64), Scalar::from(3u64)];
+        let multikey = multikey_helper(&privkeys);
+        let privkeys = ordered_privkeys(&privkeys, &multikey);
+
+        let transcript = Transcript::new(b"example transcript");
+        let mut transcripts: Vec<_> = privkeys.iter().map(|_| transcript.clone()).collect();
+
+        let (parties, precomms): (Vec<_>, Vec<_>) = privkeys
+            .clone()
+            .into_iter()
+            .zip(transcripts.iter_mut())
+            .enumerate()
+            .map(|(i, (x_i, transcript))| Signer::new(transcript, i, x_i, multikey.clone()))
+            .unzip();
+
+        let (parties, comms): (Vec<_>, Vec<_>) = parties
+            .into_iter()
+            .map(|p| p.receive_precommitments(precomms.clone()))
+            .unzip();
+
+        let (parties, shares): (Vec<_>, Vec<_>) = parties
+            .into_iter()
+            .map(|p| p.receive_commitments(comms.clone()).unwrap())
+            .unzip();
+
+        // The party #2 disconnects before sending its share.
+        let mut aggregators = parties.into_iter().map(|p| p.into_aggregating_signature());
+        let mut aggregator = aggregators.next().unwrap();
+        assert_eq!(aggregator.received_indices(), Vec::<usize>::new());
+        aggregator.add_share(1, shares[1]).unwrap();
+        aggregator.add_share(0, shares[0]).unwrap();
+        assert_eq!(aggregator.received_indices(), vec![0, 1]);
+        assert_eq!(
+            aggregator.timeout_abort(),
+            MusigError::IncompleteRound { missing: vec![2] }
+        );
+
+        // Finalizing reports all the missing shares.
+        let mut aggregator = aggregators.next().unwrap();
+        aggregator.add_share(1, shares[1]).unwrap();
+        assert_eq!(
+            aggregator.finalize().unwrap_err(),
+            MusigError::IncompleteRound {
+                missing: vec![0, 2]
+            }
+        );
+    }
+
+    #[test]
+    fn sign_to_contract() {
+        let privkeys = vec![Scalar::from(1u64), Scalar::from(2u64), Scalar::from(3u64)];
+        let multikey = multikey_helper(&privkeys);
+        let privkeys = ordered_privkeys(&privkeys, &multikey);
+        let contract = b"swap: 10 units of A for 20 units of B";
+
+        let transcript = Transcript::new(b"example transcript");
+        let mut transcripts: Vec<_> = privkeys.iter().map(|_| transcript.clone()).collect();
+
+        let (parties, precomms): (Vec<_>, Vec<_>) = privkeys
+            .clone()
+            .into_iter()
+            .zip(transcripts.iter_mut())
+            .enumerate()
+            .map(|(i, (x_i, transcript))| Signer::new(transcript, i, x_i, multikey.clone()))
+            .unzip();
+
+        let (parties, comms): (Vec<_>, Vec<_>) = parties
+            .into_iter()
+            .map(|p| p.receive_precommitments(precomms.clone()))
+            .unzip();
+
+        let (parties, shares): (Vec<_>, Vec<_>) = parties
+            .into_iter()
+            .map(|p| {
+                p.receive_commitments_with_contract(comms.clone(), Some(&contract[..]))
+                    .unwrap()
+            })
+            .unzip();
+
+        let original_nonce = parties[0].original_nonce();
+        let signatures: Vec<Signature> = parties
+            .into_iter()
+            .map(|p| p.receive_shares(shares.clone()).unwrap())
+            .collect();
+        let sig = &signatures[0];
+        for s in &signatures {
+            assert_eq!(sig.s, s.s);
+            assert_eq!(sig.R, s.R);
+        }
+
+        // The signature is a valid standard signature...
+        assert!(sig
+            .verify(
+                &mut Transcript::new(b"example transcript"),
+                multikey.aggregated_key()
+            )
+            .verify()
+            .is_ok());
+
+        // ...and its nonce commits to the contract.
+        assert_ne!(sig.R, original_nonce);
+        assert!(sig.verify_contract(&original_nonce, &contract[..]));
+        assert!(!sig.verify_contract(&original_nonce, b"another contract"));
+        assert!(!sig.verify_contract(&sig.R, &contract[..]));
+    }
+
     #[test]
     fn verify_multikey() {
         // super secret, sshhh!
@@ -436,6 +898,55 @@ mod tests {
             .is_ok());
     }
 
+    #[test]
+    fn build_multimessage() {
+        let priv_keys = vec![Scalar::from(1u64), Scalar::from(2u64), Scalar::from(3u64)];
+        let pub_keys: Vec<_> = priv_keys.iter().map(VerificationKey::from_secret).collect();
+        let messages = vec![
+            b"message1".to_vec(),
+            b"message2".to_vec(),
+            b"message3".to_vec(),
+        ];
+
+        let multimessage = Multimessage::builder()
+            .add(pub_keys[0], messages[0].clone())
+            .add(pub_keys[1], messages[1].clone())
+            .add(pub_keys[2], messages[2].clone())
+            .build()
+            .unwrap();
+
+        let (signature, _) = sign_with_mpc(
+            &priv_keys,
+            multimessage,
+            Transcript::new(b"example transcript"),
+        )
+        .unwrap();
+
+        assert!(signature
+            .verify_multi(
+                &mut Transcript::new(b"example transcript"),
+                multimessage_helper(&priv_keys, messages)
+            )
+            .verify()
+            .is_ok());
+
+        assert_eq!(
+            Multimessage::builder()
+                .add(pub_keys[0], b"message1".to_vec())
+                .add(pub_keys[1], b"message2".to_vec())
+                .add(pub_keys[0], b"message3".to_vec())
+                .build()
+                .err(),
+            Some(MusigError::DuplicateKey {
+                pubkey: pub_keys[0].to_bytes()
+            })
+        );
+        assert_eq!(
+            Multimessage::<Vec<u8>>::builder().build().err(),
+            Some(MusigError::BadArguments)
+        );
+    }
+
     #[test]
     fn verify_multimessage_singleplayer() {
         // super secret, sshhh!
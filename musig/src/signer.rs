@@ -3,11 +3,12 @@ use super::counterparty::*;
 use super::errors::MusigError;
 use super::signature::Signature;
 use super::transcript::TranscriptProtocol;
+use alloc::vec::Vec;
 use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
-use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
-use rand;
+use rand::{CryptoRng, RngCore};
 
 /// Entry point to multi-party signing protocol.
 pub struct Signer {}
@@ -33,27 +34,58 @@ pub struct SignerAwaitingCommitments<'t, C: MusigContext> {
     counterparties: Vec<CounterpartyPrecommitted>,
 }
 
+/// Accumulator of signature shares that verifies and folds them in one at a time,
+/// for when the parties' shares arrive at different times.
+/// Created with `SignerAwaitingShares::into_aggregating_signature`.
+pub struct AggregatingSignature<C: MusigContext> {
+    transcript: Transcript,
+    context: C,
+    R: RistrettoPoint,
+    tweak: Scalar,
+    counterparties: Vec<CounterpartyCommitted>,
+    shares: Vec<Option<Scalar>>,
+}
+
 /// State of the party when awaiting signature shares from other parties.
 pub struct SignerAwaitingShares<C: MusigContext> {
     transcript: Transcript,
     context: C,
     R: RistrettoPoint,
+    // Sign-to-contract tweak `t` such that `R = R' + t·G`, zero if no contract is committed.
+    tweak: Scalar,
     counterparties: Vec<CounterpartyCommitted>,
 }
 
 impl Signer {
     /// Create new signing party for a given transcript.
+    /// Uses `rand::thread_rng` as a source of entropy for the nonce.
+    #[cfg(feature = "std")]
     pub fn new<'t, C: MusigContext>(
         // The message `m` has already been fed into the transcript
         transcript: &'t mut Transcript,
         position: usize,
         x_i: Scalar,
         context: C,
+    ) -> (SignerAwaitingPrecommitments<'t, C>, NoncePrecommitment) {
+        Signer::new_with_rng(transcript, position, x_i, context, &mut rand::thread_rng())
+    }
+
+    /// Create new signing party for a given transcript,
+    /// using the provided `rng` as a source of entropy for the nonce.
+    /// The nonce is also bound to the transcript and the secret key,
+    /// so a seeded `rng` yields reproducible signatures.
+    pub fn new_with_rng<'t, C: MusigContext, R: RngCore + CryptoRng>(
+        // The message `m` has already been fed into the transcript
+        transcript: &'t mut Transcript,
+        position: usize,
+        x_i: Scalar,
+        context: C,
+        rng: &mut R,
     ) -> (SignerAwaitingPrecommitments<'t, C>, NoncePrecommitment) {
         let mut rng = transcript
             .build_rng()
             .rekey_with_witness_bytes(b"x_i", &x_i.to_bytes())
-            .finalize(&mut rand::thread_rng());
+            .finalize(rng);
 
         // Generate ephemeral keypair (r_i, R_i). r_i is a random nonce.
         let r_i = Scalar::random(&mut rng);
@@ -112,12 +144,33 @@ impl<'t, C: MusigContext> SignerAwaitingCommitments<'t, C> {
     /// Provide nonce commitments to the party and transition to the next round
     /// if they match the precommitments.
     pub fn receive_commitments(
+        self,
+        nonce_commitments: Vec<NonceCommitment>,
+    ) -> Result<(SignerAwaitingShares<C>, Scalar), MusigError> {
+        self.receive_commitments_with_contract(nonce_commitments, None)
+    }
+
+    /// Provide nonce commitments to the party and transition to the next round,
+    /// making the resulting signature's nonce commit to the `contract` data
+    /// supplied by the coordinator (sign-to-contract).
+    /// All parties must receive the same `contract`.
+    /// The resulting signature can be checked against the contract with `Signature::verify_contract`,
+    /// using the original aggregated nonce returned by `SignerAwaitingShares::original_nonce`.
+    pub fn receive_commitments_with_contract(
         mut self,
         nonce_commitments: Vec<NonceCommitment>,
+        contract: Option<&[u8]>,
     ) -> Result<(SignerAwaitingShares<C>, Scalar), MusigError> {
-        // Make R = sum_i(R_i). nonce_commitments = R_i from all the parties.
+        // Make R' = sum_i(R_i). nonce_commitments = R_i from all the parties.
         let R = NonceCommitment::sum(&nonce_commitments);
 
+        // Make R = R' + t·G, where t = H(R', contract).
+        let tweak = match contract {
+            Some(contract) => Signature::contract_tweak(&R.compress(), contract),
+            None => Scalar::zero(),
+        };
+        let R = R + tweak * RISTRETTO_BASEPOINT_POINT;
+
         // Check stored precommitments against received commitments
         let counterparties = self
             .counterparties
@@ -147,6 +200,7 @@ impl<'t, C: MusigContext> SignerAwaitingCommitments<'t, C> {
                 transcript,
                 context: self.context,
                 R,
+                tweak,
                 counterparties,
             },
             s_i,
@@ -155,10 +209,30 @@ impl<'t, C: MusigContext> SignerAwaitingCommitments<'t, C> {
 }
 
 impl<'t, C: MusigContext> SignerAwaitingShares<C> {
+    /// Returns the aggregated nonce `R'` before the sign-to-contract tweak is applied.
+    /// Equals the signature's nonce `R` if no contract was committed.
+    pub fn original_nonce(&self) -> CompressedRistretto {
+        (self.R - self.tweak * RISTRETTO_BASEPOINT_POINT).compress()
+    }
+
+    /// Converts the signer into an accumulator that receives the shares
+    /// of the parties in the `MusigContext` one at a time, in any order.
+    pub fn into_aggregating_signature(self) -> AggregatingSignature<C> {
+        let shares = self.counterparties.iter().map(|_| None).collect();
+        AggregatingSignature {
+            transcript: self.transcript,
+            context: self.context,
+            R: self.R,
+            tweak: self.tweak,
+            counterparties: self.counterparties,
+            shares,
+        }
+    }
+
     /// Assemble trusted signature shares (e.g. when all keys owned by one signer)
     pub fn receive_trusted_shares(self, shares: Vec<Scalar>) -> Signature {
-        // s = sum(s_i), s_i = shares[i]
-        let s: Scalar = shares.into_iter().map(|share| share).sum();
+        // s = sum(s_i) + t, s_i = shares[i]
+        let s: Scalar = shares.into_iter().map(|share| share).sum::<Scalar>() + self.tweak;
         Signature {
             s,
             R: self.R.compress(),
@@ -180,10 +254,75 @@ impl<'t, C: MusigContext> SignerAwaitingShares<C> {
             .into_iter()
             .zip(shares)
             .map(|(counterparty, share)| counterparty.verify_share(share, context, &transcript))
-            .sum::<Result<_, _>>()?;
+            .sum::<Result<Scalar, _>>()?;
 
         Ok(Signature {
-            s,
+            s: s + self.tweak,
+            R: self.R.compress(),
+        })
+    }
+}
+
+impl<C: MusigContext> AggregatingSignature<C> {
+    /// Verifies the share of the party at `index` in the context and adds it to the signature.
+    /// Fails if the index is out of range or the share is invalid.
+    /// Adding a share for the same index again replaces the previous one.
+    pub fn add_share(&mut self, index: usize, share: Scalar) -> Result<(), MusigError> {
+        let counterparty = self
+            .counterparties
+            .get(index)
+            .ok_or(MusigError::BadArguments)?;
+        let share = counterparty.verify_share(share, &self.context, &self.transcript)?;
+        self.shares[index] = Some(share);
+        Ok(())
+    }
+
+    /// Returns true if the shares from all the parties have been added.
+    pub fn is_complete(&self) -> bool {
+        self.shares.iter().all(|share| share.is_some())
+    }
+
+    /// Returns the indices of the parties whose shares have been added, in ascending order.
+    pub fn received_indices(&self) -> Vec<usize> {
+        self.shares
+            .iter()
+            .enumerate()
+            .filter_map(|(index, share)| share.map(|_| index))
+            .collect()
+    }
+
+    /// Returns the indices of the parties whose shares have not been added, in ascending order.
+    fn missing_indices(&self) -> Vec<usize> {
+        self.shares
+            .iter()
+            .enumerate()
+            .filter_map(|(index, share)| match share {
+                Some(_) => None,
+                None => Some(index),
+            })
+            .collect()
+    }
+
+    /// Aborts the signing session, e.g. when some parties disconnected before sending their shares.
+    /// Returns `MusigError::IncompleteRound` listing the parties whose shares are missing,
+    /// so the coordinator can restart the session without them.
+    pub fn timeout_abort(self) -> MusigError {
+        MusigError::IncompleteRound {
+            missing: self.missing_indices(),
+        }
+    }
+
+    /// Assembles the signature from the added shares.
+    /// Fails with `MusigError::IncompleteRound` listing the parties whose shares are missing.
+    pub fn finalize(self) -> Result<Signature, MusigError> {
+        let missing = self.missing_indices();
+        if missing.len() != 0 {
+            return Err(MusigError::IncompleteRound { missing });
+        }
+        let s: Scalar = self.shares.iter().filter_map(|share| *share).sum();
+
+        Ok(Signature {
+            s: s + self.tweak,
             R: self.R.compress(),
         })
     }
@@ -13,7 +13,9 @@ use rand::seq::SliceRandom;
 use rand::{thread_rng, CryptoRng, Rng};
 
 extern crate spacesuit;
-use spacesuit::{cloak, CommittedValue, ProverCommittable, Value, VerifierCommittable};
+use spacesuit::{
+    cloak, cloak_parallel, CommittedValue, ProverCommittable, Value, VerifierCommittable,
+};
 
 extern crate bulletproofs;
 use bulletproofs::r1cs::{Prover, R1CSError, R1CSProof, Verifier};
@@ -41,6 +43,25 @@ where
     Ok((proof, in_com, out_com))
 }
 
+fn prove_parallel<R: Rng + CryptoRng>(
+    bp_gens: &BulletproofGens,
+    pc_gens: &PedersenGens,
+    inputs: &Vec<Value>,
+    outputs: &Vec<Value>,
+    rng: &mut R,
+) -> Result<(R1CSProof, Vec<CommittedValue>, Vec<CommittedValue>), R1CSError> {
+    let mut prover_transcript = Transcript::new(b"TransactionTest");
+    let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+    let (in_com, in_vars) = inputs.commit(&mut prover, rng);
+    let (out_com, out_vars) = outputs.commit(&mut prover, rng);
+
+    cloak_parallel(&mut prover, in_vars, out_vars)?;
+    let proof = prover.prove(&bp_gens)?;
+
+    Ok((proof, in_com, out_com))
+}
+
 fn verify(
     bp_gens: &BulletproofGens,
     pc_gens: &PedersenGens,
@@ -107,6 +128,40 @@ fn create_spacesuit_proof_n_64(c: &mut Criterion) {
     create_spacesuit_proof_helper(64, c);
 }
 
+fn create_spacesuit_parallel_proof_helper(n: usize, c: &mut Criterion) {
+    let label = format!(
+        "Spacesuit parallel proof creation with {} inputs and outputs",
+        n
+    );
+
+    c.bench_function(&label, move |b| {
+        // Generate inputs and outputs to spacesuit prover
+        let bp_gens = BulletproofGens::new(10000, 1);
+        let pc_gens = PedersenGens::default();
+
+        let mut rng = thread_rng();
+        let (min, max) = (0u64, std::u64::MAX);
+        let inputs: Vec<Value> = (0..n)
+            .map(|_| Value {
+                q: rng.gen_range(min, max).into(),
+                f: Scalar::random(&mut rng),
+            })
+            .collect();
+        let mut outputs = inputs.clone();
+        let mut rng = thread_rng();
+        outputs.shuffle(&mut rng);
+
+        // Make spacesuit proof, computing the k-mix witnesses in parallel
+        b.iter(|| {
+            prove_parallel(&bp_gens, &pc_gens, &inputs, &outputs, &mut rng).unwrap();
+        })
+    });
+}
+
+fn create_spacesuit_parallel_proof_n_32(c: &mut Criterion) {
+    create_spacesuit_parallel_proof_helper(32, c);
+}
+
 fn verify_spacesuit_proof_helper(n: usize, c: &mut Criterion) {
     let label = format!("Spacesuit proof verification with {} inputs and outputs", n);
 
@@ -163,6 +218,7 @@ criterion_group! {
         create_spacesuit_proof_n_16,
         create_spacesuit_proof_n_32,
         create_spacesuit_proof_n_64,
+        create_spacesuit_parallel_proof_n_32,
 }
 
 criterion_group! {
@@ -1,21 +1,120 @@
-use crate::{mix::k_mix, range_proof};
+use crate::mix::{k_mix_with_witness, MixWitness};
+use crate::range_proof;
 use bit_range::BitRange;
 use bulletproofs::r1cs::{R1CSError, RandomizableConstraintSystem};
 use shuffle::{padded_shuffle, value_shuffle};
-use value::AllocatedValue;
+use std::thread;
+use value::{AllocatedValue, Value};
 
 /// Enforces that the outputs are a valid rearrangement of the inputs, following the
 /// soundness and secrecy requirements in the [Cloak specification](../spec.md).
+///
+/// The numbers of inputs and outputs can differ: the shorter side is padded
+/// with values whose quantity and flavor are constrained to be zero,
+/// so callers do not need to add padding values themselves.
 pub fn cloak<CS: RandomizableConstraintSystem>(
     cs: &mut CS,
     inputs: Vec<AllocatedValue>,
     outputs: Vec<AllocatedValue>,
+) -> Result<(), R1CSError> {
+    let merge_witness = MixWitness::compute(merge_values(&inputs))?;
+    let split_witness = MixWitness::compute(split_values(&outputs))?;
+    cloak_with_witnesses(cs, inputs, outputs, merge_witness, split_witness)
+}
+
+/// Same as `cloak`, but computes the witnesses for the merge and split gadgets
+/// on separate threads before allocating them in the constraint system.
+///
+/// Variables are allocated in the same order as in `cloak`, so the resulting
+/// constraint system is identical: a proof created with `cloak_parallel`
+/// can be verified with `cloak`, and vice versa.
+pub fn cloak_parallel<CS: RandomizableConstraintSystem>(
+    cs: &mut CS,
+    inputs: Vec<AllocatedValue>,
+    outputs: Vec<AllocatedValue>,
+) -> Result<(), R1CSError> {
+    let split_values = split_values(&outputs);
+
+    // Compute the split witness in the background while computing the merge witness.
+    let split_thread = thread::spawn(move || MixWitness::compute(split_values));
+    let merge_witness = MixWitness::compute(merge_values(&inputs))?;
+    let split_witness = split_thread.join().map_err(|_| R1CSError::GadgetError {
+        description: "Thread computing the split witness has panicked".to_string(),
+    })??;
+
+    cloak_with_witnesses(cs, inputs, outputs, merge_witness, split_witness)
+}
+
+/// Same as `cloak`, but skips grouping the inputs by flavor when computing
+/// the merge witness: the caller asserts that inputs of the same flavor are adjacent.
+/// The assertion is only checked in debug builds.
+///
+/// The resulting constraint system is identical to that of `cloak`,
+/// so a proof created with `cloak_presorted` can be verified with `cloak`.
+pub fn cloak_presorted<CS: RandomizableConstraintSystem>(
+    cs: &mut CS,
+    inputs: Vec<AllocatedValue>,
+    outputs: Vec<AllocatedValue>,
+) -> Result<(), R1CSError> {
+    let merge_witness = MixWitness::compute_presorted(merge_values(&inputs))?;
+    let split_witness = MixWitness::compute(split_values(&outputs))?;
+    cloak_with_witnesses(cs, inputs, outputs, merge_witness, split_witness)
+}
+
+/// Same as `cloak`, but places the intermediate values of the merge and split gadgets
+/// in a canonical order: grouped by flavor, with the groups sorted by the flavor bytes
+/// and the values within a group sorted by quantity. The intermediate values then depend
+/// only on the multisets of the inputs and outputs, not on their order, which helps
+/// reproducing reference test vectors.
+///
+/// This only reorders the witness, so the resulting constraint system is identical
+/// to that of `cloak` and a proof created with `cloak_deterministic` can be verified with `cloak`.
+/// Note: sorting is not constant-time with respect to the values.
+pub fn cloak_deterministic<CS: RandomizableConstraintSystem>(
+    cs: &mut CS,
+    inputs: Vec<AllocatedValue>,
+    outputs: Vec<AllocatedValue>,
+) -> Result<(), R1CSError> {
+    let merge_witness = MixWitness::compute_sorted(merge_values(&inputs))?;
+    let split_witness = MixWitness::compute_sorted(split_values(&outputs))?;
+    cloak_with_witnesses(cs, inputs, outputs, merge_witness, split_witness)
+}
+
+/// Returns the assignments of the inputs of the merge gadget,
+/// or None if any of the inputs is unassigned.
+fn merge_values(inputs: &[AllocatedValue]) -> Option<Vec<Value>> {
+    inputs.iter().map(|v| v.assignment).collect()
+}
+
+/// Returns the assignments of the inputs of the split gadget (the outputs in reverse order),
+/// or None if any of the outputs is unassigned.
+fn split_values(outputs: &[AllocatedValue]) -> Option<Vec<Value>> {
+    outputs.iter().rev().map(|v| v.assignment).collect()
+}
+
+/// Allocates the merge and split gadgets with the precomputed witnesses
+/// and adds the shuffles and range proofs connecting them to the inputs and outputs.
+/// The witnesses are `None` in the verifier.
+fn cloak_with_witnesses<CS: RandomizableConstraintSystem>(
+    cs: &mut CS,
+    inputs: Vec<AllocatedValue>,
+    outputs: Vec<AllocatedValue>,
+    merge_witness: Option<MixWitness>,
+    split_witness: Option<MixWitness>,
 ) -> Result<(), R1CSError> {
     // Merge
-    let (merge_in, merge_out) = merge(cs, inputs.clone())?;
+    // Enforces that the outputs are either a merge of the inputs: `D = A + B && C = 0`,
+    // or the outputs are equal to the inputs `C = A && D = B`. See spec for more details.
+    let (merge_in, merge_out) = k_mix_with_witness(cs, inputs.clone(), merge_witness)?;
 
     // Split
-    let (split_in, split_out) = split(cs, outputs.clone())?;
+    // Enforces that the outputs are either a split of the inputs :`A = C + D && B = 0`,
+    // or the outputs are equal to the inputs `C = A && D = B`.
+    // The `split` gadget is the same thing as a `merge` gadget, but "backwards":
+    // reverse the values, and switch the inputs and outputs of a `merge` gadget.
+    let mut reversed_outputs = outputs.clone();
+    reversed_outputs.reverse();
+    let (split_out, split_in) = k_mix_with_witness(cs, reversed_outputs, split_witness)?;
 
     // Shuffle 1
     // Check that `merge_in` is a valid reordering of `inputs`
@@ -46,27 +145,17 @@ pub fn cloak<CS: RandomizableConstraintSystem>(
     Ok(())
 }
 
-/// Enforces that the outputs are either a merge of the inputs: `D = A + B && C = 0`,
-/// or the outputs are equal to the inputs `C = A && D = B`. See spec for more details.
-/// Works for `k` inputs and `k` outputs.
-fn merge<CS: RandomizableConstraintSystem>(
-    cs: &mut CS,
-    inputs: Vec<AllocatedValue>,
-) -> Result<(Vec<AllocatedValue>, Vec<AllocatedValue>), R1CSError> {
-    k_mix(cs, inputs)
-}
+/// Returns the number of multipliers allocated by the `cloak` gadget
+/// with `m` inputs and `n` outputs.
+pub fn cloak_multiplier_count(m: usize, n: usize) -> usize {
+    // Each k-mix allocates 3k-2 values and k-1 mix gadgets.
+    let mix = |k: usize| if k > 1 { 4 * k - 3 } else { 0 };
+    // Each k-value shuffle makes k multipliers and a scalar shuffle with 2(k-1) multipliers.
+    let shuffle = |k: usize| if k > 1 { 3 * k - 2 } else { 0 };
+    // Padded shuffle allocates zero values to even out the sides.
+    let padding = if m > n { m - n } else { n - m };
+    let bits: usize = BitRange::max().into();
+    let range_proofs = n * bits;
 
-/// Enforces that the outputs are either a split of the inputs :`A = C + D && B = 0`,
-/// or the outputs are equal to the inputs `C = A && D = B`. See spec for more details.
-/// Works for `k` inputs and `k` outputs.
-///
-/// Note: the `split` gadget is the same thing as a `merge` gadget, but "backwards".
-/// This means that if you reverse all of the commitment vectors, and switch the
-/// inputs and outputs of a `merge` gadget, then you have a `split` gadget.
-fn split<CS: RandomizableConstraintSystem>(
-    cs: &mut CS,
-    mut inputs: Vec<AllocatedValue>,
-) -> Result<(Vec<AllocatedValue>, Vec<AllocatedValue>), R1CSError> {
-    inputs.reverse();
-    k_mix(cs, inputs).map(|(outs, ins)| (ins, outs))
+    mix(m) + mix(n) + shuffle(m) + padding + shuffle(m.max(n)) + shuffle(n) + range_proofs
 }
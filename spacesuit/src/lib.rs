@@ -18,12 +18,16 @@ mod mix;
 mod range_proof;
 mod shuffle;
 mod signed_integer;
+mod split;
 mod value;
 
 pub use bit_range::BitRange;
-pub use cloak::cloak;
+pub use cloak::{
+    cloak, cloak_deterministic, cloak_multiplier_count, cloak_parallel, cloak_presorted,
+};
 pub use range_proof::range_proof;
-pub use signed_integer::SignedInteger;
+pub use signed_integer::{OutOfRangeError, SignedInteger};
+pub use split::split;
 pub use value::{AllocatedValue, CommittedValue, Value};
 
 // TBD: figure out if we need to export these at all
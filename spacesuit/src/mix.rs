@@ -6,6 +6,7 @@ use bulletproofs::r1cs::{
     ConstraintSystem, R1CSError, RandomizableConstraintSystem, RandomizedConstraintSystem,
 };
 use curve25519_dalek::scalar::Scalar;
+use std::cmp::Ordering;
 use std::iter;
 use subtle::{ConditionallySelectable, ConstantTimeEq};
 
@@ -48,6 +49,9 @@ pub fn k_mix<CS: RandomizableConstraintSystem>(
     inputs: Vec<AllocatedValue>,
 ) -> Result<(Vec<AllocatedValue>, Vec<AllocatedValue>), R1CSError> {
     // If there is only one input and output, simply reuse the input wires as output wires.
+    // This is sound because nothing needs to be mixed, and the value still goes through
+    // the shuffles to the outputs of the cloak, whose quantities are range-checked:
+    // a single negative input cannot be moved to the outputs as-is.
     if inputs.len() == 1 {
         return Ok((inputs.clone(), inputs));
     }
@@ -57,6 +61,88 @@ pub fn k_mix<CS: RandomizableConstraintSystem>(
     Ok((mix_in, mix_out))
 }
 
+/// Intermediate values of a `k_mix` gadget computed ahead of allocation,
+/// so that the witness can be prepared independently of the constraint system
+/// (e.g. on another thread).
+pub(crate) struct MixWitness {
+    mix_in: Vec<Value>,
+    mix_mid: Vec<Value>,
+    mix_out: Vec<Value>,
+}
+
+impl MixWitness {
+    /// Computes the witness for the `k_mix` gadget over the assignments of the `inputs`.
+    /// Returns `None` if any of the inputs is unassigned, or if there is only one input
+    /// (in which case `k_mix` needs no intermediate values).
+    pub(crate) fn compute(inputs: Option<Vec<Value>>) -> Result<Option<Self>, R1CSError> {
+        match inputs {
+            Some(ref values) if values.len() > 1 => Self::from_grouped(group_by_flavor(values)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Same as `compute`, but places the inputs in a canonical order (see `sort_by_flavor`),
+    /// so the intermediate values depend only on the multiset of the inputs.
+    pub(crate) fn compute_sorted(inputs: Option<Vec<Value>>) -> Result<Option<Self>, R1CSError> {
+        match inputs {
+            Some(ref values) if values.len() > 1 => Self::from_grouped(sort_by_flavor(values)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Same as `compute`, but skips grouping the inputs by flavor.
+    /// The caller must ensure that the values of the same flavor are adjacent.
+    pub(crate) fn compute_presorted(inputs: Option<Vec<Value>>) -> Result<Option<Self>, R1CSError> {
+        match inputs {
+            Some(values) if values.len() > 1 => {
+                debug_assert!(
+                    is_grouped_by_flavor(&values),
+                    "Values of the same flavor must be adjacent"
+                );
+                Self::from_grouped(values)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn from_grouped(mix_in: Vec<Value>) -> Result<Option<Self>, R1CSError> {
+        let (mix_mid, mix_out) = combine_values_by_flavor(&mix_in)?;
+        Ok(Some(MixWitness {
+            mix_in,
+            mix_mid,
+            mix_out,
+        }))
+    }
+}
+
+/// Same as `k_mix`, but uses the precomputed `witness` instead of computing it from the inputs.
+/// Variables are allocated in the same order as in `k_mix`, so both produce the same constraint system.
+pub(crate) fn k_mix_with_witness<CS: RandomizableConstraintSystem>(
+    cs: &mut CS,
+    inputs: Vec<AllocatedValue>,
+    witness: Option<MixWitness>,
+) -> Result<(Vec<AllocatedValue>, Vec<AllocatedValue>), R1CSError> {
+    // Reuse the input wires as output wires for a single input (see `k_mix` for soundness).
+    if inputs.len() == 1 {
+        return Ok((inputs.clone(), inputs));
+    }
+
+    let (mix_in, mix_mid, mix_out) = match witness {
+        Some(w) => (
+            allocate_values(&w.mix_in, cs)?,
+            allocate_values(&w.mix_mid, cs)?,
+            allocate_values(&w.mix_out, cs)?,
+        ),
+        None => (
+            AllocatedValue::unassigned_vec(cs, inputs.len())?,
+            AllocatedValue::unassigned_vec(cs, inputs.len() - 2)?,
+            AllocatedValue::unassigned_vec(cs, inputs.len())?,
+        ),
+    };
+    call_mix_gadget(cs, &mix_in, &mix_mid, &mix_out)?;
+    Ok((mix_in, mix_out))
+}
+
 // Calls `k` mix gadgets, using mix_in and mix_mid as inputs, and mix_mid and mix_out as outputs.
 fn call_mix_gadget<CS: RandomizableConstraintSystem>(
     cs: &mut CS,
@@ -138,8 +224,15 @@ fn order_by_flavor<CS: RandomizableConstraintSystem>(
     inputs: &Vec<Value>,
     cs: &mut CS,
 ) -> Result<(Vec<AllocatedValue>, Vec<Value>), R1CSError> {
+    let outputs = group_by_flavor(inputs);
+    let allocated_outputs = allocate_values(&outputs, cs)?;
+    Ok((allocated_outputs, outputs))
+}
+
+// Returns a reordering of the `Value`s where all values are grouped according to flavor.
+fn group_by_flavor(inputs: &[Value]) -> Vec<Value> {
     let k = inputs.len();
-    let mut outputs = inputs.clone();
+    let mut outputs = inputs.to_vec();
 
     for i in 0..k - 1 {
         // This tuple has the flavor that we are trying to group by in this loop
@@ -162,12 +255,29 @@ fn order_by_flavor<CS: RandomizableConstraintSystem>(
         }
     }
 
-    let allocated_outputs = outputs
-        .iter()
-        .map(|value| value.allocate(cs))
-        .collect::<Result<Vec<AllocatedValue>, _>>()?;
+    outputs
+}
 
-    Ok((allocated_outputs, outputs))
+// Returns the `Value`s sorted by the bytes of the flavor, and by quantity within each flavor.
+// Unlike `group_by_flavor`, the result does not depend on the order of the inputs,
+// but the running time does, so it is not constant-time with respect to the witness.
+fn sort_by_flavor(inputs: &[Value]) -> Vec<Value> {
+    let mut outputs = inputs.to_vec();
+    outputs.sort_by(|a, b| {
+        a.f.as_bytes()
+            .cmp(b.f.as_bytes())
+            .then(a.q.partial_cmp(&b.q).unwrap_or(Ordering::Equal))
+    });
+    outputs
+}
+
+// Returns true if all values of the same flavor are adjacent.
+fn is_grouped_by_flavor(values: &[Value]) -> bool {
+    values
+        .iter()
+        .enumerate()
+        .skip(1)
+        .all(|(i, v)| v.f == values[i - 1].f || values[..i - 1].iter().all(|prev| prev.f != v.f))
 }
 
 // Takes:
@@ -182,6 +292,20 @@ fn combine_by_flavor<CS: RandomizableConstraintSystem>(
     inputs: &Vec<Value>,
     cs: &mut CS,
 ) -> Result<(Vec<AllocatedValue>, Vec<AllocatedValue>), R1CSError> {
+    let (mid, outputs) = combine_values_by_flavor(inputs)?;
+
+    // Allocate the mid values followed by the outputs in a single pass,
+    // keeping the same order of multipliers so that the proofs are unchanged.
+    let mut allocated_mid = Vec::with_capacity(mid.len() + outputs.len());
+    for value in mid.iter().chain(outputs.iter()) {
+        allocated_mid.push(value.allocate(cs)?);
+    }
+    let allocated_outputs = allocated_mid.split_off(mid.len());
+    Ok((allocated_mid, allocated_outputs))
+}
+
+// Same as `combine_by_flavor`, but returns the `Value`s without allocating them.
+fn combine_values_by_flavor(inputs: &[Value]) -> Result<(Vec<Value>, Vec<Value>), R1CSError> {
     let mut mid = Vec::with_capacity(inputs.len() - 1);
     let mut outputs = Vec::with_capacity(inputs.len());
 
@@ -225,16 +349,15 @@ fn combine_by_flavor<CS: RandomizableConstraintSystem>(
         }
     }
 
-    let allocated_mid = mid
-        .iter()
-        .map(|value| value.allocate(cs))
-        .collect::<Result<Vec<AllocatedValue>, _>>()?;
-    let allocated_outputs = outputs
-        .iter()
-        .map(|value| value.allocate(cs))
-        .collect::<Result<Vec<AllocatedValue>, _>>()?;
+    Ok((mid, outputs))
+}
 
-    Ok((allocated_mid, allocated_outputs))
+// Allocates the `Value`s in the constraint system, in order.
+fn allocate_values<CS: RandomizableConstraintSystem>(
+    values: &[Value],
+    cs: &mut CS,
+) -> Result<Vec<AllocatedValue>, R1CSError> {
+    values.iter().map(|value| value.allocate(cs)).collect()
 }
 
 #[cfg(test)]
@@ -564,6 +687,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sort_by_flavor_test() {
+        // Zero flavor sorts first, then yuan (888 = 0x0378) before peso (666 = 0x029a)
+        // since the flavors are compared as little-endian bytes.
+        let expected = vec![zero(), yuan(1), yuan(2), peso(1), peso(3)];
+        let permutations = vec![
+            vec![yuan(2), peso(3), yuan(1), zero(), peso(1)],
+            vec![peso(1), peso(3), zero(), yuan(2), yuan(1)],
+            vec![zero(), yuan(1), yuan(2), peso(1), peso(3)],
+            vec![peso(3), yuan(2), peso(1), yuan(1), zero()],
+        ];
+        for inputs in permutations {
+            let sorted = sort_by_flavor(&inputs);
+            assert!(is_grouped_by_flavor(&sorted));
+            assert_eq!(sorted, expected);
+        }
+        assert_eq!(sort_by_flavor(&[yuan(1)]), vec![yuan(1)]);
+    }
+
+    #[test]
+    fn is_grouped_by_flavor_test() {
+        assert!(is_grouped_by_flavor(&[]));
+        assert!(is_grouped_by_flavor(&[yuan(1)]));
+        assert!(is_grouped_by_flavor(&[yuan(1), yuan(2), peso(3)]));
+        assert!(is_grouped_by_flavor(&[peso(3), yuan(1), yuan(2)]));
+        assert!(!is_grouped_by_flavor(&[yuan(1), peso(3), yuan(2)]));
+        assert!(!is_grouped_by_flavor(&[yuan(1), zero(), peso(3), zero()]));
+    }
+
     #[test]
     fn combine_by_flavor_test() {
         // k = 2
@@ -605,6 +757,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn combine_by_flavor_allocation() {
+        let flavors = [yuan(0).f, peso(0).f, zero().f];
+        for k in 2..=16 {
+            let inputs = group_by_flavor(
+                &(0..k)
+                    .map(|i| Value {
+                        q: (i as u64 + 1).into(),
+                        f: flavors[(i * 7) % 3],
+                    })
+                    .collect::<Vec<_>>(),
+            );
+
+            let pc_gens = PedersenGens::default();
+            let mut transcript = Transcript::new(b"CombineByFlavorTest");
+            let mut prover_cs = Prover::new(&pc_gens, &mut transcript);
+            let (mid, outputs) = combine_by_flavor(&inputs, &mut prover_cs).unwrap();
+
+            // Reference: allocate the mid values, then the outputs, in two passes.
+            let mut transcript = Transcript::new(b"CombineByFlavorTest");
+            let mut reference_cs = Prover::new(&pc_gens, &mut transcript);
+            let (ref_mid, ref_outputs) = combine_values_by_flavor(&inputs).unwrap();
+            let ref_mid = allocate_values(&ref_mid, &mut reference_cs).unwrap();
+            let ref_outputs = allocate_values(&ref_outputs, &mut reference_cs).unwrap();
+
+            assert_eq!(mid.len(), k - 2);
+            assert_eq!(outputs.len(), k);
+            for (a, b) in mid
+                .iter()
+                .chain(outputs.iter())
+                .zip(ref_mid.iter().chain(ref_outputs.iter()))
+            {
+                assert_eq!(a.assignment, b.assignment);
+                assert_eq!(a.q, b.q);
+                assert_eq!(a.f, b.f);
+            }
+        }
+    }
+
     fn combine_by_flavor_helper(inputs: &Vec<Value>) -> (Vec<Value>, Vec<Value>) {
         let pc_gens = PedersenGens::default();
         let mut transcript = Transcript::new(b"CombineByFlavorTest");
@@ -625,3 +816,81 @@ mod tests {
         (mid, output)
     }
 }
+
+// Timing tests are statistical and depend on the machine, so they only run with
+// `cargo test --release --features ct-tests`.
+#[cfg(all(test, feature = "ct-tests"))]
+mod ct_tests {
+    use super::*;
+    use bulletproofs::r1cs::Prover;
+    use bulletproofs::PedersenGens;
+    use merlin::Transcript;
+    use rand::seq::SliceRandom;
+    use rand::Rng;
+    use std::time::Instant;
+
+    // Number of values ordered in each measurement.
+    const K: usize = 16;
+    // Number of measurements for each number of matching flavors.
+    const RUNS: usize = 5000;
+    // Maximum relative difference between the median timings.
+    const THRESHOLD: f64 = 0.1;
+
+    // Makes `K` values with random quantities in random order,
+    // of which `matching` values share a flavor and the rest have distinct flavors.
+    fn make_inputs(rng: &mut rand::rngs::ThreadRng, matching: usize) -> Vec<Value> {
+        let common = Scalar::random(rng);
+        let mut values: Vec<Value> = (0..K)
+            .map(|i| Value {
+                q: (rng.gen::<u32>() as u64).into(),
+                f: if i < matching {
+                    common
+                } else {
+                    Scalar::random(rng)
+                },
+            })
+            .collect();
+        values.shuffle(rng);
+        values
+    }
+
+    fn median(mut samples: Vec<u128>) -> f64 {
+        samples.sort();
+        samples[samples.len() / 2] as f64
+    }
+
+    #[test]
+    fn order_by_flavor_timing() {
+        let mut rng = rand::thread_rng();
+        let pc_gens = PedersenGens::default();
+        let mut transcript = Transcript::new(b"OrderByFlavorTimingTest");
+        let mut prover_cs = Prover::new(&pc_gens, &mut transcript);
+
+        // From all distinct flavors to all the same flavor.
+        let classes = [1, K / 2, K];
+        let mut samples = vec![Vec::with_capacity(RUNS); classes.len()];
+
+        // Classes are interleaved, so that any drift in the machine's speed affects them equally.
+        for _ in 0..RUNS {
+            for (class, matching) in classes.iter().enumerate() {
+                let inputs = make_inputs(&mut rng, *matching);
+                let start = Instant::now();
+                let result = order_by_flavor(&inputs, &mut prover_cs);
+                let elapsed = start.elapsed().as_nanos();
+                assert!(result.is_ok());
+                samples[class].push(elapsed);
+            }
+        }
+
+        let medians: Vec<f64> = samples.into_iter().map(median).collect();
+        let min = medians.iter().cloned().fold(std::f64::INFINITY, f64::min);
+        let max = medians.iter().cloned().fold(0.0, f64::max);
+        assert!(
+            (max - min) / min < THRESHOLD,
+            "median timings {:?} (ns) for {:?} matching flavors differ by more than {}",
+            medians,
+            classes,
+            THRESHOLD
+        );
+    }
+}
@@ -1,4 +1,6 @@
 //! Range-preserving arithmetic on signed integers with u64 absolute value.
+use core::convert::TryFrom;
+use core::fmt;
 use core::ops::Neg;
 use curve25519_dalek::scalar::Scalar;
 use std::ops::{Add, Mul};
@@ -8,6 +10,11 @@ use subtle::{Choice, ConditionallySelectable};
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd)]
 pub struct SignedInteger(i128);
 
+/// Error returned when an integer does not fit into the target type
+/// when converting to or from `SignedInteger`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct OutOfRangeError;
+
 impl SignedInteger {
     /// Returns Some(x) if self is non-negative
     /// Otherwise returns None.
@@ -23,6 +30,17 @@ impl SignedInteger {
     pub fn to_scalar(self) -> Scalar {
         self.into()
     }
+
+    /// Returns the `n` bits of the integer in little-endian order,
+    /// the same way the `range_proof` gadget decomposes the quantity.
+    /// Returns None if the integer is negative or does not fit into `n` bits.
+    pub fn to_bits(&self, n: usize) -> Option<Vec<bool>> {
+        let q = self.to_u64()?;
+        if n < 64 && q >> n != 0 {
+            return None;
+        }
+        Some((0..n).map(|i| i < 64 && (q >> i) & 1 == 1).collect())
+    }
 }
 
 impl From<u64> for SignedInteger {
@@ -31,6 +49,41 @@ impl From<u64> for SignedInteger {
     }
 }
 
+impl From<i64> for SignedInteger {
+    fn from(i: i64) -> SignedInteger {
+        SignedInteger(i as i128)
+    }
+}
+
+impl TryFrom<i128> for SignedInteger {
+    type Error = OutOfRangeError;
+
+    /// Fails if the absolute value of `i` does not fit into 64 bits.
+    fn try_from(i: i128) -> Result<SignedInteger, OutOfRangeError> {
+        let max = u64::max_value() as i128;
+        if i <= max && i >= -max {
+            Ok(SignedInteger(i))
+        } else {
+            Err(OutOfRangeError)
+        }
+    }
+}
+
+impl TryFrom<SignedInteger> for i64 {
+    type Error = OutOfRangeError;
+
+    /// Fails if the value is below `-2^63` or above `2^63 - 1`.
+    fn try_from(i: SignedInteger) -> Result<i64, OutOfRangeError> {
+        i64::try_from(i.0).map_err(|_| OutOfRangeError)
+    }
+}
+
+impl fmt::Display for SignedInteger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 impl Into<Scalar> for SignedInteger {
     fn into(self) -> Scalar {
         if self.0 < 0 {
@@ -125,4 +178,66 @@ mod tests {
         let b = -SignedInteger::from(u64::max_value());
         assert_eq!(a * b, None);
     }
+
+    #[test]
+    fn to_bits() {
+        assert_eq!(SignedInteger::from(0u64).to_bits(4), Some(vec![false; 4]));
+        assert_eq!(SignedInteger::from(0u64).to_bits(0), Some(vec![]));
+        assert_eq!(
+            SignedInteger::from(6u64).to_bits(4),
+            Some(vec![false, true, true, false])
+        );
+        assert_eq!(SignedInteger::from(15u64).to_bits(4), Some(vec![true; 4]));
+        assert_eq!(
+            SignedInteger::from(u64::max_value()).to_bits(64),
+            Some(vec![true; 64])
+        );
+
+        // Out of range
+        assert_eq!(SignedInteger::from(16u64).to_bits(4), None);
+        assert_eq!(SignedInteger::from(1u64).to_bits(0), None);
+        assert_eq!(SignedInteger::from(-1i64).to_bits(4), None);
+        assert_eq!(SignedInteger::from(-1i64).to_bits(64), None);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(SignedInteger::from(42u64).to_string(), "42");
+        assert_eq!(SignedInteger::from(0u64).to_string(), "0");
+        assert_eq!(SignedInteger::from(-42i64).to_string(), "-42");
+        assert_eq!(
+            (-SignedInteger::from(u64::max_value())).to_string(),
+            "-18446744073709551615"
+        );
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(SignedInteger::from(-5i64), -SignedInteger::from(5u64));
+        assert_eq!(
+            SignedInteger::from(i64::min_value()),
+            -SignedInteger::from(1u64 << 63)
+        );
+
+        // Absolute value must fit into 64 bits.
+        let max = u64::max_value() as i128;
+        assert_eq!(
+            SignedInteger::try_from(-max),
+            Ok(-SignedInteger::from(u64::max_value()))
+        );
+        assert_eq!(SignedInteger::try_from(-max - 1), Err(OutOfRangeError));
+        assert_eq!(SignedInteger::try_from(max + 1), Err(OutOfRangeError));
+
+        // Conversion to i64 fails below -2^63.
+        let min = -SignedInteger::from(1u64 << 63);
+        assert_eq!(i64::try_from(min), Ok(i64::min_value()));
+        assert_eq!(
+            i64::try_from((min + -SignedInteger::from(1u64)).unwrap()),
+            Err(OutOfRangeError)
+        );
+        assert_eq!(
+            i64::try_from(SignedInteger::from(1u64 << 63)),
+            Err(OutOfRangeError)
+        );
+    }
 }
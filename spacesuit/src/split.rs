@@ -0,0 +1,127 @@
+use bit_range::BitRange;
+use bulletproofs::r1cs::{ConstraintSystem, LinearCombination, R1CSError};
+
+use crate::range_proof::range_proof;
+use crate::signed_integer::SignedInteger;
+use crate::value::{AllocatedValue, Value};
+
+/// Splits the `input` value into values of the same flavor with the given quantities,
+/// e.g. to make change.
+///
+/// Enforces that every output has the flavor of the input and a quantity in the range [0, 2^64),
+/// and that the quantities of the outputs sum to the quantity of the input.
+/// The amounts are secret: they are only used as the prover's witness,
+/// so the verifier (whose input is unassigned) needs to pass only the right number of them.
+///
+/// Returns an error if the amounts overflow 64 bits or do not sum to the input quantity.
+pub fn split<CS: ConstraintSystem>(
+    cs: &mut CS,
+    input: AllocatedValue,
+    amounts: &[u64],
+) -> Result<Vec<AllocatedValue>, R1CSError> {
+    if let Some(value) = input.assignment {
+        let total = amounts
+            .iter()
+            .try_fold(0u64, |sum, amount| sum.checked_add(*amount))
+            .ok_or(R1CSError::GadgetError {
+                description: "Split amounts overflow 64 bits".to_string(),
+            })?;
+        if value.q != SignedInteger::from(total) {
+            return Err(R1CSError::GadgetError {
+                description: "Split amounts do not sum to the input quantity".to_string(),
+            });
+        }
+    }
+
+    let mut outputs = Vec::with_capacity(amounts.len());
+    let mut remainder: LinearCombination = input.q.into();
+    for amount in amounts.iter() {
+        let assignment = input.assignment.map(|value| Value {
+            q: (*amount).into(),
+            f: value.f,
+        });
+        let output = match assignment {
+            Some(value) => value.allocate(cs)?,
+            None => AllocatedValue::unassigned(cs)?,
+        };
+
+        // Enforce that the output has the input's flavor and a non-negative quantity.
+        cs.constrain(output.f - input.f);
+        range_proof(
+            cs,
+            output.q.into(),
+            assignment.map(|v| v.q),
+            BitRange::max(),
+        )?;
+
+        remainder = remainder - output.q;
+        outputs.push(output);
+    }
+
+    // Enforce that the output quantities sum to the input quantity.
+    cs.constrain(remainder);
+
+    Ok(outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bulletproofs::r1cs::{Prover, Verifier};
+    use bulletproofs::{BulletproofGens, PedersenGens};
+    use curve25519_dalek::scalar::Scalar;
+    use merlin::Transcript;
+    use value::{ProverCommittable, VerifierCommittable};
+
+    fn split_helper(input: u64, amounts: &[u64]) -> Result<(), R1CSError> {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(256, 1);
+        let flavor = Scalar::from(888u64);
+
+        // Prover's scope
+        let (proof, input_com) = {
+            let mut prover_transcript = Transcript::new(b"SplitTest");
+            let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+            let value = Value {
+                q: input.into(),
+                f: flavor,
+            };
+            let (input_com, input_var) = value.commit(&mut prover, &mut rand::thread_rng());
+            let outputs = split(&mut prover, input_var, amounts)?;
+            for (output, amount) in outputs.iter().zip(amounts.iter()) {
+                assert_eq!(
+                    output.assignment,
+                    Some(Value {
+                        q: (*amount).into(),
+                        f: flavor,
+                    })
+                );
+            }
+
+            (prover.prove(&bp_gens)?, input_com)
+        };
+
+        // Verifier's scope
+        let mut verifier_transcript = Transcript::new(b"SplitTest");
+        let mut verifier = Verifier::new(&mut verifier_transcript);
+        let input_var = input_com.commit(&mut verifier);
+        split(&mut verifier, input_var, amounts)?;
+        Ok(verifier.verify(&proof, &pc_gens, &bp_gens)?)
+    }
+
+    #[test]
+    fn split_value() {
+        assert!(split_helper(10, &[3, 7]).is_ok());
+        assert!(split_helper(10, &[10]).is_ok());
+        assert!(split_helper(10, &[0, 10, 0]).is_ok());
+
+        // Amounts do not sum to the input quantity
+        assert!(split_helper(10, &[3, 8]).is_err());
+        assert!(split_helper(10, &[3, 6]).is_err());
+        assert!(split_helper(10, &[]).is_err());
+
+        // Amounts overflow
+        assert!(split_helper(10, &[u64::max_value(), 11]).is_err());
+    }
+}
@@ -109,11 +109,40 @@ impl ProverCommittable for Value {
     }
 }
 
+/// Commits all the values in one pass, drawing all the blinding factors upfront.
+/// Produces the same commitments as committing the values one by one with the same RNG.
+impl ProverCommittable for [Value] {
+    type Output = (Vec<CommittedValue>, Vec<AllocatedValue>);
+
+    fn commit<R: Rng + CryptoRng>(&self, prover: &mut Prover, rng: &mut R) -> Self::Output {
+        // Two blinding factors per value, for the quantity and the flavor,
+        // in the same order as `Value::commit` draws them.
+        let blindings: Vec<Scalar> = (0..2 * self.len()).map(|_| Scalar::random(rng)).collect();
+
+        let mut commitments = Vec::with_capacity(self.len());
+        let mut vars = Vec::with_capacity(self.len());
+        for (value, blinding) in self.iter().zip(blindings.chunks(2)) {
+            let (q_commit, q_var) = prover.commit(value.q.into(), blinding[0]);
+            let (f_commit, f_var) = prover.commit(value.f, blinding[1]);
+            commitments.push(CommittedValue {
+                q: q_commit,
+                f: f_commit,
+            });
+            vars.push(AllocatedValue {
+                q: q_var,
+                f: f_var,
+                assignment: Some(*value),
+            });
+        }
+        (commitments, vars)
+    }
+}
+
 impl ProverCommittable for Vec<Value> {
     type Output = (Vec<CommittedValue>, Vec<AllocatedValue>);
 
     fn commit<R: Rng + CryptoRng>(&self, prover: &mut Prover, rng: &mut R) -> Self::Output {
-        self.iter().map(|value| value.commit(prover, rng)).unzip()
+        self.as_slice().commit(prover, rng)
     }
 }
 
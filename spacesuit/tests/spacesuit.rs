@@ -6,9 +6,13 @@ extern crate spacesuit;
 use bulletproofs::r1cs::{Prover, R1CSError, R1CSProof, Verifier};
 use bulletproofs::{BulletproofGens, PedersenGens};
 use merlin::Transcript;
-use rand::{CryptoRng, Rng};
+use rand::rngs::StdRng;
+use rand::{CryptoRng, Rng, SeedableRng};
 
-use spacesuit::{cloak, CommittedValue, ProverCommittable, Value, VerifierCommittable};
+use spacesuit::{
+    cloak, cloak_deterministic, cloak_multiplier_count, cloak_parallel, cloak_presorted,
+    AllocatedValue, CommittedValue, ProverCommittable, SignedInteger, Value, VerifierCommittable,
+};
 
 fn spacesuit_helper(
     bp_gens: &BulletproofGens,
@@ -18,20 +22,29 @@ fn spacesuit_helper(
     let pc_gens = PedersenGens::default();
     let mut rng = rand::thread_rng();
 
-    let (proof, in_com, out_com) = prove(&bp_gens, &pc_gens, &inputs, &outputs, &mut rng)?;
+    let (proof, in_com, out_com) = prove(
+        &bp_gens,
+        &pc_gens,
+        &inputs,
+        &outputs,
+        &mut rng,
+        |cs, i, o| cloak(cs, i, o),
+    )?;
 
     verify(&bp_gens, &pc_gens, &proof, &in_com, &out_com)
 }
 
-fn prove<R: Rng + CryptoRng>(
+fn prove<R, G>(
     bp_gens: &BulletproofGens,
     pc_gens: &PedersenGens,
     inputs: &Vec<Value>,
     outputs: &Vec<Value>,
     rng: &mut R,
+    gadget: G,
 ) -> Result<(R1CSProof, Vec<CommittedValue>, Vec<CommittedValue>), R1CSError>
 where
-    R: rand::RngCore,
+    R: Rng + CryptoRng,
+    G: FnOnce(&mut Prover, Vec<AllocatedValue>, Vec<AllocatedValue>) -> Result<(), R1CSError>,
 {
     let mut prover_transcript = Transcript::new(b"TransactionTest");
     let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
@@ -39,7 +52,7 @@ where
     let (in_com, in_vars) = inputs.commit(&mut prover, rng);
     let (out_com, out_vars) = outputs.commit(&mut prover, rng);
 
-    cloak(&mut prover, in_vars, out_vars)?;
+    gadget(&mut prover, in_vars, out_vars)?;
     let proof = prover.prove(&bp_gens)?;
 
     Ok((proof, in_com, out_com))
@@ -96,6 +109,20 @@ fn spacesuit_1_1() {
     assert!(spacesuit_helper(&bp_gens, vec![yuan(1)], vec![peso(4)]).is_err());
 }
 
+// m=1 with a negative input: the merge passes the input through unchanged,
+// but the range proofs on the outputs still reject the negative quantity.
+#[test]
+fn spacesuit_1_negative_input() {
+    let bp_gens = BulletproofGens::new(1000, 1);
+    let negative = |q: u64| Value {
+        q: -SignedInteger::from(q),
+        f: 888u64.into(),
+    };
+    assert!(spacesuit_helper(&bp_gens, vec![negative(5)], vec![negative(5)]).is_err());
+    assert!(spacesuit_helper(&bp_gens, vec![negative(5)], vec![zero()]).is_err());
+    assert!(spacesuit_helper(&bp_gens, vec![negative(5)], vec![yuan(1), negative(6)]).is_err());
+}
+
 // max(m, n) = 2
 #[test]
 fn spacesuit_uneven_2() {
@@ -260,6 +287,38 @@ fn spacesuit_uneven_3() {
     .is_ok());
 }
 
+// m=3, n=5 and m=5, n=2 with implicit padding
+#[test]
+fn spacesuit_uneven_padding() {
+    let bp_gens = BulletproofGens::new(1000, 1);
+    assert!(spacesuit_helper(
+        &bp_gens,
+        vec![yuan(10), peso(4), yuan(2)],
+        vec![yuan(3), peso(1), yuan(4), peso(3), yuan(5)],
+    )
+    .is_ok());
+    assert!(spacesuit_helper(
+        &bp_gens,
+        vec![yuan(3), peso(1), yuan(4), peso(3), yuan(5)],
+        vec![peso(4), yuan(12)],
+    )
+    .is_ok());
+
+    // Padding cannot be used to create or destroy value
+    assert!(spacesuit_helper(
+        &bp_gens,
+        vec![yuan(10), peso(4), yuan(2)],
+        vec![yuan(3), peso(1), yuan(4), peso(3), yuan(6)],
+    )
+    .is_err());
+    assert!(spacesuit_helper(
+        &bp_gens,
+        vec![yuan(3), peso(1), yuan(4), peso(3), yuan(5)],
+        vec![peso(4), yuan(11)],
+    )
+    .is_err());
+}
+
 // m=4, n=4
 #[test]
 fn spacesuit_4_4() {
@@ -336,3 +395,207 @@ fn spacesuit_4_4() {
     )
     .is_ok());
 }
+
+// Proofs created with `cloak_parallel` are verified with `cloak`
+#[test]
+fn spacesuit_parallel() {
+    let bp_gens = BulletproofGens::new(1000, 1);
+    let pc_gens = PedersenGens::default();
+    let mut rng = rand::thread_rng();
+
+    let cases = vec![
+        (vec![yuan(1)], vec![yuan(1)], true),
+        (vec![yuan(3)], vec![yuan(1), yuan(2)], true),
+        (
+            vec![yuan(4), peso(4), peso(4), yuan(3)],
+            vec![peso(1), yuan(2), yuan(5), peso(7)],
+            true,
+        ),
+        (
+            vec![yuan(10), peso(1), peso(2), peso(3)],
+            vec![yuan(5), yuan(4), yuan(1), peso(6)],
+            true,
+        ),
+        (
+            vec![yuan(1), yuan(2), yuan(5), yuan(2)],
+            vec![yuan(4), yuan(3), yuan(3), yuan(20)],
+            false,
+        ),
+    ];
+
+    for (inputs, outputs, valid) in cases {
+        let result = prove(
+            &bp_gens,
+            &pc_gens,
+            &inputs,
+            &outputs,
+            &mut rng,
+            |cs, i, o| cloak_parallel(cs, i, o),
+        )
+        .and_then(|(proof, in_com, out_com)| verify(&bp_gens, &pc_gens, &proof, &in_com, &out_com));
+        assert_eq!(result.is_ok(), valid);
+    }
+}
+
+// Proofs created with `cloak_presorted` from flavor-grouped inputs
+// match proofs created with `cloak` from the same inputs in arbitrary order.
+#[test]
+fn spacesuit_presorted() {
+    let bp_gens = BulletproofGens::new(1000, 1);
+    let pc_gens = PedersenGens::default();
+    let mut rng = rand::thread_rng();
+
+    let cases = vec![
+        (
+            vec![yuan(4), peso(4), peso(4), yuan(3)],
+            vec![yuan(4), yuan(3), peso(4), peso(4)],
+            vec![peso(1), yuan(2), yuan(5), peso(7)],
+        ),
+        (
+            vec![peso(1), yuan(10), peso(2), euro(3)],
+            vec![euro(3), peso(1), peso(2), yuan(10)],
+            vec![yuan(5), yuan(4), yuan(1), peso(3), euro(3)],
+        ),
+    ];
+
+    for (unsorted, sorted, outputs) in cases {
+        let (proof, in_com, out_com) = prove(
+            &bp_gens,
+            &pc_gens,
+            &unsorted,
+            &outputs,
+            &mut rng,
+            |cs, i, o| cloak(cs, i, o),
+        )
+        .unwrap();
+        assert!(verify(&bp_gens, &pc_gens, &proof, &in_com, &out_com).is_ok());
+
+        let (presorted_proof, in_com, out_com) = prove(
+            &bp_gens,
+            &pc_gens,
+            &sorted,
+            &outputs,
+            &mut rng,
+            |cs, i, o| cloak_presorted(cs, i, o),
+        )
+        .unwrap();
+        assert!(verify(&bp_gens, &pc_gens, &presorted_proof, &in_com, &out_com).is_ok());
+
+        assert_eq!(proof.to_bytes().len(), presorted_proof.to_bytes().len());
+    }
+}
+
+// Proofs created with `cloak_deterministic` are verified with `cloak`,
+// regardless of the order of the inputs and outputs.
+#[test]
+fn spacesuit_deterministic() {
+    let bp_gens = BulletproofGens::new(1000, 1);
+    let pc_gens = PedersenGens::default();
+    let mut rng = rand::thread_rng();
+
+    let cases = vec![
+        (
+            vec![yuan(4), peso(4), peso(4), yuan(3)],
+            vec![peso(1), yuan(2), yuan(5), peso(7)],
+        ),
+        (
+            vec![euro(3), yuan(10), peso(2), peso(1)],
+            vec![yuan(4), peso(3), yuan(5), euro(3), yuan(1)],
+        ),
+        (vec![yuan(1), yuan(2)], vec![yuan(3)]),
+    ];
+
+    for (mut inputs, mut outputs) in cases {
+        for _ in 0..2 {
+            let (proof, in_com, out_com) = prove(
+                &bp_gens,
+                &pc_gens,
+                &inputs,
+                &outputs,
+                &mut rng,
+                |cs, i, o| cloak_deterministic(cs, i, o),
+            )
+            .unwrap();
+            assert!(verify(&bp_gens, &pc_gens, &proof, &in_com, &out_com).is_ok());
+            inputs.reverse();
+            outputs.reverse();
+        }
+    }
+
+    // Unbalanced values are still rejected.
+    let inputs = vec![yuan(1), peso(2)];
+    let outputs = vec![yuan(2), peso(1)];
+    let result = prove(
+        &bp_gens,
+        &pc_gens,
+        &inputs,
+        &outputs,
+        &mut rng,
+        |cs, i, o| cloak_deterministic(cs, i, o),
+    )
+    .and_then(|(proof, in_com, out_com)| verify(&bp_gens, &pc_gens, &proof, &in_com, &out_com));
+    assert!(result.is_err());
+}
+
+#[test]
+fn multiplier_count() {
+    let bp_gens = BulletproofGens::new(1000, 1);
+    let pc_gens = PedersenGens::default();
+    let mut rng = rand::thread_rng();
+
+    let cases = vec![
+        (vec![yuan(1)], vec![yuan(1)]),
+        (vec![yuan(3)], vec![yuan(1), yuan(2)]),
+        (vec![yuan(1), yuan(2)], vec![yuan(3)]),
+        (
+            vec![yuan(4), peso(4), peso(4), yuan(3)],
+            vec![peso(1), yuan(2), yuan(5), peso(7)],
+        ),
+        (
+            vec![yuan(1), peso(4), euro(8)],
+            vec![yuan(1), peso(4), euro(2), euro(6), zero()],
+        ),
+    ];
+
+    for (inputs, outputs) in cases {
+        let mut transcript = Transcript::new(b"TransactionTest");
+        let mut prover = Prover::new(&pc_gens, &mut transcript);
+        let (_, in_vars) = inputs.commit(&mut prover, &mut rng);
+        let (_, out_vars) = outputs.commit(&mut prover, &mut rng);
+        cloak(&mut prover, in_vars, out_vars).unwrap();
+        let (_, metrics) = prover.prove_and_return_metrics(&bp_gens).unwrap();
+
+        assert_eq!(
+            metrics.multipliers,
+            cloak_multiplier_count(inputs.len(), outputs.len())
+        );
+    }
+}
+
+#[test]
+fn batch_commit_matches_individual_commits() {
+    let pc_gens = PedersenGens::default();
+    let values = vec![yuan(1), peso(2), euro(3), zero()];
+
+    let mut transcript = Transcript::new(b"TransactionTest");
+    let mut prover = Prover::new(&pc_gens, &mut transcript);
+    let mut rng = StdRng::from_seed([7u8; 32]);
+    let (batch_com, batch_vars) = values.commit(&mut prover, &mut rng);
+
+    let mut transcript = Transcript::new(b"TransactionTest");
+    let mut prover = Prover::new(&pc_gens, &mut transcript);
+    let mut rng = StdRng::from_seed([7u8; 32]);
+    let (single_com, single_vars): (Vec<_>, Vec<_>) = values
+        .iter()
+        .map(|value| value.commit(&mut prover, &mut rng))
+        .unzip();
+
+    assert_eq!(batch_com.len(), values.len());
+    for (batch, single) in batch_com.iter().zip(single_com.iter()) {
+        assert_eq!(batch.q, single.q);
+        assert_eq!(batch.f, single.f);
+    }
+    for (batch, single) in batch_vars.iter().zip(single_vars.iter()) {
+        assert_eq!(batch.assignment, single.assignment);
+    }
+}
@@ -0,0 +1,65 @@
+//! Derive macros for ZkVM encoding.
+//! See `README.md` for details.
+#![deny(missing_docs)]
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Index};
+
+/// Implements `Encodable` for a struct by encoding its fields in declaration order.
+/// `serialized_length` is the sum of the fields' serialized lengths,
+/// so it always matches the output of `encode`.
+#[proc_macro_derive(Encodable)]
+pub fn derive_encodable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields
+                .named
+                .iter()
+                .map(|f| {
+                    let name = &f.ident;
+                    quote!(self.#name)
+                })
+                .collect::<Vec<_>>(),
+            Fields::Unnamed(fields) => (0..fields.unnamed.len())
+                .map(|i| {
+                    let index = Index::from(i);
+                    quote!(self.#index)
+                })
+                .collect::<Vec<_>>(),
+            Fields::Unit => Vec::new(),
+        },
+        _ => {
+            return Error::new(
+                Span::call_site(),
+                "Encodable can only be derived for structs",
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let encode_fields = fields.iter();
+    let length_fields = fields.iter();
+
+    let expanded = quote! {
+        impl #impl_generics crate::encoding::Encodable for #name #ty_generics #where_clause {
+            fn encode<S: crate::encoding::Sink>(&self, buf: &mut S) {
+                #( crate::encoding::Encodable::encode(&#encode_fields, buf); )*
+            }
+
+            fn serialized_length(&self) -> usize {
+                0 #( + crate::encoding::Encodable::serialized_length(&#length_fields) )*
+            }
+        }
+    };
+
+    expanded.into()
+}
@@ -1,4 +1,3 @@
-use bulletproofs::BulletproofGens;
 use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
 use musig::Signature;
@@ -6,7 +5,7 @@ use rand::RngCore;
 
 use super::*;
 use crate::{
-    Anchor, Commitment, Contract, PortableItem, Predicate, Program, Prover, String, TxHeader,
+    gens, Anchor, Commitment, Contract, PortableItem, Predicate, Program, Prover, String, TxHeader,
     Value, VerificationKey,
 };
 
@@ -34,7 +33,7 @@ fn make_nonce_contract(privkey: u64, qty: u64) -> Contract {
 
 #[test]
 fn test_state_machine() {
-    let bp_gens = BulletproofGens::new(256, 1);
+    let (_, bp_gens) = gens();
     let privkey = Scalar::from(1u64);
     let initial_contract = make_nonce_contract(1, 100);
     let (mut state, proofs) = BlockchainState::make_initial(0u64, vec![initial_contract.id()]);
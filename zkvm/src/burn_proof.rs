@@ -0,0 +1,119 @@
+//! Proof that a cleartext value was retired in a given transaction.
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
+
+use crate::constraints::Commitment;
+use crate::encoding;
+use crate::encoding::Encodable;
+use crate::encoding::Sink;
+use crate::encoding::SliceReader;
+use crate::errors::VMError;
+use crate::merkle::{self, MerkleNeighbor, MerkleTree};
+use crate::tx::{TxEntry, TxID};
+use crate::types::ClearValue;
+
+/// Burn proof shows that a specific `ClearValue` was destroyed by `retire`
+/// in a transaction, without revealing the rest of the transaction.
+/// It opens the commitments of the retired value and proves that their
+/// `TxEntry::Retire` entry is included in the transaction log committed to by the `TxID`.
+#[derive(Clone, Debug)]
+pub struct BurnProof {
+    /// Retired value.
+    pub value: ClearValue,
+
+    /// Blinding factor of the quantity commitment.
+    pub qty_blinding: Scalar,
+
+    /// Blinding factor of the flavor commitment.
+    pub flv_blinding: Scalar,
+
+    /// List of left-right neighbors from the retire entry up to the txid, excluding both.
+    pub neighbors: Vec<MerkleNeighbor>,
+}
+
+impl BurnProof {
+    /// Creates a burn proof for the value retired in the given transaction log.
+    /// Fails with `VMError::InvalidMerkleProof` if the log has no retire entry
+    /// with the commitments to the value under the given blinding factors.
+    pub fn new(
+        txlog: &[TxEntry],
+        value: ClearValue,
+        qty_blinding: Scalar,
+        flv_blinding: Scalar,
+    ) -> Result<Self, VMError> {
+        let mut proof = BurnProof {
+            value,
+            qty_blinding,
+            flv_blinding,
+            neighbors: Vec::new(),
+        };
+        let (qty, flv) = proof.commitments();
+        let index = txlog
+            .iter()
+            .position(|entry| match entry {
+                TxEntry::Retire(q, f) => q == &qty && f == &flv,
+                _ => false,
+            })
+            .ok_or(VMError::InvalidMerkleProof)?;
+        proof.neighbors = MerkleTree::build(b"ZkVM.txid", txlog).create_path(index)?;
+        Ok(proof)
+    }
+
+    /// Verifies that the value was retired in the transaction with the given ID.
+    pub fn verify(&self, txid: &TxID) -> Result<(), VMError> {
+        MerkleTree::verify_path(b"ZkVM.txid", &self.entry(), self.neighbors.clone(), &txid.0)
+    }
+
+    /// Returns the retire entry committing to the value.
+    fn entry(&self) -> TxEntry {
+        let (qty, flv) = self.commitments();
+        TxEntry::Retire(qty, flv)
+    }
+
+    /// Returns the quantity and flavor commitments of the value.
+    fn commitments(&self) -> (CompressedRistretto, CompressedRistretto) {
+        (
+            Commitment::blinded_with_factor(self.value.qty, self.qty_blinding).to_point(),
+            Commitment::blinded_with_factor(self.value.flv, self.flv_blinding).to_point(),
+        )
+    }
+
+    /// Decodes the burn proof from bytes.
+    pub fn decode<'a>(reader: &mut SliceReader<'a>) -> Result<Self, VMError> {
+        let qty = reader.read_u64()?;
+        let flv = reader.read_scalar()?;
+        let qty_blinding = reader.read_scalar()?;
+        let flv_blinding = reader.read_scalar()?;
+        let neighbors = merkle::decode_path(reader)?;
+        Ok(BurnProof {
+            value: ClearValue { qty, flv },
+            qty_blinding,
+            flv_blinding,
+            neighbors,
+        })
+    }
+
+    /// Serializes the burn proof to a byte array.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encode_to_vec()
+    }
+
+    /// Deserializes the burn proof from a byte slice.
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, VMError> {
+        SliceReader::parse(slice, |r| Self::decode(r))
+    }
+}
+
+impl Encodable for BurnProof {
+    fn encode<S: Sink>(&self, buf: &mut S) {
+        encoding::write_u64(self.value.qty, buf);
+        encoding::write_bytes(self.value.flv.as_bytes(), buf);
+        encoding::write_bytes(self.qty_blinding.as_bytes(), buf);
+        encoding::write_bytes(self.flv_blinding.as_bytes(), buf);
+        merkle::encode_path(&self.neighbors, buf);
+    }
+
+    fn serialized_length(&self) -> usize {
+        8 + 32 * 3 + 4 + self.neighbors.len() * 32
+    }
+}
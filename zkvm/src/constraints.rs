@@ -5,11 +5,12 @@ use bulletproofs::{r1cs, r1cs::ConstraintSystem, PedersenGens};
 use curve25519_dalek::ristretto::CompressedRistretto;
 use curve25519_dalek::scalar::Scalar;
 use std::iter::FromIterator;
-use std::ops::{Add, Neg};
+use std::ops::{Add, Mul, Neg};
 use subtle::{ConditionallySelectable, ConstantTimeEq};
 
 use crate::encoding;
 use crate::encoding::Encodable;
+use crate::encoding::Sink;
 use crate::errors::VMError;
 use crate::scalar_witness::ScalarWitness;
 
@@ -199,7 +200,7 @@ impl SecretConstraint {
                 let assignment = expr1
                     .eval()
                     .and_then(|x| expr2.eval().map(|y| (x - y).to_scalar()));
-                Ok((expr1.to_r1cs_lc() - expr2.to_r1cs_lc(), assignment))
+                Ok(((expr1 + -expr2).simplify().to_r1cs_lc(), assignment))
             }
             SecretConstraint::And(c1, c2) => {
                 let (a, a_assg) = c1.flatten(cs)?;
@@ -275,7 +276,7 @@ impl SecretConstraint {
 
 impl Encodable for Commitment {
     /// Encodes the commitment as a point.
-    fn encode(&self, buf: &mut Vec<u8>) {
+    fn encode<S: Sink>(&self, buf: &mut S) {
         encoding::write_point(&self.to_point(), buf);
     }
     /// Returns the number of bytes needed to serialize the Commitment.
@@ -293,6 +294,7 @@ impl Commitment {
     }
 
     /// Creates an open commitment with a zero blinding factor.
+    /// The commitment point is `value·B`.
     pub fn unblinded<T: Into<ScalarWitness>>(x: T) -> Self {
         Commitment::Open(Box::new(CommitmentWitness {
             blinding: Scalar::zero(),
@@ -309,6 +311,7 @@ impl Commitment {
     }
 
     /// Creates an open commitment with a specified blinding factor.
+    /// The commitment point is `value·B + blinding·B_blinding`, same as `PedersenGens::commit`.
     pub fn blinded_with_factor<T: Into<ScalarWitness>>(x: T, blinding: Scalar) -> Self {
         Commitment::Open(Box::new(CommitmentWitness {
             blinding,
@@ -316,6 +319,25 @@ impl Commitment {
         }))
     }
 
+    /// Adds `added_blinding` to the blinding factor, keeping the committed value.
+    /// The commitment point becomes `C + added_blinding·B_blinding`.
+    /// Fails with `VMError::InvalidPoint` if a closed commitment is not a valid point.
+    pub fn reblind(&self, added_blinding: Scalar) -> Result<Self, VMError> {
+        match self {
+            Commitment::Closed(x) => {
+                let gens = PedersenGens::default();
+                let point = x.decompress().ok_or(VMError::InvalidPoint)?;
+                Ok(Commitment::Closed(
+                    (point + added_blinding * gens.B_blinding).compress(),
+                ))
+            }
+            Commitment::Open(w) => Ok(Commitment::Open(Box::new(CommitmentWitness {
+                value: w.value,
+                blinding: w.blinding + added_blinding,
+            }))),
+        }
+    }
+
     /// Returns a pair of secrets: the committed scalar or integer, and the blinding factor
     /// TBD: rename to `to_option()`.
     pub fn witness(&self) -> Option<(ScalarWitness, Scalar)> {
@@ -404,6 +426,26 @@ impl Expression {
         }
     }
 
+    /// Combines the terms with the same variable, folding all the constant terms into one,
+    /// and removes the terms with zero coefficients.
+    /// The resulting expression is numerically equivalent to the original one.
+    pub fn simplify(self) -> Self {
+        match self {
+            Expression::Constant(a) => Expression::Constant(a),
+            Expression::LinearCombination(terms, assignment) => {
+                let mut simplified: Vec<(r1cs::Variable, Scalar)> = Vec::with_capacity(terms.len());
+                for (var, coeff) in terms {
+                    match simplified.iter_mut().find(|(v, _)| *v == var) {
+                        Some((_, c)) => *c += coeff,
+                        None => simplified.push((var, coeff)),
+                    }
+                }
+                simplified.retain(|(_, c)| c != &Scalar::zero());
+                Expression::LinearCombination(simplified, assignment)
+            }
+        }
+    }
+
     pub(crate) fn to_r1cs_lc(&self) -> r1cs::LinearCombination {
         match self {
             Expression::Constant(a) => a.to_scalar().into(),
@@ -479,6 +521,55 @@ impl Add for Expression {
     }
 }
 
+impl Add<Scalar> for Expression {
+    type Output = Expression;
+
+    fn add(self, rhs: Scalar) -> Expression {
+        self + Expression::from(rhs)
+    }
+}
+
+impl Add<Expression> for Scalar {
+    type Output = Expression;
+
+    fn add(self, rhs: Expression) -> Expression {
+        Expression::from(self) + rhs
+    }
+}
+
+impl Mul<Scalar> for Expression {
+    type Output = Expression;
+
+    fn mul(self, rhs: Scalar) -> Expression {
+        let rhs = ScalarWitness::Scalar(rhs);
+        match self {
+            Expression::Constant(a) => Expression::Constant(a * rhs),
+            Expression::LinearCombination(mut terms, assignment) => {
+                for (_, n) in terms.iter_mut() {
+                    *n = *n * rhs.to_scalar();
+                }
+                Expression::LinearCombination(terms, assignment.map(|a| a * rhs))
+            }
+        }
+    }
+}
+
+impl Mul<Expression> for Scalar {
+    type Output = Expression;
+
+    fn mul(self, rhs: Expression) -> Expression {
+        rhs * self
+    }
+}
+
+// Upcasting constants into Expression
+
+impl From<Scalar> for Expression {
+    fn from(x: Scalar) -> Self {
+        Expression::constant(x)
+    }
+}
+
 // Upcasting witness/points into Commitment
 
 impl From<CommitmentWitness> for Commitment {
@@ -504,6 +595,44 @@ mod tests {
     use super::*;
     use merlin::Transcript;
 
+    #[test]
+    fn commitment_points() {
+        let gens = PedersenGens::default();
+        let blinding = Scalar::from(42u64);
+
+        let com = Commitment::blinded_with_factor(10u64, blinding);
+        assert_eq!(
+            com.to_point(),
+            gens.commit(Scalar::from(10u64), blinding).compress()
+        );
+        assert_eq!(com.assignment(), Some(ScalarWitness::from(10u64)));
+        assert_eq!(com.witness(), Some((ScalarWitness::from(10u64), blinding)));
+
+        let negative = -ScalarWitness::from(10u64);
+        let com = Commitment::blinded_with_factor(negative, blinding);
+        assert_eq!(
+            com.to_point(),
+            gens.commit(-Scalar::from(10u64), blinding).compress()
+        );
+        assert_eq!(com.assignment(), Some(negative));
+
+        let flv = Scalar::from(0xffu64);
+        let com = Commitment::unblinded(flv);
+        assert_eq!(com.to_point(), (flv * gens.B).compress());
+        assert_eq!(com.assignment(), Some(ScalarWitness::Scalar(flv)));
+
+        let com = Commitment::blinded(10u64);
+        let (_, blinding) = com.witness().unwrap();
+        assert_eq!(
+            com.to_point(),
+            gens.commit(Scalar::from(10u64), blinding).compress()
+        );
+
+        let closed = Commitment::Closed(com.to_point());
+        assert_eq!(closed.assignment(), None);
+        assert_eq!(closed.to_point(), com.to_point());
+    }
+
     #[test]
     fn expression_arithmetic() {
         // const + const => const
@@ -617,6 +746,114 @@ mod tests {
         );
     }
 
+    #[test]
+    fn scalar_promotion() {
+        let a = Scalar::from(3u64);
+        let b = Scalar::from(5u64);
+        assert_eq!(Expression::from(a), Expression::Constant(a.into()));
+        assert_eq!(
+            Expression::from(a) + b,
+            Expression::Constant(Scalar::from(8u64).into())
+        );
+        assert_eq!(
+            b * Expression::from(a),
+            Expression::Constant(Scalar::from(15u64).into())
+        );
+
+        let expr = Expression::LinearCombination(
+            vec![(r1cs::Variable::Committed(0), 2u64.into())],
+            Some(7u64.into()),
+        );
+        assert_eq!(
+            b * expr.clone() + a,
+            Expression::LinearCombination(
+                vec![
+                    (r1cs::Variable::Committed(0), 10u64.into()),
+                    (r1cs::Variable::One(), a)
+                ],
+                Some(Scalar::from(38u64).into())
+            )
+        );
+        assert_eq!(a + expr.clone() * b, b * expr + a);
+    }
+
+    #[test]
+    fn simplify_expression() {
+        let (x0, x1) = (Scalar::from(11u64), Scalar::from(13u64));
+        let eval = |expr: &Expression| match expr {
+            Expression::Constant(a) => a.to_scalar(),
+            Expression::LinearCombination(terms, _) => terms
+                .iter()
+                .map(|(var, coeff)| match var {
+                    r1cs::Variable::Committed(0) => coeff * x0,
+                    r1cs::Variable::Committed(1) => coeff * x1,
+                    r1cs::Variable::One() => *coeff,
+                    _ => unreachable!(),
+                })
+                .sum(),
+        };
+
+        // 2·x0 + 3 + x1 + 5·x0 + 4 - x1
+        let expr = Expression::LinearCombination(
+            vec![
+                (r1cs::Variable::Committed(0), 2u64.into()),
+                (r1cs::Variable::One(), 3u64.into()),
+                (r1cs::Variable::Committed(1), 1u64.into()),
+                (r1cs::Variable::Committed(0), 5u64.into()),
+                (r1cs::Variable::One(), 4u64.into()),
+                (r1cs::Variable::Committed(1), -Scalar::one()),
+            ],
+            Some(Scalar::from(84u64).into()),
+        );
+        let simplified = expr.clone().simplify();
+        assert_eq!(
+            simplified,
+            Expression::LinearCombination(
+                vec![
+                    (r1cs::Variable::Committed(0), 7u64.into()),
+                    (r1cs::Variable::One(), 7u64.into()),
+                ],
+                Some(Scalar::from(84u64).into())
+            )
+        );
+        assert_eq!(eval(&simplified), eval(&expr));
+        assert_eq!(eval(&expr), Scalar::from(84u64));
+
+        let constant = Expression::constant(5u64);
+        assert_eq!(constant.clone().simplify(), constant);
+    }
+
+    #[test]
+    fn scalar_promotion_in_proof() {
+        use bulletproofs::r1cs::{Prover, Verifier};
+        use bulletproofs::BulletproofGens;
+
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(64, 1);
+        let x = Scalar::from(4u64);
+        let (a, b) = (Scalar::from(3u64), Scalar::from(5u64));
+
+        // Proves that 3*x + 5 == 17 for a committed x.
+        let constraint = |var: r1cs::Variable, assignment: Option<ScalarWitness>| {
+            let expr = Expression::LinearCombination(vec![(var, Scalar::one())], assignment);
+            Constraint::eq(a * expr + b, Expression::from(Scalar::from(17u64)))
+        };
+
+        let (proof, com) = {
+            let mut transcript = Transcript::new(b"ScalarPromotionTest");
+            let mut prover = Prover::new(&pc_gens, &mut transcript);
+            let (com, var) = prover.commit(x, Scalar::from(10u64));
+            constraint(var, Some(x.into())).verify(&mut prover).unwrap();
+            (prover.prove(&bp_gens).unwrap(), com)
+        };
+
+        let mut transcript = Transcript::new(b"ScalarPromotionTest");
+        let mut verifier = Verifier::new(&mut transcript);
+        let var = verifier.commit(com);
+        constraint(var, None).verify(&mut verifier).unwrap();
+        assert!(verifier.verify(&proof, &pc_gens, &bp_gens).is_ok());
+    }
+
     #[test]
     fn constraints_arithmetic() {
         // eq(const, const) => cleartext(true)
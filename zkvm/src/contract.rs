@@ -1,7 +1,8 @@
-use serde::{self, Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 use crate::constraints::Commitment;
-use crate::encoding::{self, Encodable, SliceReader};
+use crate::encoding::{self, Encodable, Sink, SliceReader};
 use crate::errors::VMError;
 use crate::merkle::MerkleItem;
 use crate::predicate::Predicate;
@@ -19,13 +20,15 @@ pub const PROG_TYPE: u8 = 0x01;
 pub const VALUE_TYPE: u8 = 0x02;
 
 /// A unique identifier for an anchor
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
-#[serde(transparent)]
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Anchor(pub [u8; 32]);
 
 /// A unique identifier for a contract.
-#[derive(Copy, Clone, Eq, Hash, Debug, PartialEq, Default, Serialize, Deserialize)]
-#[serde(transparent)]
+#[derive(Copy, Clone, Eq, Hash, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct ContractID(pub [u8; 32]);
 
 /// A ZkVM contract that holds a _payload_ (a list of portable items) protected by a _predicate_.
@@ -56,7 +59,7 @@ pub enum PortableItem {
 
 impl Encodable for Contract {
     /// Serializes the contract to a byte array
-    fn encode(&self, buf: &mut Vec<u8>) {
+    fn encode<S: Sink>(&self, buf: &mut S) {
         encoding::write_bytes(&self.anchor.0, buf);
         encoding::write_point(&self.predicate.to_point(), buf);
         encoding::write_u32(self.payload.len() as u32, buf);
@@ -74,7 +77,11 @@ impl Encodable for Contract {
     }
 }
 impl Contract {
-    /// Returns the contract's ID
+    /// Returns the contract's ID: a hash of the contract's encoding
+    /// (see `Encodable for Contract`) in a `ZkVM.contractid` transcript.
+    /// The ID depends only on the encoded form, so the prover (holding the witness data)
+    /// and the verifier (holding the opaque encoding) compute the same ID.
+    /// This is the ID that the `input` instruction records in the transaction log.
     pub fn id(&self) -> ContractID {
         let buf = self.encode_to_vec();
         let mut t = Transcript::new(b"ZkVM.contractid");
@@ -84,6 +91,23 @@ impl Contract {
         ContractID(id)
     }
 
+    /// Writes the public view of the contract for explorers and other third parties:
+    /// the anchor, the predicate and the payload with values as closed commitments.
+    /// Witness data (keys, openings of the commitments, `alloc` assignments in programs)
+    /// is never written, so a contract with open commitments has the same public encoding
+    /// as its closed equivalent. The contract ID is a hash of this encoding.
+    pub fn encode_public(&self, buf: &mut Vec<u8>) {
+        self.encode(buf);
+    }
+
+    /// Parses the public view of a contract written by `encode_public`.
+    /// The resulting contract has an opaque predicate and closed value commitments.
+    /// Fails with `VMError::FormatError` if the data is malformed
+    /// and with `VMError::TrailingBytes` if it has extra bytes after the contract.
+    pub fn decode_public(data: &[u8]) -> Result<Self, VMError> {
+        SliceReader::parse(data, |r| Self::decode(r))
+    }
+
     /// Parses a contract from an output object
     pub fn decode<'a>(reader: &mut SliceReader<'a>) -> Result<Self, VMError> {
         //    Output  =  Anchor  ||  Predicate  ||  LE32(k)  ||  Item[0]  || ... ||  Item[k-1]
@@ -96,13 +120,9 @@ impl Contract {
 
         let anchor = Anchor(reader.read_u8x32()?);
         let predicate = Predicate::Opaque(reader.read_point()?);
-        let k = reader.read_size()?;
-
         // sanity check: avoid allocating unreasonably more memory
         // just because an untrusted length prefix says so.
-        if k > reader.len() {
-            return Err(VMError::FormatError);
-        }
+        let k = reader.read_size_bounded(reader.len())?;
         let mut payload: Vec<PortableItem> = Vec::with_capacity(k);
         for _ in 0..k {
             payload.push(PortableItem::decode(reader)?);
@@ -159,7 +179,7 @@ impl ContractID {
 }
 
 impl Encodable for PortableItem {
-    fn encode(&self, buf: &mut Vec<u8>) {
+    fn encode<S: Sink>(&self, buf: &mut S) {
         match self {
             // String = 0x00 || LE32(len) || <bytes>
             PortableItem::String(d) => {
@@ -195,12 +215,12 @@ impl PortableItem {
     fn decode<'a>(output: &mut SliceReader<'a>) -> Result<Self, VMError> {
         match output.read_u8()? {
             STRING_TYPE => {
-                let len = output.read_size()?;
+                let len = output.read_size_bounded(output.len())?;
                 let bytes = output.read_bytes(len)?;
                 Ok(PortableItem::String(String::Opaque(bytes.to_vec())))
             }
             PROG_TYPE => {
-                let len = output.read_size()?;
+                let len = output.read_size_bounded(output.len())?;
                 let bytes = output.read_bytes(len)?;
                 Ok(PortableItem::Program(ProgramItem::Bytecode(bytes.to_vec())))
             }
@@ -214,8 +234,206 @@ impl PortableItem {
     }
 }
 
+impl MerkleItem for Contract {
+    /// Commits the encoded contract under a `contract` tag,
+    /// so a contract never collides with a program or a value in the same tree.
+    fn commit(&self, t: &mut Transcript) {
+        t.append_message(b"item", b"contract");
+        t.append_message(b"contract.output", &self.encode_to_vec());
+    }
+}
+
 impl MerkleItem for ContractID {
     fn commit(&self, t: &mut Transcript) {
         t.append_message(b"contract", self.as_bytes());
     }
 }
+
+/// Sorts the outputs by their contract IDs (bytewise), so that wallets
+/// building the same logical transaction produce the same program and txid.
+/// Apply this to the outputs before emitting them into the transaction program.
+pub fn sort_outputs(outputs: &mut [Contract]) {
+    outputs.sort_by_cached_key(|contract| contract.id().0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Program;
+    use curve25519_dalek::scalar::Scalar;
+    use musig::VerificationKey;
+
+    fn make_contract() -> Contract {
+        Contract {
+            predicate: Predicate::Key(VerificationKey::from_secret(&Scalar::from(7u64))),
+            payload: vec![
+                PortableItem::String(String::Opaque(b"metadata".to_vec())),
+                PortableItem::Program(ProgramItem::Program(Program::build(|p| p.drop()))),
+                PortableItem::Value(Value {
+                    qty: Commitment::blinded(10u64),
+                    flv: Commitment::blinded(Scalar::from(1u64)),
+                }),
+            ],
+            anchor: Anchor::from_raw_bytes([1u8; 32]),
+        }
+    }
+
+    /// Same as `make_contract`, but with an unblinded value of the given quantity,
+    /// so that the encoding of the contract is deterministic.
+    fn make_unblinded_contract(qty: u64) -> Contract {
+        let mut contract = make_contract();
+        contract.payload[2] = PortableItem::Value(Value {
+            qty: Commitment::unblinded(qty),
+            flv: Commitment::unblinded(Scalar::from(1u64)),
+        });
+        contract
+    }
+
+    #[test]
+    fn stable_contract_id() {
+        // Prover's view: contract with all the witness data.
+        let contract = make_contract();
+        assert_eq!(contract.id(), contract.id());
+        assert_eq!(contract.id(), contract.clone().id());
+
+        // Verifier's view: contract decoded from its opaque encoding.
+        let bytes = contract.encode_to_vec();
+        let decoded = SliceReader::parse(&bytes, |r| Contract::decode(r)).unwrap();
+        assert_eq!(contract.id(), decoded.id());
+        assert_eq!(bytes, decoded.encode_to_vec());
+
+        // A different anchor yields a different ID.
+        let mut other = make_contract();
+        other.anchor = other.anchor.ratchet();
+        assert_ne!(contract.id(), other.id());
+    }
+
+    #[test]
+    fn contracts_merkle_root() {
+        use crate::merkle::MerkleTree;
+
+        let contracts = vec![
+            make_unblinded_contract(10),
+            make_unblinded_contract(20),
+            make_unblinded_contract(30),
+        ];
+        let root = MerkleTree::root(b"ZkVM.outputs", &contracts);
+        assert_eq!(
+            hex::encode(root),
+            "1413c37093020f1321b7f5578caac509c1fcd88445fc5c95d05712dd5318d906"
+        );
+
+        // Verifier's view of the same contracts yields the same root.
+        let decoded = contracts
+            .iter()
+            .map(|c| Contract::decode_public(&c.encode_to_vec()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(root, MerkleTree::root(b"ZkVM.outputs", &decoded));
+
+        // Swapping a value changes the root.
+        let mut swapped = contracts.clone();
+        swapped[1].payload[2] = PortableItem::Value(Value {
+            qty: Commitment::blinded(11u64),
+            flv: Commitment::blinded(Scalar::from(1u64)),
+        });
+        assert_ne!(root, MerkleTree::root(b"ZkVM.outputs", &swapped));
+
+        // Values and contracts are tagged differently.
+        let value = match &contracts[0].payload[2] {
+            PortableItem::Value(v) => v.clone(),
+            _ => unreachable!(),
+        };
+        assert_ne!(
+            MerkleTree::root(b"ZkVM.outputs", &[value.clone()]),
+            MerkleTree::root(b"ZkVM.outputs", &contracts[..1])
+        );
+        assert_eq!(
+            MerkleTree::root(b"ZkVM.outputs", &[value.clone()]),
+            MerkleTree::root(b"ZkVM.outputs", &[value])
+        );
+    }
+
+    #[test]
+    fn public_encoding() {
+        let contract = make_contract();
+        let closed = Contract {
+            predicate: Predicate::Opaque(contract.predicate.to_point()),
+            payload: contract
+                .payload
+                .iter()
+                .map(|item| match item {
+                    PortableItem::Value(v) => PortableItem::Value(Value {
+                        qty: Commitment::Closed(v.qty.to_point()),
+                        flv: Commitment::Closed(v.flv.to_point()),
+                    }),
+                    PortableItem::Program(p) => {
+                        PortableItem::Program(ProgramItem::Bytecode(p.to_bytes()))
+                    }
+                    item => item.clone(),
+                })
+                .collect(),
+            anchor: contract.anchor,
+        };
+
+        let mut public = Vec::new();
+        contract.encode_public(&mut public);
+        let mut closed_public = Vec::new();
+        closed.encode_public(&mut closed_public);
+        assert_eq!(public, closed_public);
+        assert_eq!(public.len(), contract.serialized_length());
+
+        let decoded = Contract::decode_public(&public).unwrap();
+        assert_eq!(decoded.id(), contract.id());
+        let mut reencoded = Vec::new();
+        decoded.encode_public(&mut reencoded);
+        assert_eq!(reencoded, public);
+
+        public.push(0);
+        assert_eq!(
+            Contract::decode_public(&public).unwrap_err(),
+            VMError::TrailingBytes
+        );
+    }
+
+    #[test]
+    fn versioned_encoding() {
+        let contract = make_contract();
+        let mut bytes = Vec::new();
+        contract.encode_versioned(&mut bytes);
+        assert_eq!(bytes[0], 0);
+        assert_eq!(&bytes[1..], &contract.encode_to_vec()[..]);
+
+        let decoded = SliceReader::parse_versioned(&bytes, |r| Contract::decode(r)).unwrap();
+        assert_eq!(decoded.id(), contract.id());
+
+        bytes[0] = 255;
+        assert_eq!(
+            SliceReader::parse_versioned(&bytes, |r| Contract::decode(r)).unwrap_err(),
+            VMError::UnsupportedVersion(255)
+        );
+    }
+
+    #[test]
+    fn canonical_output_order() {
+        let mut anchor = Anchor::from_raw_bytes([0u8; 32]);
+        let outputs: Vec<Contract> = (0..5)
+            .map(|_| {
+                anchor = anchor.ratchet();
+                let mut contract = make_contract();
+                contract.anchor = anchor;
+                contract
+            })
+            .collect();
+
+        let mut a = outputs.clone();
+        a.reverse();
+        let mut b = outputs.clone();
+        b.rotate_left(2);
+
+        sort_outputs(&mut a);
+        sort_outputs(&mut b);
+        let ids = |v: &Vec<Contract>| v.iter().map(|c| c.id()).collect::<Vec<_>>();
+        assert_eq!(ids(&a), ids(&b));
+        assert!(ids(&a).windows(2).all(|w| w[0].0 < w[1].0));
+    }
+}
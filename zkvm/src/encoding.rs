@@ -4,9 +4,16 @@
 use byteorder::{ByteOrder, LittleEndian};
 use curve25519_dalek::ristretto::CompressedRistretto;
 use curve25519_dalek::scalar::Scalar;
+use std::io;
 
 use crate::errors::VMError;
 
+/// Current version of the encoding, prepended by `Encodable::encode_versioned`.
+pub(crate) const ENCODING_VERSION: u8 = 0;
+
+/// Bounds-checked reader over a byte slice.
+/// Every read advances the internal offset; reading past the end of the slice
+/// fails with `VMError::FormatError` instead of panicking.
 #[derive(Debug)]
 pub struct SliceReader<'a> {
     whole: &'a [u8],
@@ -23,10 +30,19 @@ impl<'a> SliceReader<'a> {
         }
     }
 
+    /// Returns the number of bytes remaining to be read.
     pub fn len(&self) -> usize {
         self.end - self.start
     }
 
+    /// Returns true if there are no more bytes to read.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Parses the entire slice using the provided closure.
+    /// Fails with `VMError::TrailingBytes` if the closure did not consume all the bytes,
+    /// or with the closure's error if the data is malformed.
     pub fn parse<F, T>(data: &'a [u8], parse_fn: F) -> Result<T, VMError>
     where
         F: FnOnce(&mut Self) -> Result<T, VMError>,
@@ -39,6 +55,23 @@ impl<'a> SliceReader<'a> {
         Ok(result)
     }
 
+    /// Parses the entire slice prefixed with a version tag (see `Encodable::encode_versioned`)
+    /// using the provided closure.
+    /// Fails with `VMError::UnsupportedVersion` if the version tag is unknown.
+    pub(crate) fn parse_versioned<F, T>(data: &'a [u8], parse_fn: F) -> Result<T, VMError>
+    where
+        F: FnOnce(&mut Self) -> Result<T, VMError>,
+    {
+        Self::parse(data, |r| {
+            let version = r.read_u8()?;
+            if version != ENCODING_VERSION {
+                return Err(VMError::UnsupportedVersion(version));
+            }
+            parse_fn(r)
+        })
+    }
+
+    /// Skips all the remaining bytes and returns their number.
     pub fn skip_trailing_bytes(&mut self) -> usize {
         let trailing = self.end - self.start;
         self.start = self.end;
@@ -47,6 +80,7 @@ impl<'a> SliceReader<'a> {
 
     /// Returns a slice of the first `prefix_size` of bytes and advances
     /// the internal offset.
+    /// Fails with `VMError::FormatError` if fewer than `prefix_size` bytes remain.
     pub fn read_bytes(&mut self, prefix_size: usize) -> Result<&[u8], VMError> {
         if prefix_size > self.len() {
             return Err(VMError::FormatError);
@@ -56,29 +90,49 @@ impl<'a> SliceReader<'a> {
         Ok(prefix)
     }
 
+    /// Reads a single byte.
+    /// Fails with `VMError::FormatError` if no bytes remain.
     pub fn read_u8(&mut self) -> Result<u8, VMError> {
         let bytes = self.read_bytes(1)?;
         Ok(bytes[0])
     }
 
+    /// Reads a LE32-encoded integer.
+    /// Fails with `VMError::FormatError` if fewer than 4 bytes remain.
     pub fn read_u32(&mut self) -> Result<u32, VMError> {
         let bytes = self.read_bytes(4)?;
         let x = LittleEndian::read_u32(&bytes);
         Ok(x)
     }
 
+    /// Reads a LE64-encoded integer.
+    /// Fails with `VMError::FormatError` if fewer than 8 bytes remain.
     pub fn read_u64(&mut self) -> Result<u64, VMError> {
         let bytes = self.read_bytes(8)?;
         let x = LittleEndian::read_u64(&bytes);
         Ok(x)
     }
 
-    // returns a 32-byte "size" type used in ZkVM
+    /// Reads a LE32-encoded "size" type used in ZkVM.
+    /// Fails with `VMError::FormatError` if fewer than 4 bytes remain.
     pub fn read_size(&mut self) -> Result<usize, VMError> {
         let n = self.read_u32()?;
         Ok(n as usize)
     }
 
+    /// Reads a LE32-encoded size of a length-prefixed collection (e.g. a byte string).
+    /// Fails with `VMError::FormatError` if the size exceeds `max`, or the number of
+    /// the remaining bytes, since every element of a collection occupies at least one byte.
+    pub fn read_size_bounded(&mut self, max: usize) -> Result<usize, VMError> {
+        let n = self.read_size()?;
+        if n > max || n > self.len() {
+            return Err(VMError::FormatError);
+        }
+        Ok(n)
+    }
+
+    /// Reads a 32-byte array.
+    /// Fails with `VMError::FormatError` if fewer than 32 bytes remain.
     pub fn read_u8x32(&mut self) -> Result<[u8; 32], VMError> {
         let mut buf = [0u8; 32];
         let bytes = self.read_bytes(32)?;
@@ -86,6 +140,8 @@ impl<'a> SliceReader<'a> {
         Ok(buf)
     }
 
+    /// Reads a 64-byte array.
+    /// Fails with `VMError::FormatError` if fewer than 64 bytes remain.
     pub fn read_u8x64(&mut self) -> Result<[u8; 64], VMError> {
         let mut buf = [0u8; 64];
         let bytes = self.read_bytes(64)?;
@@ -93,11 +149,17 @@ impl<'a> SliceReader<'a> {
         Ok(buf)
     }
 
+    /// Reads a compressed Ristretto point.
+    /// The point is not decompressed, so its validity is not checked.
+    /// Fails with `VMError::FormatError` if fewer than 32 bytes remain.
     pub fn read_point(&mut self) -> Result<CompressedRistretto, VMError> {
         let buf = self.read_u8x32()?;
         Ok(CompressedRistretto(buf))
     }
 
+    /// Reads a scalar in canonical encoding.
+    /// Fails with `VMError::FormatError` if fewer than 32 bytes remain
+    /// or the scalar is not canonically encoded.
     pub fn read_scalar(&mut self) -> Result<Scalar, VMError> {
         let buf = self.read_u8x32()?;
         Scalar::from_canonical_bytes(buf).ok_or(VMError::FormatError)
@@ -105,45 +167,74 @@ impl<'a> SliceReader<'a> {
 }
 
 // Writing API
-// This currently writes into the Vec, but later can be changed to support Arenas to minimize allocations
+// Writes go into a `Sink`: a `Vec<u8>`, or an `io::Write` passed to `Encodable::encode_to_writer`.
+
+/// Destination of the encoded bytes.
+/// Pushing bytes is infallible: a sink that can fail (see `IoSink`) keeps the error
+/// and reports it when the encoding is complete.
+pub(crate) trait Sink {
+    /// Appends the bytes to the sink.
+    fn push_bytes(&mut self, bytes: &[u8]);
+}
+
+impl Sink for Vec<u8> {
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+}
+
+/// Sink passing the bytes through to an `io::Write`.
+/// Keeps the first error and drops all the bytes pushed after it.
+struct IoSink<'a, W: io::Write> {
+    writer: &'a mut W,
+    result: io::Result<()>,
+}
+
+impl<'a, W: io::Write> Sink for IoSink<'a, W> {
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        if self.result.is_ok() {
+            self.result = self.writer.write_all(bytes);
+        }
+    }
+}
 
 // Writes a single byte
-pub(crate) fn write_u8<'a>(x: u8, target: &mut Vec<u8>) {
-    target.push(x);
+pub(crate) fn write_u8<S: Sink>(x: u8, target: &mut S) {
+    target.push_bytes(&[x]);
 }
 
 // Writes a LE32-encoded integer
-pub(crate) fn write_u32<'a>(x: u32, target: &mut Vec<u8>) {
+pub(crate) fn write_u32<S: Sink>(x: u32, target: &mut S) {
     let mut buf = [0u8; 4];
     LittleEndian::write_u32(&mut buf, x);
-    target.extend_from_slice(&buf);
+    target.push_bytes(&buf);
 }
 
 // Writes a LE64-encoded integer
-pub(crate) fn write_u64<'a>(x: u64, target: &mut Vec<u8>) {
+pub(crate) fn write_u64<S: Sink>(x: u64, target: &mut S) {
     let mut buf = [0u8; 8];
     LittleEndian::write_u64(&mut buf, x);
-    target.extend_from_slice(&buf);
+    target.push_bytes(&buf);
 }
 
 // Writes a usize as a LE32-encoded integer
-pub(crate) fn write_size<'a>(x: usize, target: &mut Vec<u8>) {
+pub(crate) fn write_size<S: Sink>(x: usize, target: &mut S) {
     write_u32(x as u32, target);
 }
 
 /// Writes a 32-byte array and returns the subsequent slice
-pub(crate) fn write_bytes(x: &[u8], target: &mut Vec<u8>) {
-    target.extend_from_slice(&x);
+pub(crate) fn write_bytes<S: Sink>(x: &[u8], target: &mut S) {
+    target.push_bytes(&x);
 }
 
 /// Writes a compressed point
-pub(crate) fn write_point(x: &CompressedRistretto, target: &mut Vec<u8>) {
+pub(crate) fn write_point<S: Sink>(x: &CompressedRistretto, target: &mut S) {
     write_bytes(x.as_bytes(), target);
 }
 // Encodable provides a set of additional methods to work with bytes
 pub(crate) trait Encodable {
-    ///Encodes receiver into bytes appending them to a provided buffer.
-    fn encode(&self, buf: &mut Vec<u8>);
+    ///Encodes receiver into bytes appending them to a provided sink.
+    fn encode<S: Sink>(&self, buf: &mut S);
     ///Returns precise length in bytes for the serialized representation of the receiver.
     fn serialized_length(&self) -> usize;
     /// Encodes the receiver into a newly allocated vector of bytes.
@@ -152,4 +243,182 @@ pub(crate) trait Encodable {
         self.encode(&mut buf);
         buf
     }
+    /// Encodes the receiver directly into the provided writer, e.g. a file or a socket,
+    /// without buffering the whole encoding.
+    /// Fails with the first error returned by the writer.
+    fn encode_to_writer<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut sink = IoSink {
+            writer: w,
+            result: Ok(()),
+        };
+        self.encode(&mut sink);
+        sink.result
+    }
+    /// Encodes the receiver prefixed with the version tag `ENCODING_VERSION`.
+    /// Use `SliceReader::parse_versioned` to decode.
+    fn encode_versioned<S: Sink>(&self, buf: &mut S) {
+        write_u8(ENCODING_VERSION, buf);
+        self.encode(buf);
+    }
+}
+
+// Encodings of the primitive types used as fields by `#[derive(Encodable)]`.
+
+impl Encodable for u8 {
+    fn encode<S: Sink>(&self, buf: &mut S) {
+        write_u8(*self, buf);
+    }
+    fn serialized_length(&self) -> usize {
+        1
+    }
+}
+
+impl Encodable for u32 {
+    fn encode<S: Sink>(&self, buf: &mut S) {
+        write_u32(*self, buf);
+    }
+    fn serialized_length(&self) -> usize {
+        4
+    }
+}
+
+impl Encodable for u64 {
+    fn encode<S: Sink>(&self, buf: &mut S) {
+        write_u64(*self, buf);
+    }
+    fn serialized_length(&self) -> usize {
+        8
+    }
+}
+
+impl Encodable for [u8; 32] {
+    fn encode<S: Sink>(&self, buf: &mut S) {
+        write_bytes(&self[..], buf);
+    }
+    fn serialized_length(&self) -> usize {
+        32
+    }
+}
+
+impl Encodable for [u8; 64] {
+    fn encode<S: Sink>(&self, buf: &mut S) {
+        write_bytes(&self[..], buf);
+    }
+    fn serialized_length(&self) -> usize {
+        64
+    }
+}
+
+/// Vectors are encoded with a LE32 length prefix followed by the items.
+impl<T: Encodable> Encodable for Vec<T> {
+    fn encode<S: Sink>(&self, buf: &mut S) {
+        write_size(self.len(), buf);
+        for item in self.iter() {
+            item.encode(buf);
+        }
+    }
+    fn serialized_length(&self) -> usize {
+        4 + self
+            .iter()
+            .map(|item| item.serialized_length())
+            .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_past_the_end() {
+        let bytes = [0u8; 7];
+        let mut r = SliceReader::new(&bytes);
+        assert_eq!(r.read_u64(), Err(VMError::FormatError));
+        // failed read does not advance the offset
+        assert_eq!(r.len(), 7);
+        assert_eq!(r.read_bytes(8), Err(VMError::FormatError));
+        assert_eq!(r.read_bytes(7).unwrap(), &bytes[..]);
+        assert_eq!(r.read_u8(), Err(VMError::FormatError));
+
+        let bytes = [0u8; 31];
+        assert_eq!(
+            SliceReader::parse(&bytes, |r| r.read_point()).unwrap_err(),
+            VMError::FormatError
+        );
+        assert_eq!(
+            SliceReader::parse(&bytes, |r| r.read_scalar()).unwrap_err(),
+            VMError::FormatError
+        );
+    }
+
+    #[test]
+    fn non_canonical_scalar() {
+        let bytes = [0xffu8; 32];
+        assert_eq!(
+            SliceReader::parse(&bytes, |r| r.read_scalar()).unwrap_err(),
+            VMError::FormatError
+        );
+    }
+
+    #[test]
+    fn bounded_size() {
+        let mut bytes = Vec::new();
+        write_size(3, &mut bytes);
+        bytes.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(
+            SliceReader::parse(&bytes, |r| {
+                let n = r.read_size_bounded(3)?;
+                r.read_bytes(n).map(|b| b.to_vec())
+            }),
+            Ok(vec![1, 2, 3])
+        );
+
+        // Size exceeds the bound
+        assert_eq!(
+            SliceReader::parse(&bytes, |r| r.read_size_bounded(2)).unwrap_err(),
+            VMError::FormatError
+        );
+
+        // Truncated buffer
+        assert_eq!(
+            SliceReader::parse(&bytes[..6], |r| r.read_size_bounded(3)).unwrap_err(),
+            VMError::FormatError
+        );
+
+        // Oversized length
+        let mut bytes = Vec::new();
+        write_u32(std::u32::MAX, &mut bytes);
+        assert_eq!(
+            SliceReader::parse(&bytes, |r| r.read_size_bounded(std::usize::MAX)).unwrap_err(),
+            VMError::FormatError
+        );
+    }
+
+    #[test]
+    fn encode_to_slice_writer() {
+        let items = vec![1u64, 2, 3];
+        let mut bytes = [0u8; 4 + 3 * 8];
+        items.encode_to_writer(&mut &mut bytes[..]).unwrap();
+        assert_eq!(&bytes[..], &items.encode_to_vec()[..]);
+
+        // The writer fails once it runs out of space.
+        let mut bytes = [0u8; 4 + 2 * 8];
+        assert_eq!(
+            items
+                .encode_to_writer(&mut &mut bytes[..])
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::WriteZero
+        );
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let bytes = [1u8, 0, 0, 0, 0, 0, 0, 0, 0xff];
+        assert_eq!(
+            SliceReader::parse(&bytes, |r| r.read_u64()),
+            Err(VMError::TrailingBytes)
+        );
+        assert_eq!(SliceReader::parse(&bytes[..8], |r| r.read_u64()), Ok(1));
+    }
 }
@@ -20,33 +20,42 @@ pub enum VMError {
     #[fail(display = "Invalid trailing bytes.")]
     TrailingBytes,
 
+    /// This error occurs when the encoding version tag is not supported.
+    #[fail(display = "Unsupported encoding version: {}.", _0)]
+    UnsupportedVersion(u8),
+
     /// This error occurs when data is malformed
     #[fail(display = "Transaction version does not permit extension instructions.")]
     ExtensionsNotAllowed,
 
     /// This error occurs when an instruction requires a copyable type, but a linear type is encountered.
-    #[fail(display = "Item is not a copyable type.")]
-    TypeNotCopyable,
+    #[fail(display = "Expected a copyable type, found {}.", _0)]
+    TypeNotCopyable(&'static str),
 
     /// This error occurs when an instruction requires a portable type, but a non-portable type is encountered.
-    #[fail(display = "Item is not a portable type.")]
-    TypeNotPortable,
+    #[fail(display = "Expected a portable type, found {}.", _0)]
+    TypeNotPortable(&'static str),
+
+    /// This error occurs when an item is cloned with `Item::try_clone`,
+    /// but its type is neither copyable nor portable.
+    #[fail(display = "Expected a copyable or portable type, found {}.", _0)]
+    TypeNotCloneable(&'static str),
 
     /// This error occurs when an instruction requires a string.
-    #[fail(display = "Item is not a string.")]
-    TypeNotString,
+    #[fail(display = "Expected a string, found {}.", _0)]
+    TypeNotString(&'static str),
 
     /// This error occurs when an instruction requires a contract type.
-    #[fail(display = "Item is not a contract.")]
-    TypeNotContract,
+    #[fail(display = "Expected a contract, found {}.", _0)]
+    TypeNotContract(&'static str),
 
     /// This error occurs when an instruction requires a variable type.
-    #[fail(display = "Item is not a variable.")]
-    TypeNotVariable,
+    #[fail(display = "Expected a variable, found {}.", _0)]
+    TypeNotVariable(&'static str),
 
     /// This error occurs when an instruction requires an expression type.
-    #[fail(display = "Item is not an expression.")]
-    TypeNotExpression,
+    #[fail(display = "Expected an expression, found {}.", _0)]
+    TypeNotExpression(&'static str),
 
     /// This error occurs when an instruction requires a predicate string.
     #[fail(display = "Item is not a predicate.")]
@@ -65,16 +74,16 @@ pub enum VMError {
     TypeNotCallProof,
 
     /// This error occurs when an instruction requires a constraint type.
-    #[fail(display = "Item is not a constraint.")]
-    TypeNotConstraint,
+    #[fail(display = "Expected a constraint, found {}.", _0)]
+    TypeNotConstraint(&'static str),
 
     /// This error occurs when an instruction requires a scalar string.
     #[fail(display = "Item is not a scalar.")]
     TypeNotScalar,
 
     /// This error occurs when an instruction requires a program item.
-    #[fail(display = "Item is not a program item.")]
-    TypeNotProgramItem,
+    #[fail(display = "Expected a program item, found {}.", _0)]
+    TypeNotProgramItem(&'static str),
 
     /// This error occurs when an instruction expects a predicate tree type.
     #[fail(display = "Item is not a predicate tree.")]
@@ -97,22 +106,67 @@ pub enum VMError {
     InconsistentWitness,
 
     /// This error occurs when an instruction requires a value type.
-    #[fail(display = "Item is not a value.")]
-    TypeNotValue,
+    #[fail(display = "Expected a value, found {}.", _0)]
+    TypeNotValue(&'static str),
 
     /// This error occurs when an instruction requires a value or a wide value.
-    #[fail(display = "Item is not a wide value.")]
-    TypeNotWideValue,
+    #[fail(display = "Expected a wide value, found {}.", _0)]
+    TypeNotWideValue(&'static str),
+
+    /// This error occurs when the transaction's mintime is greater than its maxtime.
+    #[fail(display = "Transaction time bounds are inconsistent")]
+    InvalidTimeBounds,
+
+    /// This error occurs when the total fee declared in the transaction overflows 64 bits.
+    #[fail(display = "Total fee is too high")]
+    FeeTooHigh,
+
+    /// This error occurs when a program exceeds `MAX_PROGRAM_BYTES` in size.
+    #[fail(display = "Program is too large")]
+    ProgramTooLarge,
+
+    /// This error occurs when a `push` instruction declares more immediate data than allowed.
+    #[fail(display = "Push data is too large")]
+    PushTooLarge,
+
+    /// This error occurs when programs are nested deeper than `MAX_NESTING_DEPTH`.
+    #[fail(display = "Programs are nested too deep")]
+    NestingTooDeep,
 
     /// This error occurs when VM does not have enough items on the stack
-    #[fail(display = "Stack does not have enough items")]
-    StackUnderflow,
+    #[fail(
+        display = "Stack does not have enough items: requested {}, available {}",
+        requested, available
+    )]
+    StackUnderflow {
+        /// Number of items required by the instruction.
+        requested: usize,
+        /// Number of items on the stack.
+        available: usize,
+    },
+
+    /// This error occurs when `Program::verify_structure` finds an instruction
+    /// that would not have enough items on the stack
+    #[fail(
+        display = "Instruction {} requires {} items on the stack, but only {} are available",
+        index, requested, available
+    )]
+    StackUnderflowAt {
+        /// Index of the instruction in the program.
+        index: usize,
+        /// Number of items required by the instruction.
+        requested: usize,
+        /// Number of items on the stack.
+        available: usize,
+    },
 
     /// This error occurs when VM is left with some items on the stack
     #[fail(display = "Stack is not cleared by the program")]
     StackNotClean,
 
-    /// This error occurs when VM's anchor remains unset.
+    /// This error occurs when VM's anchor remains unset: a contract is created
+    /// before any `input` provided an anchor, or the transaction spends no inputs at all.
+    /// Anchors make every contract ID unique and prevent replaying the transaction.
     #[fail(display = "VM anchor is not set via `input`")]
     AnchorMissing,
 
@@ -131,6 +185,39 @@ pub enum VMError {
     #[fail(display = "R1CS proof is invalid")]
     InvalidR1CSProof,
 
+    /// This error occurs when the Bulletproofs generators used to create or verify
+    /// the R1CS proof do not have enough capacity for the transaction's constraint system.
+    #[fail(
+        display = "Constraint system requires at least {} multipliers, but the generators support only {}",
+        needed, available
+    )]
+    InvalidGenerators {
+        /// Padded multiplier count: the number of multipliers allocated before the proof
+        /// is created or verified, rounded up to the next power of two as the generators require.
+        /// Multipliers allocated while the proof is created (by `cloak` shuffles
+        /// and secret constraints) are not known at that point, so the constraint system
+        /// may require more.
+        needed: usize,
+        /// Capacity of the Bulletproofs generators.
+        available: usize,
+    },
+
+    /// This error occurs when the `cloak` instructions of the program alone require
+    /// more R1CS multipliers than the Bulletproofs generators can accommodate.
+    /// The prover checks this before executing the program.
+    #[fail(
+        display = "Cloak requires at least {} multipliers, but the generators support only {}",
+        needed, available
+    )]
+    InsufficientGens {
+        /// Padded multiplier count: the number of multipliers allocated by the `cloak`
+        /// instructions of the program (see `Program::cloak_multiplier_count`),
+        /// rounded up to the next power of two as the generators require.
+        needed: usize,
+        /// Capacity of the Bulletproofs generators.
+        available: usize,
+    },
+
     /// This error occurs when R1CS gadget reports and error due to inconsistent input
     #[fail(display = "R1CS detected inconsistent input")]
     R1CSInconsistency,
@@ -166,4 +253,23 @@ pub enum VMError {
     /// This error occurs when a false cleartext constraint is verified.
     #[fail(display = "Cleartext constraint is false")]
     CleartextConstraintFalse,
+
+    /// This error occurs when the transaction inputs and outputs do not balance per flavor.
+    #[fail(display = "Inputs and outputs are not balanced")]
+    UnbalancedValues,
+
+    /// This error occurs when cleartext values of different flavors are added together.
+    #[fail(display = "Values have different flavors")]
+    FlavorMismatch,
+
+    /// This error occurs when the sum of cleartext quantities does not fit in 64 bits.
+    #[fail(display = "Sum of quantities overflows 64 bits")]
+    QuantityOverflow,
+
+    /// This error occurs when a program in the assembly text format cannot be parsed.
+    #[fail(display = "Invalid assembly at line {}", line)]
+    InvalidAssembly {
+        /// Line number (starting from 1) of the malformed instruction.
+        line: usize,
+    },
 }
@@ -0,0 +1,48 @@
+//! Canonical generators for proving and verifying transactions.
+//!
+//! Bulletproofs generators created with `BulletproofGens::new` are derived deterministically,
+//! so generators of different capacities begin with the same points: a prover and a verifier
+//! disagree only if one side's generators are too small for the constraint system.
+//! That case fails with `VMError::InvalidGenerators`, or earlier in the prover with
+//! `VMError::InsufficientGens` if the `cloak` instructions alone do not fit.
+//! Any other failure to create or verify the R1CS proof, including one caused
+//! by generators constructed differently, is reported as `VMError::InvalidR1CSProof`.
+use bulletproofs::r1cs::R1CSError;
+use bulletproofs::{BulletproofGens, PedersenGens};
+
+use crate::errors::VMError;
+
+/// Number of R1CS multipliers supported by the generators returned by `gens`.
+pub const GENS_CAPACITY: usize = 4096;
+
+/// Returns the generators the VM uses for the commitments and the R1CS proof:
+/// the Pedersen generators used by `Prover` and `Verifier`, and the Bulletproofs generators
+/// for a single party with `GENS_CAPACITY` multipliers, to pass to both of them.
+pub fn gens() -> (PedersenGens, BulletproofGens) {
+    (pedersen_gens(), BulletproofGens::new(GENS_CAPACITY, 1))
+}
+
+/// Returns the Pedersen generators for the commitments, without
+/// the costly construction of the Bulletproofs generators.
+pub(crate) fn pedersen_gens() -> PedersenGens {
+    PedersenGens::default()
+}
+
+/// Converts an error from proving or verifying the R1CS proof,
+/// telling apart the generators of insufficient capacity from an invalid proof.
+/// Generators of sufficient capacity but different points cannot be told apart
+/// from an invalid proof.
+/// `multipliers` is the number of multipliers allocated before the proof is created or verified.
+pub(crate) fn r1cs_proof_error(
+    err: R1CSError,
+    multipliers: usize,
+    bp_gens: &BulletproofGens,
+) -> VMError {
+    match err {
+        R1CSError::InvalidGeneratorsLength => VMError::InvalidGenerators {
+            needed: multipliers.next_power_of_two(),
+            available: bp_gens.gens_capacity,
+        },
+        _ => VMError::InvalidR1CSProof,
+    }
+}
@@ -3,13 +3,16 @@
 
 #[macro_use]
 extern crate failure;
+#[cfg(feature = "serde")]
 extern crate serde;
 
 pub mod blockchain;
+mod burn_proof;
 mod constraints;
 mod contract;
 mod encoding;
 mod errors;
+mod gens;
 mod merkle;
 mod ops;
 mod point_ops;
@@ -19,22 +22,29 @@ mod prover;
 mod scalar_witness;
 mod transcript;
 mod tx;
+mod tx_builder;
+mod tx_stream;
 mod types;
 pub mod utreexo;
 mod verifier;
 mod vm;
 
+pub use self::burn_proof::BurnProof;
 pub use self::constraints::{Commitment, CommitmentWitness, Constraint, Expression, Variable};
-pub use self::contract::{Anchor, Contract, ContractID, PortableItem};
+pub use self::contract::{sort_outputs, Anchor, Contract, ContractID, PortableItem};
+pub use self::encoding::SliceReader;
 pub use self::errors::VMError;
+pub use self::gens::{gens, GENS_CAPACITY};
 pub use self::merkle::{MerkleItem, MerkleNeighbor, MerkleTree};
-pub use self::ops::{Instruction, Opcode};
+pub use self::ops::{Instruction, Opcode, MAX_PUSH_BYTES};
 pub use self::predicate::{Predicate, PredicateTree};
-pub use self::program::{Program, ProgramItem};
+pub use self::program::{Program, ProgramItem, MAX_NESTING_DEPTH, MAX_PROGRAM_BYTES};
 pub use self::prover::Prover;
 pub use self::scalar_witness::ScalarWitness;
 pub use self::transcript::TranscriptProtocol;
-pub use self::tx::{Tx, TxEntry, TxHeader, TxID, TxLog, UnsignedTx, VerifiedTx};
+pub use self::tx::{Tx, TxEntry, TxHeader, TxID, TxLog, UnsignedTx, VerifiedTx, VmStats};
+pub use self::tx_builder::TxBuilder;
+pub use self::tx_stream::{TxStreamReader, MAX_TX_BYTES};
 pub use self::types::{ClearValue, Item, String, Value, WideValue};
 pub use self::verifier::Verifier;
 
@@ -1,6 +1,7 @@
 use merlin::Transcript;
 use subtle::ConstantTimeEq;
 
+use crate::encoding::{self, Sink, SliceReader};
 use crate::errors::VMError;
 
 /// MerkleItem defines an item in the Merkle tree.
@@ -143,6 +144,48 @@ impl MerkleTree {
     }
 }
 
+/// Encodes the merkle path as a 32-bit mask of the neighbors' sides,
+/// with the highest set bit marking the length of the path, followed by the neighbors' hashes.
+pub(crate) fn encode_path<S: Sink>(neighbors: &[MerkleNeighbor], buf: &mut S) {
+    let mut positions: u32 = 1 << neighbors.len();
+    for (i, n) in neighbors.iter().enumerate() {
+        match n {
+            MerkleNeighbor::Right(_) => {
+                positions = positions | (1 << i);
+            }
+            _ => {}
+        }
+    }
+    encoding::write_u32(positions, buf);
+    for n in neighbors {
+        match n {
+            MerkleNeighbor::Left(l) => encoding::write_bytes(l, buf),
+            MerkleNeighbor::Right(r) => encoding::write_bytes(r, buf),
+        }
+    }
+}
+
+/// Decodes the merkle path encoded with `encode_path`.
+pub(crate) fn decode_path<'a>(
+    reader: &mut SliceReader<'a>,
+) -> Result<Vec<MerkleNeighbor>, VMError> {
+    let positions = reader.read_u32()?;
+    if positions == 0 {
+        return Err(VMError::FormatError);
+    }
+    let num_neighbors = (31 - positions.leading_zeros()) as usize;
+    let mut neighbors = Vec::with_capacity(num_neighbors);
+    for i in 0..num_neighbors {
+        let bytes = reader.read_u8x32()?;
+        neighbors.push(if positions & (1 << i) == 0 {
+            MerkleNeighbor::Left(bytes)
+        } else {
+            MerkleNeighbor::Right(bytes)
+        });
+    }
+    Ok(neighbors)
+}
+
 impl MerkleNode {
     fn subpath(
         &self,
@@ -3,12 +3,17 @@
 
 use crate::encoding;
 use crate::encoding::Encodable;
+use crate::encoding::Sink;
 use crate::encoding::SliceReader;
 use crate::errors::VMError;
 use crate::program::ProgramItem;
 use crate::scalar_witness::ScalarWitness;
 use crate::types::String;
 use core::mem;
+use spacesuit::BitRange;
+
+/// Default maximum length of the immediate data of a `push` instruction.
+pub const MAX_PUSH_BYTES: usize = 1 << 16;
 
 /// A decoded instruction.
 #[derive(Clone, Debug)]
@@ -29,7 +34,7 @@ pub enum Instruction {
     Add,
     Mul,
     Eq,
-    Range,
+    Range(BitRange), // bit width
     And,
     Or,
     Not,
@@ -47,6 +52,7 @@ pub enum Instruction {
     Signtx,
     Signid,
     Signtag,
+    Fee,
     Ext(u8),
 }
 
@@ -87,10 +93,11 @@ pub enum Opcode {
     Call = 0x1d,
     Signtx = 0x1e,
     Signid = 0x1f,
-    Signtag = MAX_OPCODE,
+    Signtag = 0x20,
+    Fee = MAX_OPCODE,
 }
 
-const MAX_OPCODE: u8 = 0x20;
+const MAX_OPCODE: u8 = 0x21;
 
 impl Opcode {
     /// Converts the opcode to `u8`.
@@ -107,72 +114,80 @@ impl Opcode {
             unsafe { mem::transmute(code) }
         }
     }
+
+    /// Returns the lowercase name of the opcode used in the assembly text format.
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::Push => "push",
+            Opcode::Program => "program",
+            Opcode::Drop => "drop",
+            Opcode::Dup => "dup",
+            Opcode::Roll => "roll",
+            Opcode::Const => "const",
+            Opcode::Var => "var",
+            Opcode::Alloc => "alloc",
+            Opcode::Mintime => "mintime",
+            Opcode::Maxtime => "maxtime",
+            Opcode::Expr => "expr",
+            Opcode::Neg => "neg",
+            Opcode::Add => "add",
+            Opcode::Mul => "mul",
+            Opcode::Eq => "eq",
+            Opcode::Range => "range",
+            Opcode::And => "and",
+            Opcode::Or => "or",
+            Opcode::Not => "not",
+            Opcode::Verify => "verify",
+            Opcode::Unblind => "unblind",
+            Opcode::Issue => "issue",
+            Opcode::Borrow => "borrow",
+            Opcode::Retire => "retire",
+            Opcode::Cloak => "cloak",
+            Opcode::Input => "input",
+            Opcode::Output => "output",
+            Opcode::Contract => "contract",
+            Opcode::Log => "log",
+            Opcode::Call => "call",
+            Opcode::Signtx => "signtx",
+            Opcode::Signid => "signid",
+            Opcode::Signtag => "signtag",
+            Opcode::Fee => "fee",
+        }
+    }
+
+    /// Instantiates the opcode from its name in the assembly text format.
+    /// Unknown name is mapped to `None`.
+    pub fn from_mnemonic(name: &str) -> Option<Opcode> {
+        (0..=MAX_OPCODE)
+            .filter_map(Opcode::from_u8)
+            .find(|op| op.mnemonic() == name)
+    }
 }
 
 impl Encodable for Instruction {
     /// Appends the bytecode representation of an Instruction
     /// to the program.
-    fn encode(&self, program: &mut Vec<u8>) {
-        let mut write = |op: Opcode| program.push(op.to_u8());
+    fn encode<S: Sink>(&self, program: &mut S) {
+        encoding::write_u8(self.opcode(), program);
         match self {
             Instruction::Push(data) => {
-                write(Opcode::Push);
                 encoding::write_u32(data.serialized_length() as u32, program);
                 data.encode(program);
             }
             Instruction::Program(subprog) => {
-                write(Opcode::Program);
                 encoding::write_u32(subprog.serialized_length() as u32, program);
                 subprog.encode(program);
             }
-            Instruction::Drop => write(Opcode::Drop),
-            Instruction::Dup(idx) => {
-                write(Opcode::Dup);
-                encoding::write_u32(*idx as u32, program);
-            }
-            Instruction::Roll(idx) => {
-                write(Opcode::Roll);
-                encoding::write_u32(*idx as u32, program);
-            }
-            Instruction::Const => write(Opcode::Const),
-            Instruction::Var => write(Opcode::Var),
-            Instruction::Alloc(_) => write(Opcode::Alloc),
-            Instruction::Mintime => write(Opcode::Mintime),
-            Instruction::Maxtime => write(Opcode::Maxtime),
-            Instruction::Expr => write(Opcode::Expr),
-            Instruction::Neg => write(Opcode::Neg),
-            Instruction::Add => write(Opcode::Add),
-            Instruction::Mul => write(Opcode::Mul),
-            Instruction::Eq => write(Opcode::Eq),
-            Instruction::Range => write(Opcode::Range),
-            Instruction::And => write(Opcode::And),
-            Instruction::Or => write(Opcode::Or),
-            Instruction::Not => write(Opcode::Not),
-            Instruction::Verify => write(Opcode::Verify),
-            Instruction::Unblind => write(Opcode::Unblind),
-            Instruction::Issue => write(Opcode::Issue),
-            Instruction::Borrow => write(Opcode::Borrow),
-            Instruction::Retire => write(Opcode::Retire),
+            Instruction::Dup(idx) => encoding::write_u32(*idx as u32, program),
+            Instruction::Roll(idx) => encoding::write_u32(*idx as u32, program),
+            Instruction::Range(n) => encoding::write_u8((*n).into(), program),
             Instruction::Cloak(m, n) => {
-                write(Opcode::Cloak);
                 encoding::write_u32(*m as u32, program);
                 encoding::write_u32(*n as u32, program);
             }
-            Instruction::Input => write(Opcode::Input),
-            Instruction::Output(k) => {
-                write(Opcode::Output);
-                encoding::write_u32(*k as u32, program);
-            }
-            Instruction::Contract(k) => {
-                write(Opcode::Contract);
-                encoding::write_u32(*k as u32, program);
-            }
-            Instruction::Log => write(Opcode::Log),
-            Instruction::Call => write(Opcode::Call),
-            Instruction::Signtx => write(Opcode::Signtx),
-            Instruction::Signid => write(Opcode::Signid),
-            Instruction::Signtag => write(Opcode::Signtag),
-            Instruction::Ext(x) => program.push(*x),
+            Instruction::Output(k) => encoding::write_u32(*k as u32, program),
+            Instruction::Contract(k) => encoding::write_u32(*k as u32, program),
+            _ => {}
         };
     }
 
@@ -183,7 +198,7 @@ impl Encodable for Instruction {
             Instruction::Program(progitem) => 1 + 4 + progitem.serialized_length(),
             Instruction::Dup(_) => 1 + 4,
             Instruction::Roll(_) => 1 + 4,
-            Instruction::Range => 1,
+            Instruction::Range(_) => 1 + 1,
             Instruction::Cloak(_, _) => 1 + 4 + 4,
             Instruction::Output(_) => 1 + 4,
             Instruction::Contract(_) => 1 + 4,
@@ -193,14 +208,95 @@ impl Encodable for Instruction {
 }
 
 impl Instruction {
+    /// Returns a static estimate of the computational weight of the instruction:
+    /// one unit for executing it, plus the number of R1CS multipliers it may add
+    /// to the constraint system.
+    /// Nested programs are not included: their instructions are weighed when they are called.
+    pub fn cost(&self) -> u64 {
+        let bits: usize = BitRange::max().into();
+        let range_proof = bits as u64;
+        let multipliers = match self {
+            Instruction::Mul => 1,
+            Instruction::Or => 1,
+            Instruction::Not => 2,
+            Instruction::Range(n) => {
+                let bits: usize = (*n).into();
+                bits as u64
+            }
+            Instruction::Issue => range_proof,
+            Instruction::Borrow => range_proof + 1,
+            Instruction::Cloak(m, n) => spacesuit::cloak_multiplier_count(*m, *n) as u64,
+            _ => 0,
+        };
+        1 + multipliers
+    }
+
+    /// Returns the opcode byte of the instruction.
+    /// Extension instructions return their unassigned code as-is.
+    pub fn opcode(&self) -> u8 {
+        let op = match self {
+            Instruction::Push(_) => Opcode::Push,
+            Instruction::Program(_) => Opcode::Program,
+            Instruction::Drop => Opcode::Drop,
+            Instruction::Dup(_) => Opcode::Dup,
+            Instruction::Roll(_) => Opcode::Roll,
+            Instruction::Const => Opcode::Const,
+            Instruction::Var => Opcode::Var,
+            Instruction::Alloc(_) => Opcode::Alloc,
+            Instruction::Mintime => Opcode::Mintime,
+            Instruction::Maxtime => Opcode::Maxtime,
+            Instruction::Expr => Opcode::Expr,
+            Instruction::Neg => Opcode::Neg,
+            Instruction::Add => Opcode::Add,
+            Instruction::Mul => Opcode::Mul,
+            Instruction::Eq => Opcode::Eq,
+            Instruction::Range(_) => Opcode::Range,
+            Instruction::And => Opcode::And,
+            Instruction::Or => Opcode::Or,
+            Instruction::Not => Opcode::Not,
+            Instruction::Verify => Opcode::Verify,
+            Instruction::Unblind => Opcode::Unblind,
+            Instruction::Issue => Opcode::Issue,
+            Instruction::Borrow => Opcode::Borrow,
+            Instruction::Retire => Opcode::Retire,
+            Instruction::Cloak(_, _) => Opcode::Cloak,
+            Instruction::Input => Opcode::Input,
+            Instruction::Output(_) => Opcode::Output,
+            Instruction::Contract(_) => Opcode::Contract,
+            Instruction::Log => Opcode::Log,
+            Instruction::Call => Opcode::Call,
+            Instruction::Signtx => Opcode::Signtx,
+            Instruction::Signid => Opcode::Signid,
+            Instruction::Signtag => Opcode::Signtag,
+            Instruction::Fee => Opcode::Fee,
+            Instruction::Ext(x) => return *x,
+        };
+        op.to_u8()
+    }
+
+    /// Returns the kind of instruction for the given opcode byte.
+    /// Unassigned codes (extension instructions) are mapped to `None`.
+    pub fn from_opcode(code: u8) -> Option<Opcode> {
+        Opcode::from_u8(code)
+    }
+
     /// Returns a parsed instruction from a subslice of the program string, modifying
     /// the subslice according to the bytes the instruction occupies
     /// E.g. a push instruction with 5-byte string occupies 1+4+5=10 bytes,
     /// (4 for the LE32 length prefix), advancing the program subslice by 10 bytes.
     ///
     /// Return `VMError::FormatError` if there are not enough bytes to parse an
-    /// instruction.
+    /// instruction, and `VMError::PushTooLarge` if a `push` declares
+    /// more than `MAX_PUSH_BYTES` of data.
     pub fn parse(program: &mut SliceReader) -> Result<Self, VMError> {
+        Self::parse_with_max_push(program, MAX_PUSH_BYTES)
+    }
+
+    /// Same as `parse`, but limits the immediate data of a `push` to `max_push` bytes.
+    pub fn parse_with_max_push(
+        program: &mut SliceReader,
+        max_push: usize,
+    ) -> Result<Self, VMError> {
         let byte = program.read_u8()?;
 
         // Interpret the opcode. Unknown opcodes are extension opcodes.
@@ -214,11 +310,14 @@ impl Instruction {
         match opcode {
             Opcode::Push => {
                 let strlen = program.read_size()?;
+                if strlen > max_push {
+                    return Err(VMError::PushTooLarge);
+                }
                 let data_slice = program.read_bytes(strlen)?;
                 Ok(Instruction::Push(String::Opaque(data_slice.to_vec())))
             }
             Opcode::Program => {
-                let strlen = program.read_size()?;
+                let strlen = program.read_size_bounded(program.len())?;
                 let data_slice = program.read_bytes(strlen)?;
                 Ok(Instruction::Program(ProgramItem::Bytecode(
                     data_slice.to_vec(),
@@ -243,7 +342,11 @@ impl Instruction {
             Opcode::Add => Ok(Instruction::Add),
             Opcode::Mul => Ok(Instruction::Mul),
             Opcode::Eq => Ok(Instruction::Eq),
-            Opcode::Range => Ok(Instruction::Range),
+            Opcode::Range => {
+                let n = program.read_u8()?;
+                let n = BitRange::new(n as usize).ok_or(VMError::InvalidBitrange)?;
+                Ok(Instruction::Range(n))
+            }
             Opcode::And => Ok(Instruction::And),
             Opcode::Or => Ok(Instruction::Or),
             Opcode::Not => Ok(Instruction::Not),
@@ -271,6 +374,229 @@ impl Instruction {
             Opcode::Signtx => Ok(Instruction::Signtx),
             Opcode::Signid => Ok(Instruction::Signid),
             Opcode::Signtag => Ok(Instruction::Signtag),
+            Opcode::Fee => Ok(Instruction::Fee),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Program;
+    use std::collections::HashSet;
+
+    #[test]
+    fn stable_opcodes() {
+        let prog = Program::build(|p| {
+            p.push(String::default())
+                .program(Program::new())
+                .drop()
+                .dup(1)
+                .roll(1)
+                .r#const()
+                .var()
+                .alloc(None)
+                .mintime()
+                .maxtime()
+                .expr()
+                .neg()
+                .add()
+                .mul()
+                .eq()
+                .range()
+                .and()
+                .or()
+                .not()
+                .verify()
+                .unblind()
+                .issue()
+                .borrow()
+                .retire()
+                .cloak(1, 1)
+                .input()
+                .output(1)
+                .contract(1)
+                .log()
+                .call()
+                .sign_tx()
+                .signid()
+                .signtag()
+                .fee()
+        });
+        let instructions = prog.to_vec();
+
+        let mut seen = HashSet::new();
+        for (i, instr) in instructions.iter().enumerate() {
+            // opcodes are assigned sequentially in the order of the builder calls above
+            assert_eq!(instr.opcode(), i as u8);
+            assert!(seen.insert(instr.opcode()));
+            assert_eq!(instr.encode_to_vec()[0], instr.opcode());
+            assert_eq!(
+                Instruction::from_opcode(instr.opcode()).map(|op| op.to_u8()),
+                Some(instr.opcode())
+            );
+            let op = Opcode::from_u8(instr.opcode()).unwrap();
+            assert_eq!(Opcode::from_mnemonic(op.mnemonic()), Some(op));
+        }
+        assert_eq!(seen.len(), MAX_OPCODE as usize + 1);
+        assert_eq!(Opcode::from_mnemonic("ext"), None);
+        assert_eq!(Opcode::from_mnemonic("PUSH"), None);
+
+        assert_eq!(Instruction::Ext(0xff).opcode(), 0xff);
+        assert_eq!(Instruction::from_opcode(0xff), None);
+        assert_eq!(Instruction::from_opcode(MAX_OPCODE + 1), None);
+    }
+
+    /// Returns an instance of every `Instruction` variant, in the order of their opcodes,
+    /// followed by an extension instruction.
+    fn all_instructions() -> Vec<Instruction> {
+        let instructions = vec![
+            Instruction::Push(String::Opaque(vec![1, 2, 3])),
+            Instruction::Program(ProgramItem::Bytecode(vec![Opcode::Drop.to_u8()])),
+            Instruction::Drop,
+            Instruction::Dup(1),
+            Instruction::Roll(2),
+            Instruction::Const,
+            Instruction::Var,
+            Instruction::Alloc(None),
+            Instruction::Mintime,
+            Instruction::Maxtime,
+            Instruction::Expr,
+            Instruction::Neg,
+            Instruction::Add,
+            Instruction::Mul,
+            Instruction::Eq,
+            Instruction::Range(BitRange::new(32).unwrap()),
+            Instruction::And,
+            Instruction::Or,
+            Instruction::Not,
+            Instruction::Verify,
+            Instruction::Unblind,
+            Instruction::Issue,
+            Instruction::Borrow,
+            Instruction::Retire,
+            Instruction::Cloak(2, 3),
+            Instruction::Input,
+            Instruction::Output(1),
+            Instruction::Contract(2),
+            Instruction::Log,
+            Instruction::Call,
+            Instruction::Signtx,
+            Instruction::Signid,
+            Instruction::Signtag,
+            Instruction::Fee,
+            Instruction::Ext(MAX_OPCODE + 1),
+        ];
+        // No wildcard: a new variant does not compile until it is added to the list above.
+        for instr in instructions.iter() {
+            match instr {
+                Instruction::Push(_)
+                | Instruction::Program(_)
+                | Instruction::Drop
+                | Instruction::Dup(_)
+                | Instruction::Roll(_)
+                | Instruction::Const
+                | Instruction::Var
+                | Instruction::Alloc(_)
+                | Instruction::Mintime
+                | Instruction::Maxtime
+                | Instruction::Expr
+                | Instruction::Neg
+                | Instruction::Add
+                | Instruction::Mul
+                | Instruction::Eq
+                | Instruction::Range(_)
+                | Instruction::And
+                | Instruction::Or
+                | Instruction::Not
+                | Instruction::Verify
+                | Instruction::Unblind
+                | Instruction::Issue
+                | Instruction::Borrow
+                | Instruction::Retire
+                | Instruction::Cloak(_, _)
+                | Instruction::Input
+                | Instruction::Output(_)
+                | Instruction::Contract(_)
+                | Instruction::Log
+                | Instruction::Call
+                | Instruction::Signtx
+                | Instruction::Signid
+                | Instruction::Signtag
+                | Instruction::Fee
+                | Instruction::Ext(_) => {}
+            }
+        }
+        instructions
+    }
+
+    #[test]
+    fn all_instructions_roundtrip() {
+        let instructions = all_instructions();
+
+        let mut seen = HashSet::new();
+        for instr in instructions.iter() {
+            assert!(seen.insert(instr.opcode()));
+            let bytes = instr.encode_to_vec();
+            assert_eq!(bytes.len(), instr.serialized_length());
+            let parsed = SliceReader::parse(&bytes, |r| Instruction::parse(r)).unwrap();
+            assert_eq!(parsed.opcode(), instr.opcode());
+            assert_eq!(parsed.encode_to_vec(), bytes);
+        }
+
+        // Every assigned opcode is covered, plus one extension opcode.
+        assert_eq!(seen.len(), MAX_OPCODE as usize + 2);
+        assert!((0..=MAX_OPCODE).all(|code| seen.contains(&code)));
+    }
+
+    #[test]
+    fn push_size_limit() {
+        let push = |len: usize| {
+            let mut buf = vec![Opcode::Push.to_u8()];
+            encoding::write_size(len, &mut buf);
+            buf.extend(vec![0u8; len.min(16)]);
+            buf
+        };
+        let parse = |data: &[u8], max: usize| {
+            SliceReader::parse(data, |r| Instruction::parse_with_max_push(r, max))
+        };
+
+        assert!(parse(&push(16), 16).is_ok());
+        assert_eq!(parse(&push(16), 15).err(), Some(VMError::PushTooLarge));
+
+        // Declared length exceeding the cap fails cleanly without reading the data.
+        let data = push(MAX_PUSH_BYTES + 1);
+        assert_eq!(
+            SliceReader::parse(&data, |r| Instruction::parse(r)).err(),
+            Some(VMError::PushTooLarge)
+        );
+
+        // Declared length within the cap but beyond the data is a format error.
+        let mut data = push(16);
+        data.truncate(10);
+        assert_eq!(parse(&data, 16).err(), Some(VMError::FormatError));
+    }
+
+    #[test]
+    fn range_bit_width() {
+        let prog = Program::build(|p| p.range_bits(8).range());
+        assert_eq!(
+            prog.to_bytes(),
+            vec![Opcode::Range.to_u8(), 8, Opcode::Range.to_u8(), 64]
+        );
+
+        let parse = |data: &[u8]| SliceReader::parse(data, |r| Instruction::parse(r));
+        match parse(&[Opcode::Range.to_u8(), 8]) {
+            Ok(Instruction::Range(n)) => assert_eq!(Into::<usize>::into(n), 8),
+            _ => panic!("expected a range instruction"),
         }
+        assert_eq!(
+            parse(&[Opcode::Range.to_u8(), 65]).err(),
+            Some(VMError::InvalidBitrange)
+        );
+        assert_eq!(
+            parse(&[Opcode::Range.to_u8()]).err(),
+            Some(VMError::FormatError)
+        );
     }
 }
@@ -8,12 +8,14 @@ use curve25519_dalek::ristretto::CompressedRistretto;
 use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
 use musig::VerificationKey;
+use rand::RngCore;
 
 use crate::encoding;
 use crate::encoding::Encodable;
+use crate::encoding::Sink;
 use crate::encoding::SliceReader;
 use crate::errors::VMError;
-use crate::merkle::{MerkleItem, MerkleNeighbor, MerkleTree};
+use crate::merkle::{self, MerkleItem, MerkleNeighbor, MerkleTree};
 use crate::point_ops::PointOp;
 use crate::program::{Program, ProgramItem};
 use crate::transcript::TranscriptProtocol;
@@ -75,7 +77,7 @@ pub enum PredicateLeaf {
 }
 impl Encodable for Predicate {
     /// Encodes the Predicate in program bytecode.
-    fn encode(&self, prog: &mut Vec<u8>) {
+    fn encode<S: Sink>(&self, prog: &mut S) {
         encoding::write_point(&self.to_point(), prog);
     }
     /// Returns the number of bytes needed to serialize the Predicate.
@@ -84,6 +86,12 @@ impl Encodable for Predicate {
     }
 }
 impl Predicate {
+    /// Creates a predicate that is satisfied by a signature with the given verification key,
+    /// e.g. a MuSig aggregated key (see `Multikey::aggregated_key`), via the `signtx` instruction.
+    pub fn key(vk: VerificationKey) -> Self {
+        Predicate::Key(vk)
+    }
+
     /// Converts predicate to a compressed point
     pub fn to_point(&self) -> CompressedRistretto {
         match self {
@@ -127,6 +135,20 @@ impl Predicate {
         Predicate::Opaque(self.to_point())
     }
 
+    /// Returns true if the predicate is the given verification key,
+    /// e.g. to check an opaque predicate decoded from a transaction against an expected key.
+    pub fn verify_is_key(&self, vk: &VerificationKey) -> bool {
+        self.to_point() == *vk.as_compressed()
+    }
+
+    /// Returns true if the predicate is a taproot commitment to the verification key `key`
+    /// and the Merkle root `root` of a predicate tree (see `PredicateTree::root`).
+    pub fn verify_is_tree_root(&self, key: &VerificationKey, root: &[u8; 32]) -> bool {
+        let h = Self::commit_taproot(key, root);
+        let p = key.into_point() + h * PedersenGens::default().B;
+        self.to_point() == p.compress()
+    }
+
     fn commit_taproot(key: &VerificationKey, root: &[u8; 32]) -> Scalar {
         let mut t = Transcript::new(b"ZkVM.taproot");
         t.append_message(b"key", &key.as_compressed().to_bytes());
@@ -198,6 +220,53 @@ impl PredicateTree {
         })
     }
 
+    /// Creates a predicate tree that can be satisfied by any one of the `programs`.
+    /// The tree uses an unsignable key, so the contract can only be unlocked via `call`.
+    /// Programs are blinded with a random blinding key.
+    pub fn disjunction(programs: Vec<Program>) -> Result<Self, VMError> {
+        let mut blinding_key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut blinding_key);
+        Self::new(None, programs, blinding_key)
+    }
+
+    /// Creates a predicate tree for a contract with `k` payload items that can only be spent
+    /// by a transaction with `maxtime` not greater than `deadline_ms` (see `Program::check_maxtime`).
+    /// The only program in the tree checks the deadline and wraps the payload in a new contract
+    /// locked by `owner`, which the spender then unlocks as usual (e.g. with `signtx`).
+    /// The tree uses an unsignable key, so the deadline cannot be bypassed by signing.
+    pub fn with_deadline(
+        owner: Predicate,
+        deadline_ms: u64,
+        k: usize,
+        blinding_key: [u8; 32],
+    ) -> Result<Self, VMError> {
+        let program = Program::build(|p| p.check_maxtime(deadline_ms).push(owner).contract(k));
+        Self::new(None, vec![program], blinding_key)
+    }
+
+    /// Returns the Merkle root of the tree, committed to by the predicate key.
+    /// Call proofs made with `create_callproof` are verified against this root.
+    pub fn root(&self) -> [u8; 32] {
+        MerkleTree::root(b"ZkVM.taproot", &self.leaves)
+    }
+
+    /// Appends a program to the tree, and recomputes the root and the predicate key.
+    /// The blinding factors are derived from the entire list of programs, so all the leaves
+    /// are re-derived and the result is the same as a tree created with `new` from all the programs.
+    pub fn append_program(&mut self, program: Program) -> Result<(), VMError> {
+        let mut progs = self
+            .leaves
+            .iter()
+            .filter_map(|leaf| match leaf {
+                PredicateLeaf::Program(prog) => Some(prog.clone().to_program()),
+                PredicateLeaf::Blinding(_) => None,
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        progs.push(program);
+        *self = Self::new(Some(self.key), progs, self.blinding_key)?;
+        Ok(())
+    }
+
     /// Returns the adjustment factor for signing
     // TODO: Instead, we would rather return a "key witness" object like musig::Multikey.
     // That would directly store the adjustment factor.
@@ -205,7 +274,24 @@ impl PredicateTree {
         self.adjustment_factor
     }
 
+    /// Returns the index of the program in the tree, suitable for `create_callproof`,
+    /// or `None` if the tree does not contain the program.
+    pub fn contains(&self, program: &Program) -> Option<usize> {
+        let bytecode = program.to_bytes();
+        self.leaves
+            .iter()
+            .filter_map(|leaf| match leaf {
+                PredicateLeaf::Program(prog) => Some(prog),
+                PredicateLeaf::Blinding(_) => None,
+            })
+            .position(|prog| prog.canonical_bytes() == bytecode)
+    }
+
     /// Creates the call proof and returns that with the program at an index.
+    ///
+    /// The proof is bound to the current root of the tree: the root is committed
+    /// in the predicate key, so the proof is rejected by `prove_taproot`
+    /// for any other tree, including this tree after `append_program`.
     pub fn create_callproof(&self, prog_index: usize) -> Result<(CallProof, Program), VMError> {
         // The `prog_index` is used over the list of the programs,
         // but the actual tree contains also contains blinding factors,
@@ -265,26 +351,10 @@ impl PredicateTree {
 
 impl Encodable for CallProof {
     /// Serializes the call proof to a byte array.
-    fn encode(&self, buf: &mut Vec<u8>) {
+    fn encode<S: Sink>(&self, buf: &mut S) {
         encoding::write_point(self.verification_key.as_compressed(), buf);
 
-        let num_neighbors = self.neighbors.len();
-        let mut positions: u32 = 1 << num_neighbors;
-        for (i, n) in self.neighbors.iter().enumerate() {
-            match n {
-                MerkleNeighbor::Right(_) => {
-                    positions = positions | (1 << i);
-                }
-                _ => {}
-            }
-        }
-        encoding::write_u32(positions, buf);
-        for n in &self.neighbors {
-            match n {
-                MerkleNeighbor::Left(l) => encoding::write_bytes(l, buf),
-                MerkleNeighbor::Right(r) => encoding::write_bytes(r, buf),
-            }
-        }
+        merkle::encode_path(&self.neighbors, buf);
     }
     fn serialized_length(&self) -> usize {
         // VerificationKey is a 32-byte array
@@ -299,20 +369,7 @@ impl CallProof {
         let verification_key =
             VerificationKey::from_compressed(reader.read_point()?).ok_or(VMError::InvalidPoint)?;
 
-        let positions = reader.read_u32()?;
-        if positions == 0 {
-            return Err(VMError::FormatError);
-        }
-        let num_neighbors = (31 - positions.leading_zeros()) as usize;
-        let mut neighbors = Vec::with_capacity(num_neighbors);
-        for i in 0..num_neighbors {
-            let bytes = reader.read_u8x32()?;
-            neighbors.push(if positions & (1 << i) == 0 {
-                MerkleNeighbor::Left(bytes)
-            } else {
-                MerkleNeighbor::Right(bytes)
-            });
-        }
+        let neighbors = merkle::decode_path(reader)?;
         Ok(CallProof {
             verification_key,
             neighbors,
@@ -374,4 +431,110 @@ mod tests {
         let op = tree_pred.prove_taproot(&ProgramItem::Program(prog3), &call_proof);
         assert!(op.verify().is_err())
     }
+
+    #[test]
+    fn append_programs() {
+        let blinding_key = rand::thread_rng().gen::<[u8; 32]>();
+        let progs = vec![
+            Program::build(|p| p.drop()),
+            Program::build(|p| p.dup(1)),
+            Program::build(|p| p.dup(2)),
+        ];
+        let mut tree = PredicateTree::new(None, vec![], blinding_key).unwrap();
+        for (n, prog) in progs.iter().enumerate() {
+            tree.append_program(prog.clone()).unwrap();
+
+            // Every program in the updated tree is provable against the updated root.
+            for i in 0..=n {
+                let (call_proof, prog) = tree.create_callproof(i).unwrap();
+                let item = ProgramItem::Program(prog);
+                let root = MerkleTree::compute_root_from_path(
+                    b"ZkVM.taproot",
+                    &item,
+                    &call_proof.neighbors,
+                );
+                assert_eq!(root, tree.root());
+                let tree_pred = Predicate::Tree(tree.clone());
+                assert!(tree_pred.prove_taproot(&item, &call_proof).verify().is_ok());
+            }
+        }
+
+        let full_tree = PredicateTree::new(None, progs, blinding_key).unwrap();
+        assert_eq!(tree.root(), full_tree.root());
+        assert_eq!(
+            Predicate::Tree(tree).to_point(),
+            Predicate::Tree(full_tree).to_point()
+        );
+    }
+
+    #[test]
+    fn contains_program() {
+        let blinding_key = rand::thread_rng().gen::<[u8; 32]>();
+        let progs = vec![
+            Program::build(|p| p.drop()),
+            Program::build(|p| p.dup(1)),
+            Program::build(|p| p.dup(2)),
+        ];
+        let tree = PredicateTree::new(None, progs.clone(), blinding_key).unwrap();
+        for (i, prog) in progs.iter().enumerate() {
+            assert_eq!(tree.contains(prog), Some(i));
+            let (_, found) = tree.create_callproof(i).unwrap();
+            assert_eq!(found.to_bytes(), prog.to_bytes());
+        }
+        assert_eq!(tree.contains(&Program::build(|p| p.dup(3))), None);
+    }
+
+    #[test]
+    fn callproof_replay() {
+        let blinding_key = rand::thread_rng().gen::<[u8; 32]>();
+        let prog = Program::build(|p| p.drop());
+        let mut tree = PredicateTree::new(None, vec![prog.clone()], blinding_key).unwrap();
+        let (call_proof, _) = tree.create_callproof(0).unwrap();
+        let item = ProgramItem::Program(prog.clone());
+        assert!(Predicate::Tree(tree.clone())
+            .prove_taproot(&item, &call_proof)
+            .verify()
+            .is_ok());
+
+        // The same program in another tree.
+        let other_key = rand::thread_rng().gen::<[u8; 32]>();
+        let other = PredicateTree::new(None, vec![prog.clone()], other_key).unwrap();
+        assert!(Predicate::Tree(other)
+            .prove_taproot(&item, &call_proof)
+            .verify()
+            .is_err());
+
+        // The same tree after a mutation.
+        tree.append_program(Program::build(|p| p.dup(1))).unwrap();
+        assert_eq!(tree.contains(&prog), Some(0));
+        assert!(Predicate::Tree(tree.clone())
+            .prove_taproot(&item, &call_proof)
+            .verify()
+            .is_err());
+        let (call_proof, _) = tree.create_callproof(0).unwrap();
+        assert!(Predicate::Tree(tree)
+            .prove_taproot(&item, &call_proof)
+            .verify()
+            .is_ok());
+    }
+
+    #[test]
+    fn opaque_predicate_matching() {
+        let key = VerificationKey::from_secret(&Scalar::from(1u64));
+        let other_key = VerificationKey::from_secret(&Scalar::from(2u64));
+
+        let opaque = Predicate::Key(key).as_opaque();
+        assert!(opaque.verify_is_key(&key));
+        assert!(!opaque.verify_is_key(&other_key));
+
+        let blinding_key = rand::thread_rng().gen::<[u8; 32]>();
+        let progs = vec![Program::build(|p| p.drop())];
+        let tree = PredicateTree::new(Some(key), progs, blinding_key).unwrap();
+        let root = tree.root();
+        let opaque = Predicate::Tree(tree).as_opaque();
+        assert!(opaque.verify_is_tree_root(&key, &root));
+        assert!(!opaque.verify_is_tree_root(&other_key, &root));
+        assert!(!opaque.verify_is_tree_root(&key, &[0u8; 32]));
+        assert!(!opaque.verify_is_key(&key));
+    }
 }
@@ -1,13 +1,22 @@
-use crate::encoding::Encodable;
+use crate::contract::Contract;
+use crate::encoding::{self, Encodable, Sink, SliceReader};
 use crate::errors::VMError;
 use crate::merkle::MerkleItem;
-use crate::ops::Instruction;
-use crate::predicate::PredicateTree;
+use crate::ops::{Instruction, Opcode};
+use crate::predicate::{Predicate, PredicateTree};
 use crate::scalar_witness::ScalarWitness;
 use crate::types::String;
 
 use core::borrow::Borrow;
 use merlin::Transcript;
+use spacesuit::BitRange;
+
+/// Maximum size of a program's bytecode accepted by `Program::parse` and the VM.
+pub const MAX_PROGRAM_BYTES: usize = 1 << 20;
+
+/// Maximum number of nested programs that can be running at once in the VM
+/// (each `call`, `signid` and `signtag` instruction runs a program nested in the current one).
+pub const MAX_NESTING_DEPTH: usize = 64;
 
 /// A builder type for assembling a sequence of `Instruction`s with chained method calls.
 /// E.g. `let prog = Program::new().push(...).input().push(...).output(1).to_vec()`.
@@ -48,7 +57,7 @@ macro_rules! def_op {
 }
 
 impl Encodable for Program {
-    fn encode(&self, buf: &mut Vec<u8>) {
+    fn encode<S: Sink>(&self, buf: &mut S) {
         for i in self.0.iter() {
             i.borrow().encode(buf);
         }
@@ -71,6 +80,7 @@ impl Program {
     def_op!(dup, Dup, usize);
     def_op!(eq, Eq);
     def_op!(expr, Expr);
+    def_op!(fee, Fee);
     def_op!(input, Input);
     def_op!(issue, Issue);
     def_op!(log, Log);
@@ -80,13 +90,43 @@ impl Program {
     def_op!(neg, Neg);
     def_op!(or, Or);
     def_op!(output, Output, usize);
-    def_op!(range, Range);
     def_op!(retire, Retire);
     def_op!(roll, Roll, usize);
+
+    /// Adds a `range` instruction with a 64-bit range proof.
+    pub fn range(&mut self) -> &mut Program {
+        self.0.push(Instruction::Range(BitRange::max()));
+        self
+    }
+
+    /// Adds a `range` instruction with an `n`-bit range proof.
+    /// Narrower ranges cost fewer multipliers in the constraint system.
+    ///
+    /// Panics if `n` is greater than 64.
+    pub fn range_bits(&mut self, n: usize) -> &mut Program {
+        let n = BitRange::new(n).expect("range proof must be at most 64 bits wide");
+        self.0.push(Instruction::Range(n));
+        self
+    }
     def_op!(sign_tx, Signtx);
     def_op!(signid, Signid);
     def_op!(signtag, Signtag);
-    def_op!(unblind, Unblind);
+
+    /// Adds an `unblind` instruction.
+    ///
+    /// The instruction pops a scalar `v` and a commitment `V`,
+    /// checks that `V` commits to `v` with a zero blinding factor,
+    /// and pushes `V` back as a string.
+    ///
+    /// If `V` is an open commitment whose witness does not match `v`,
+    /// the prover fails with `VMError::InconsistentWitness`.
+    /// Otherwise the check is deferred to the verifier, which fails
+    /// with `VMError::PointOperationFailed`.
+    pub fn unblind(&mut self) -> &mut Program {
+        self.0.push(Instruction::Unblind);
+        self
+    }
+
     def_op!(var, Var);
     def_op!(verify, Verify);
 
@@ -110,16 +150,189 @@ impl Program {
         self.encode_to_vec()
     }
 
-    // /// Creates a program from parsing the Bytecode data slice of encoded instructions.
-    // pub(crate) fn parse(data: &[u8]) -> Result<Self, VMError> {
-    //     SliceReader::parse(data, |r| {
-    //         let mut program = Self::new();
-    //         while r.len() > 0 {
-    //             program.0.push(Instruction::parse(r)?);
-    //         }
-    //         Ok(program)
-    //     })
-    // }
+    /// Serializes the program prefixed with its LE32 length,
+    /// so it can be embedded in a larger structure (e.g. as a call target).
+    pub fn encode_with_length(&self, buf: &mut Vec<u8>) {
+        encoding::write_size(self.serialized_length(), buf);
+        self.encode(buf);
+    }
+
+    /// Decodes a length-prefixed program written by `encode_with_length`,
+    /// leaving the reader right after it.
+    /// Fails with `VMError::FormatError` if the length exceeds `MAX_PROGRAM_BYTES`
+    /// or the remaining data.
+    pub fn decode_with_length<'a>(reader: &mut SliceReader<'a>) -> Result<Self, VMError> {
+        let len = reader.read_size_bounded(MAX_PROGRAM_BYTES)?;
+        Self::parse(reader.read_bytes(len)?)
+    }
+
+    /// Converts the prover's view of the program into the verifier's view.
+    /// Witness data carried by the instructions (openings of commitments,
+    /// `alloc` assignments, nested programs) is not part of the encoding,
+    /// so the resulting bytecode executes to the same txid in the verifier.
+    pub fn to_verifier(self) -> ProgramItem {
+        ProgramItem::Bytecode(self.to_bytes())
+    }
+
+    /// Creates a program from parsing the Bytecode data slice of encoded instructions.
+    pub fn parse(data: &[u8]) -> Result<Self, VMError> {
+        if data.len() > MAX_PROGRAM_BYTES {
+            return Err(VMError::ProgramTooLarge);
+        }
+        SliceReader::parse(data, |r| {
+            let mut program = Self::new();
+            while !r.is_empty() {
+                program.0.push(Instruction::parse(r)?);
+            }
+            Ok(program)
+        })
+    }
+
+    /// Returns the program in the assembly text format, one instruction per line,
+    /// e.g. `push 0xdead`, `cloak 2 3` or `output 1`.
+    /// Strings and nested programs are written as hex-encoded bytes,
+    /// extension instructions as `ext` followed by their opcode.
+    /// Like the bytecode, the text does not include witness data.
+    pub fn to_asm(&self) -> std::string::String {
+        let mut asm = std::string::String::new();
+        for instr in self.0.iter() {
+            let name = match Opcode::from_u8(instr.opcode()) {
+                Some(op) => op.mnemonic(),
+                None => "ext",
+            };
+            let operands = match instr {
+                Instruction::Push(data) => vec![asm_bytes(&data.clone().to_bytes())],
+                Instruction::Program(prog) => vec![asm_bytes(&prog.to_bytes())],
+                Instruction::Dup(i) | Instruction::Roll(i) => vec![i.to_string()],
+                Instruction::Range(n) => {
+                    let bits: usize = (*n).into();
+                    vec![bits.to_string()]
+                }
+                Instruction::Cloak(m, n) => vec![m.to_string(), n.to_string()],
+                Instruction::Output(k) | Instruction::Contract(k) => vec![k.to_string()],
+                Instruction::Ext(code) => vec![format!("0x{:02x}", code)],
+                _ => vec![],
+            };
+            asm.push_str(name);
+            for operand in operands {
+                asm.push(' ');
+                asm.push_str(&operand);
+            }
+            asm.push('\n');
+        }
+        asm
+    }
+
+    /// Parses a program in the assembly text format produced by `to_asm`.
+    /// Numeric operands can be written in decimal or as `0x`-prefixed hex,
+    /// and a `push` of a decimal number pushes it as a scalar.
+    /// Empty lines and text after `#` are ignored.
+    ///
+    /// Fails with `VMError::InvalidAssembly` reporting the first malformed line.
+    pub fn from_asm(s: &str) -> Result<Program, VMError> {
+        let mut program = Self::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            let name = match words.next() {
+                Some(name) => name,
+                None => continue,
+            };
+            let operands: Vec<&str> = words.collect();
+            let instr = parse_asm_instruction(name, &operands)
+                .ok_or(VMError::InvalidAssembly { line: i + 1 })?;
+            program.0.push(instr);
+        }
+        Ok(program)
+    }
+
+    /// Returns the estimated computational weight of the program,
+    /// the sum of `Instruction::cost` of its instructions.
+    pub fn total_cost(&self) -> u64 {
+        self.0.iter().map(|i| i.cost()).sum()
+    }
+
+    /// Returns the length of the largest immediate data of a `push` instruction of the program,
+    /// or 0 if the program has no `push` instructions.
+    /// Nested programs are not included: their instructions are checked when they are called.
+    pub fn max_push_length(&self) -> usize {
+        self.0
+            .iter()
+            .map(|i| match i {
+                Instruction::Push(data) => data.serialized_length(),
+                _ => 0,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of R1CS multipliers allocated by the `cloak` instructions
+    /// of the program (see `spacesuit::cloak_multiplier_count`).
+    /// Nested programs are not included, since they may never be called:
+    /// the count is a lower bound, suitable for a best-effort early exit only.
+    pub fn cloak_multiplier_count(&self) -> usize {
+        self.0
+            .iter()
+            .map(|i| match i {
+                Instruction::Cloak(m, n) => spacesuit::cloak_multiplier_count(*m, *n),
+                _ => 0,
+            })
+            .fold(0, usize::saturating_add)
+    }
+
+    /// Checks that no instruction of the program would underflow the stack,
+    /// assuming the program starts with an empty stack, without executing it.
+    /// Fails with `VMError::StackUnderflowAt` reporting the first such instruction.
+    ///
+    /// The stack depth after `signtx`, `call`, `signid` and `signtag` depends on the
+    /// contract payload (and the latter three continue with another program),
+    /// so the instructions that follow them are not checked.
+    /// Types of the items are not checked either: a program that passes
+    /// may still fail when executed.
+    pub fn verify_structure(&self) -> Result<(), VMError> {
+        let mut depth = 0usize;
+        for (index, instr) in self.0.iter().enumerate() {
+            let (requested, remaining) = stack_effect(instr);
+            if requested > depth {
+                return Err(VMError::StackUnderflowAt {
+                    index,
+                    requested,
+                    available: depth,
+                });
+            }
+            match remaining {
+                Some(remaining) => depth = depth - requested + remaining,
+                None => return Ok(()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the program with redundant instructions removed by the peephole rewrites:
+    /// - `dup:k drop` and `push:x drop` are removed, since the dropped item is never used;
+    /// - `roll:0` is removed, since it leaves the top item in place.
+    ///
+    /// The rewrites preserve the resulting stack and the transaction log (and therefore the txid)
+    /// of any program that executes successfully. A program that fails at a removed instruction
+    /// (e.g. `roll:0` on an empty stack) may fail later or not at all once optimized,
+    /// so only programs that are known to be valid should be optimized.
+    /// Nested programs are left as-is, since they may be committed to by predicates.
+    pub fn optimize(self) -> Program {
+        let mut optimized: Vec<Instruction> = Vec::with_capacity(self.0.len());
+        for instr in self.0 {
+            match (&instr, optimized.last()) {
+                (Instruction::Roll(0), _) => continue,
+                (Instruction::Drop, Some(Instruction::Dup(_)))
+                | (Instruction::Drop, Some(Instruction::Push(_))) => {
+                    optimized.pop();
+                    continue;
+                }
+                _ => {}
+            }
+            optimized.push(instr);
+        }
+        Program(optimized)
+    }
 
     /// Converts the program to a plain vector of instructions.
     pub fn to_vec(self) -> Vec<Instruction> {
@@ -138,6 +351,53 @@ impl Program {
         self
     }
 
+    /// Adds instructions that spend the `contract` with a `signtx` signature
+    /// by its current predicate, and re-lock its payload unchanged under the new `predicate`
+    /// (e.g. to upgrade the key governing the contract).
+    /// The transaction log records the spent contract as an input and the new one as an output,
+    /// so the transfer is atomic with the rest of the transaction.
+    pub fn relock(&mut self, contract: Contract, predicate: Predicate) -> &mut Program {
+        let k = contract.payload.len();
+        self.push(contract) // stack: input-data
+            .input() // stack: contract
+            .sign_tx() // stack: payload...
+            .push(predicate) // stack: payload..., predicate
+            .output(k) // stack: empty
+    }
+
+    /// Adds instructions that fail unless the transaction's `maxtime` is not greater
+    /// than `deadline_ms`: the difference `deadline_ms - tx.maxtime` must be
+    /// a non-negative 64-bit integer, otherwise the VM fails with `VMError::InvalidBitrange`.
+    pub fn check_maxtime(&mut self, deadline_ms: u64) -> &mut Program {
+        self.push(deadline_ms)
+            .r#const() // stack: deadline
+            .maxtime()
+            .neg()
+            .add() // stack: deadline - maxtime
+            .range() // stack: deadline - maxtime (checked to be non-negative)
+            .maxtime()
+            .add() // stack: deadline
+            .push(deadline_ms)
+            .r#const()
+            .eq()
+            .verify() // stack: empty
+    }
+
+    /// Locks `k` items from the stack in an output that can be spent only by a transaction
+    /// with `maxtime` not greater than `deadline_ms`, and then only by satisfying `owner`.
+    /// The output is locked with `PredicateTree::with_deadline(owner, deadline_ms, k, blinding_key)`,
+    /// which the spender recreates to unlock it.
+    pub fn output_with_deadline(
+        &mut self,
+        k: usize,
+        owner: Predicate,
+        deadline_ms: u64,
+        blinding_key: [u8; 32],
+    ) -> Result<&mut Program, VMError> {
+        let tree = PredicateTree::with_deadline(owner, deadline_ms, k, blinding_key)?;
+        Ok(self.push(Predicate::Tree(tree)).output(k))
+    }
+
     /// Takes predicate tree and index of program in Merkle tree to verify
     /// the program's membership in that Merkle tree and call the program.
     pub fn choose_call(
@@ -145,7 +405,18 @@ impl Program {
         pred_tree: PredicateTree,
         prog_index: usize,
     ) -> Result<&mut Program, VMError> {
-        let (call_proof, program) = pred_tree.create_callproof(prog_index)?;
+        self.or_call(&pred_tree, prog_index)
+    }
+
+    /// Picks the branch `branch` of the predicate tree (e.g. one made with `PredicateTree::disjunction`),
+    /// and adds the call proof, the branch program and the `call` instruction.
+    /// Expects the contract locked with the predicate tree on top of the stack.
+    pub fn or_call(
+        &mut self,
+        pred_tree: &PredicateTree,
+        branch: usize,
+    ) -> Result<&mut Program, VMError> {
+        let (call_proof, program) = pred_tree.create_callproof(branch)?;
         self.push(String::Opaque(call_proof.to_bytes()))
             .program(program)
             .call();
@@ -153,12 +424,131 @@ impl Program {
     }
 }
 
+/// Returns the number of items the instruction requires on top of the stack,
+/// and the number of items it leaves in their place,
+/// or `None` if the latter depends on a contract payload.
+fn stack_effect(instr: &Instruction) -> (usize, Option<usize>) {
+    let (requested, remaining) = match instr {
+        Instruction::Push(_) | Instruction::Program(_) | Instruction::Alloc(_) => (0, 1),
+        Instruction::Mintime | Instruction::Maxtime => (0, 1),
+        Instruction::Drop
+        | Instruction::Verify
+        | Instruction::Retire
+        | Instruction::Log
+        | Instruction::Fee => (1, 0),
+        Instruction::Dup(i) => (i.saturating_add(1), i.saturating_add(2)),
+        Instruction::Roll(i) => (i.saturating_add(1), i.saturating_add(1)),
+        Instruction::Const
+        | Instruction::Var
+        | Instruction::Expr
+        | Instruction::Neg
+        | Instruction::Range(_)
+        | Instruction::Not
+        | Instruction::Input => (1, 1),
+        Instruction::Add
+        | Instruction::Mul
+        | Instruction::Eq
+        | Instruction::And
+        | Instruction::Or
+        | Instruction::Unblind => (2, 1),
+        Instruction::Issue => (4, 1),
+        Instruction::Borrow => (2, 2),
+        Instruction::Cloak(m, n) => (m.saturating_add(n.saturating_mul(2)), *n),
+        Instruction::Output(k) => (k.saturating_add(1), 0),
+        Instruction::Contract(k) => (k.saturating_add(1), 1),
+        Instruction::Ext(_) => (0, 0),
+        Instruction::Signtx => return (1, None),
+        Instruction::Call | Instruction::Signid | Instruction::Signtag => return (3, None),
+    };
+    (requested, Some(remaining))
+}
+
+/// Parses a line of the assembly text format, split into the mnemonic and the operands.
+fn parse_asm_instruction(name: &str, operands: &[&str]) -> Option<Instruction> {
+    let number = |i: usize| operands.get(i).and_then(|s| parse_asm_number(s));
+    let (instr, arity) = if name == "ext" {
+        let code = number(0).filter(|code| *code <= 0xff)? as u8;
+        if Opcode::from_u8(code).is_some() {
+            return None;
+        }
+        (Instruction::Ext(code), 1)
+    } else {
+        match Opcode::from_mnemonic(name)? {
+            Opcode::Push => {
+                let operand = operands.get(0)?;
+                let data = match parse_asm_bytes(operand) {
+                    Some(bytes) => String::Opaque(bytes),
+                    None => String::from(operand.parse::<u64>().ok()?),
+                };
+                (Instruction::Push(data), 1)
+            }
+            Opcode::Program => {
+                let bytecode = parse_asm_bytes(operands.get(0)?)?;
+                (Instruction::Program(ProgramItem::Bytecode(bytecode)), 1)
+            }
+            Opcode::Dup => (Instruction::Dup(number(0)?), 1),
+            Opcode::Roll => (Instruction::Roll(number(0)?), 1),
+            Opcode::Range => (Instruction::Range(BitRange::new(number(0)?)?), 1),
+            Opcode::Cloak => (Instruction::Cloak(number(0)?, number(1)?), 2),
+            Opcode::Output => (Instruction::Output(number(0)?), 1),
+            Opcode::Contract => (Instruction::Contract(number(0)?), 1),
+            op => {
+                // The remaining instructions have no immediate data.
+                let instr = SliceReader::parse(&[op.to_u8()], |r| Instruction::parse(r)).ok()?;
+                (instr, 0)
+            }
+        }
+    };
+    if operands.len() != arity {
+        return None;
+    }
+    Some(instr)
+}
+
+/// Parses a decimal or `0x`-prefixed hex number that fits the 32-bit immediate.
+fn parse_asm_number(s: &str) -> Option<usize> {
+    let n = if s.starts_with("0x") {
+        let digits = &s[2..];
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        u32::from_str_radix(digits, 16).ok()?
+    } else {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse::<u32>().ok()?
+    };
+    Some(n as usize)
+}
+
+/// Parses `0x`-prefixed hex-encoded bytes.
+fn parse_asm_bytes(s: &str) -> Option<Vec<u8>> {
+    if !s.starts_with("0x") {
+        return None;
+    }
+    let digits = &s.as_bytes()[2..];
+    if digits.len() % 2 != 0 || !digits.iter().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(core::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// Formats bytes as `0x`-prefixed hex.
+fn asm_bytes(bytes: &[u8]) -> std::string::String {
+    let digits: std::string::String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("0x{}", digits)
+}
+
 impl Encodable for ProgramItem {
-    fn encode(&self, buf: &mut Vec<u8>) {
+    fn encode<S: Sink>(&self, buf: &mut S) {
         match self {
             ProgramItem::Program(prog) => prog.encode(buf),
             ProgramItem::Bytecode(bytes) => {
-                buf.extend_from_slice(&bytes);
+                encoding::write_bytes(&bytes, buf);
             }
         }
     }
@@ -175,6 +565,20 @@ impl ProgramItem {
         self.encode_to_vec()
     }
 
+    /// Returns the canonical bytecode of the program item, identical for
+    /// the `Program` and the `Bytecode` views of the same program.
+    /// Well-formed bytecode is normalized by parsing and re-encoding it,
+    /// malformed bytecode is returned as-is.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        match self {
+            ProgramItem::Program(prog) => prog.to_bytes(),
+            ProgramItem::Bytecode(bytes) => match Program::parse(bytes) {
+                Ok(prog) => prog.to_bytes(),
+                Err(_) => bytes.clone(),
+            },
+        }
+    }
+
     /// Downcasts a program item into a program.
     pub fn to_program(self) -> Result<Program, VMError> {
         match self {
@@ -194,6 +598,14 @@ impl ProgramItem {
     }
 }
 
+impl PartialEq for ProgramItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.canonical_bytes() == other.canonical_bytes()
+    }
+}
+
+impl Eq for ProgramItem {}
+
 impl From<Program> for ProgramItem {
     fn from(x: Program) -> Self {
         ProgramItem::Program(x)
@@ -214,3 +626,224 @@ impl MerkleItem for Program {
         t.append_message(b"program", &self.to_bytes());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constraints::Commitment;
+    use crate::predicate::Predicate;
+    use curve25519_dalek::scalar::Scalar;
+    use musig::VerificationKey;
+
+    #[test]
+    fn program_item_equality() {
+        let prog = Program::build(|p| {
+            p.push(Commitment::blinded(5u64))
+                .var()
+                .push(Predicate::Key(VerificationKey::from_secret(&Scalar::from(
+                    1u64,
+                ))))
+                .program(Program::build(|p| p.drop()))
+                .cloak(1, 2)
+                .output(1)
+        });
+        let item = ProgramItem::Program(prog.clone());
+        let bytecode = ProgramItem::Bytecode(prog.to_bytes());
+        let reparsed = ProgramItem::Program(Program::parse(&prog.to_bytes()).unwrap());
+
+        assert_eq!(item.canonical_bytes(), bytecode.canonical_bytes());
+        assert_eq!(item, bytecode);
+        assert_eq!(bytecode, reparsed);
+        assert_eq!(item, reparsed);
+
+        let other = ProgramItem::Program(Program::build(|p| p.drop()));
+        assert_ne!(item, other);
+        assert_ne!(bytecode, other);
+
+        // Malformed bytecode is compared as-is.
+        let truncated = ProgramItem::Bytecode(vec![0x00, 0x05]);
+        assert_eq!(truncated, ProgramItem::Bytecode(vec![0x00, 0x05]));
+        assert_ne!(truncated, ProgramItem::Bytecode(vec![0x00]));
+    }
+
+    #[test]
+    fn verify_structure() {
+        let pred = || Predicate::Key(VerificationKey::from_secret(&Scalar::from(1u64)));
+
+        let program = Program::build(|p| {
+            p.push(Commitment::blinded(5u64))
+                .var()
+                .push(Commitment::blinded(1u64))
+                .var()
+                .push(String::Opaque(vec![]))
+                .push(pred())
+                .issue()
+                .sign_tx()
+                .output(1) // not checked after `signtx`
+        });
+        assert_eq!(program.verify_structure(), Ok(()));
+
+        // `output:1` has the predicate but no value to put in the payload.
+        let program = Program::build(|p| {
+            p.push(String::Opaque(vec![1]))
+                .drop()
+                .push(pred())
+                .output(1)
+        });
+        assert_eq!(
+            program.verify_structure(),
+            Err(VMError::StackUnderflowAt {
+                index: 3,
+                requested: 2,
+                available: 1,
+            })
+        );
+
+        let program = Program::build(|p| p.push(pred()).call());
+        assert_eq!(
+            program.verify_structure(),
+            Err(VMError::StackUnderflowAt {
+                index: 1,
+                requested: 3,
+                available: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn asm_roundtrip() {
+        let asm = "\
+push 0xdead
+push 0x
+program 0x0203
+drop
+dup 0
+roll 2
+alloc
+range 64
+cloak 2 3
+output 1
+contract 0
+ext 0x40
+signtx
+";
+        let program = Program::from_asm(asm).unwrap();
+        assert_eq!(program.to_asm(), asm);
+        assert_eq!(Program::parse(&program.to_bytes()).unwrap().to_asm(), asm);
+
+        // Decimal and hex immediates, comments and empty lines.
+        let program = Program::from_asm("# header\n\n  cloak 0x2 3 # 2 inputs\npush 5\n").unwrap();
+        assert_eq!(
+            program.to_asm(),
+            format!("cloak 2 3\npush 0x05{}\n", "00".repeat(31))
+        );
+
+        let err = |line| Some(VMError::InvalidAssembly { line });
+        assert_eq!(Program::from_asm("drop\ndup").err(), err(2));
+        assert_eq!(Program::from_asm("drop 1").err(), err(1));
+        assert_eq!(Program::from_asm("push dead").err(), err(1));
+        assert_eq!(Program::from_asm("push 0xabc").err(), err(1));
+        assert_eq!(Program::from_asm("range 65").err(), err(1));
+        assert_eq!(Program::from_asm("output 0x100000000").err(), err(1));
+        assert_eq!(Program::from_asm("ext 0x02").err(), err(1));
+        assert_eq!(Program::from_asm("\nfrobnicate").err(), err(2));
+    }
+
+    #[test]
+    fn length_prefixed_program() {
+        let program = Program::build(|p| {
+            p.push(String::Opaque(vec![1, 2, 3]))
+                .program(Program::build(|p| p.drop()))
+                .dup(0)
+                .verify()
+        });
+        let mut buf = Vec::new();
+        encoding::write_u64(7, &mut buf);
+        program.encode_with_length(&mut buf);
+        encoding::write_u64(8, &mut buf);
+
+        let (before, decoded, after) = SliceReader::parse(&buf, |r| {
+            Ok((
+                r.read_u64()?,
+                Program::decode_with_length(r)?,
+                r.read_u64()?,
+            ))
+        })
+        .unwrap();
+        assert_eq!(before, 7);
+        assert_eq!(decoded.to_bytes(), program.to_bytes());
+        assert_eq!(after, 8);
+
+        // Length prefix exceeding the remaining data.
+        let mut buf = Vec::new();
+        encoding::write_size(100, &mut buf);
+        program.encode(&mut buf);
+        assert_eq!(
+            SliceReader::parse(&buf, |r| Program::decode_with_length(r)).unwrap_err(),
+            VMError::FormatError
+        );
+    }
+
+    #[test]
+    fn program_too_large() {
+        // `verify` instructions
+        let data = vec![Instruction::Verify.opcode(); MAX_PROGRAM_BYTES];
+        assert_eq!(
+            Program::parse(&data).unwrap().to_vec().len(),
+            MAX_PROGRAM_BYTES
+        );
+
+        let data = vec![Instruction::Verify.opcode(); MAX_PROGRAM_BYTES + 1];
+        assert_eq!(Program::parse(&data).unwrap_err(), VMError::ProgramTooLarge);
+    }
+
+    #[test]
+    fn optimize() {
+        let program = Program::build(|p| {
+            p.push(String::Opaque(vec![1]))
+                .dup(0) // removed with the following `drop`
+                .drop()
+                .roll(0) // removed
+                .push(String::Opaque(vec![2])) // removed with the following `drop`
+                .drop()
+                .dup(0)
+                .roll(1)
+        });
+        let expected = Program::build(|p| p.push(String::Opaque(vec![1])).dup(0).roll(1));
+        assert_eq!(program.optimize().to_bytes(), expected.to_bytes());
+
+        // Pairs exposed by removing other pairs are removed too.
+        let program = Program::build(|p| p.push(String::Opaque(vec![1])).dup(0).drop().drop());
+        assert_eq!(program.optimize().to_vec().len(), 0);
+
+        // `drop` after other instructions is kept.
+        let program = Program::build(|p| p.roll(1).drop().roll(2));
+        assert_eq!(program.clone().optimize().to_bytes(), program.to_bytes());
+    }
+
+    #[test]
+    fn program_cost() {
+        let prog = Program::build(|p| {
+            p.push(Commitment::blinded(1u64))
+                .var()
+                .dup(0)
+                .expr()
+                .range()
+                .drop()
+                .cloak(2, 3)
+                .program(Program::build(|inner| inner.mul().mul()))
+                .call()
+        });
+        let builder_cost: u64 = prog.clone().to_vec().iter().map(|i| i.cost()).sum();
+        let parsed = Program::parse(&prog.to_bytes()).unwrap();
+        assert_eq!(parsed.total_cost(), builder_cost);
+        assert_eq!(prog.total_cost(), builder_cost);
+
+        // Heavier instructions allocate more multipliers.
+        let range = Instruction::Range(BitRange::max());
+        assert!(Instruction::Cloak(2, 3).cost() > range.cost());
+        assert!(range.cost() > Instruction::Range(BitRange::new(8).unwrap()).cost());
+        assert!(range.cost() > Instruction::Dup(0).cost());
+        assert_eq!(Instruction::Dup(0).cost(), Instruction::Drop.cost());
+    }
+}
@@ -1,6 +1,6 @@
 use bulletproofs::r1cs;
 use bulletproofs::r1cs::ConstraintSystem;
-use bulletproofs::{BulletproofGens, PedersenGens};
+use bulletproofs::BulletproofGens;
 use curve25519_dalek::ristretto::CompressedRistretto;
 use merlin::Transcript;
 use musig::VerificationKey;
@@ -10,11 +10,13 @@ use crate::constraints::Commitment;
 use crate::contract::ContractID;
 use crate::encoding::Encodable;
 use crate::errors::VMError;
-use crate::ops::Instruction;
+use crate::gens::{pedersen_gens, r1cs_proof_error};
+use crate::ops::{Instruction, MAX_PUSH_BYTES};
 use crate::point_ops::PointOp;
 use crate::predicate::Predicate;
-use crate::program::{Program, ProgramItem};
-use crate::tx::{TxHeader, UnsignedTx};
+use crate::program::{Program, ProgramItem, MAX_PROGRAM_BYTES};
+use crate::transcript::TranscriptProtocol;
+use crate::tx::{TxHeader, UnsignedTx, VmStats};
 use crate::vm::{Delegate, VM};
 /// This is the entry point API for creating a transaction.
 /// Prover passes the list of instructions through the VM,
@@ -24,6 +26,7 @@ pub struct Prover<'t, 'g> {
     // TBD: use Multikey as a witness thing
     signtx_items: Vec<(VerificationKey, ContractID)>,
     cs: r1cs::Prover<'t, 'g>,
+    instructions: usize,
 }
 
 pub(crate) struct ProverRun {
@@ -62,7 +65,11 @@ impl<'t, 'g> Delegate<r1cs::Prover<'t, 'g>> for Prover<'t, 'g> {
         &mut self,
         run: &mut Self::RunType,
     ) -> Result<Option<Instruction>, VMError> {
-        Ok(run.program.pop_front())
+        let instr = run.program.pop_front();
+        if instr.is_some() {
+            self.instructions += 1;
+        }
+        Ok(instr)
     }
 
     fn new_run(&self, data: ProgramItem) -> Result<Self::RunType, VMError> {
@@ -80,43 +87,88 @@ impl<'t, 'g> Prover<'t, 'g> {
     /// Builds a transaction with a given list of instructions and a `TxHeader`.
     /// Returns a transaction `Tx` along with its ID (`TxID`) and a transaction log (`TxLog`).
     /// Fails if the input program is malformed, or some witness data is missing.
+    /// `bp_gens` should be the canonical generators returned by `zkvm::gens`,
+    /// same as for `Verifier::verify_tx`.
+    /// Fails with `VMError::InsufficientGens` before running the program if its `cloak`
+    /// instructions alone need more multipliers than `bp_gens` support, and with
+    /// `VMError::InvalidGenerators` if the whole constraint system does not fit.
     pub fn build_tx(
         program: Program,
         header: TxHeader,
         bp_gens: &BulletproofGens,
+    ) -> Result<UnsignedTx, VMError> {
+        Self::build_tx_for_network(program, header, b"", bp_gens)
+    }
+
+    /// Builds a transaction for the network identified by the `network` label.
+    /// Issued flavors, signatures and the R1CS proof are bound to the network,
+    /// so the transaction cannot be replayed on a network with a different label.
+    /// The `ZkVM.signtx` transcript used to sign the resulting txid
+    /// must be bound to the same label via `TranscriptProtocol::commit_network`.
+    pub fn build_tx_for_network(
+        program: Program,
+        header: TxHeader,
+        network: &[u8],
+        bp_gens: &BulletproofGens,
     ) -> Result<UnsignedTx, VMError> {
         // Prepare the constraint system
         let mut r1cs_transcript = Transcript::new(b"ZkVM.r1cs");
-        let pc_gens = PedersenGens::default();
+        r1cs_transcript.commit_network(network);
+        let pc_gens = pedersen_gens();
         let cs = r1cs::Prover::new(&pc_gens, &mut r1cs_transcript);
 
         // Serialize the tx program
         let mut bytecode = Vec::new();
         program.encode(&mut bytecode);
+        if bytecode.len() > MAX_PROGRAM_BYTES {
+            return Err(VMError::ProgramTooLarge);
+        }
+        if program.max_push_length() > MAX_PUSH_BYTES {
+            return Err(VMError::PushTooLarge);
+        }
+
+        // Fail early if the generators cannot fit even the cloaks declared by the program.
+        // This is a best-effort check: cloaks in nested programs are counted
+        // only when they are executed, by the final check of the R1CS proof.
+        let needed = program.cloak_multiplier_count().next_power_of_two();
+        if needed > bp_gens.gens_capacity {
+            return Err(VMError::InsufficientGens {
+                needed,
+                available: bp_gens.gens_capacity,
+            });
+        }
 
         let mut prover = Prover {
             signtx_items: Vec::new(),
             cs,
+            instructions: 0,
         };
 
         let vm = VM::new(
             header,
+            network,
             ProverRun {
                 program: program.to_vec().into(),
             },
             &mut prover,
         );
 
-        let (txid, txlog) = vm.run()?;
+        let (txid, txlog, fee) = vm.run()?;
 
         // Commit txid so that the proof is bound to the entire transaction, not just the constraint system.
         prover.cs.transcript().append_message(b"ZkVM.txid", &txid.0);
 
         // Generate the R1CS proof
-        let proof = prover
+        let multipliers = prover.cs.metrics().multipliers;
+        let (proof, metrics) = prover
             .cs
-            .prove(bp_gens)
-            .map_err(|_| VMError::InvalidR1CSProof)?;
+            .prove_and_return_metrics(bp_gens)
+            .map_err(|e| r1cs_proof_error(e, multipliers, bp_gens))?;
+        let stats = VmStats {
+            multipliers: metrics.multipliers,
+            constraints: metrics.constraints,
+            instructions: prover.instructions,
+        };
 
         // Defer signing of the transaction to the UnsignedTx API.
         Ok(UnsignedTx {
@@ -125,6 +177,8 @@ impl<'t, 'g> Prover<'t, 'g> {
             proof,
             txid,
             txlog,
+            fee,
+            stats,
             signing_instructions: prover.signtx_items,
         })
     }
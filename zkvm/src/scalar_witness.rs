@@ -1,10 +1,11 @@
 //! Arithmetic and conversion API for ScalarWitness.
 
 use curve25519_dalek::scalar::Scalar;
-use spacesuit::SignedInteger;
+use spacesuit::{BitRange, SignedInteger};
 
 use crate::encoding;
 use crate::encoding::Encodable;
+use crate::encoding::Sink;
 use crate::errors::VMError;
 use std::ops::{Add, Mul, Neg, Sub};
 use std::u64;
@@ -20,7 +21,7 @@ pub enum ScalarWitness {
 
 impl Encodable for ScalarWitness {
     /// Converts to a scalar and encodes it to a vec of bytes.
-    fn encode(&self, buf: &mut Vec<u8>) {
+    fn encode<S: Sink>(&self, buf: &mut S) {
         encoding::write_bytes(&self.to_scalar().to_bytes(), buf);
     }
     /// Returns the number of bytes needed to serialize the ScalarWitness.
@@ -30,11 +31,17 @@ impl Encodable for ScalarWitness {
 }
 
 impl ScalarWitness {
-    /// Converts the witness to an integer if it is an integer
-    pub fn to_integer(self) -> Result<SignedInteger, VMError> {
+    /// Converts the witness to an integer.
+    /// A scalar is converted only if it or its negation fits in u64,
+    /// that is, if it lies within the range of `SignedInteger`.
+    pub fn to_integer(self) -> Option<SignedInteger> {
         match self {
-            ScalarWitness::Integer(i) => Ok(i),
-            ScalarWitness::Scalar(_) => Err(VMError::TypeNotSignedInteger),
+            ScalarWitness::Integer(i) => Some(i),
+            ScalarWitness::Scalar(s) => match (scalar_to_u64(&s), scalar_to_u64(&-s)) {
+                (Some(x), _) => Some(x.into()),
+                (None, Some(x)) => Some(-SignedInteger::from(x)),
+                (None, None) => None,
+            },
         }
     }
 
@@ -46,19 +53,30 @@ impl ScalarWitness {
         }
     }
 
-    /// Converts `Option<ScalarWitness>` into optional integer if it is one.
+    /// Converts `Option<ScalarWitness>` into optional integer (see `to_integer`).
+    /// Fails with `VMError::TypeNotSignedInteger` if the witness is out of the integer range.
     pub fn option_to_integer(assignment: Option<Self>) -> Result<Option<SignedInteger>, VMError> {
         match assignment {
             None => Ok(None),
-            Some(ScalarWitness::Integer(i)) => Ok(Some(i)),
-            Some(ScalarWitness::Scalar(_)) => Err(VMError::TypeNotSignedInteger),
+            Some(w) => w
+                .to_integer()
+                .map(Some)
+                .ok_or(VMError::TypeNotSignedInteger),
         }
     }
 
     /// Returns true if the scalar fits in u64.
     pub fn in_range(self) -> bool {
-        let scalar_bytes = self.to_scalar().to_bytes();
-        (&scalar_bytes[8..32]).iter().all(|v| v == &0)
+        scalar_to_u64(&self.to_scalar()).is_some()
+    }
+
+    /// Returns true if the scalar fits in the given number of bits.
+    pub fn in_bit_range(self, n: BitRange) -> bool {
+        let bits: usize = n.into();
+        match scalar_to_u64(&self.to_scalar()) {
+            Some(x) => bits == 64 || x >> bits == 0,
+            None => false,
+        }
     }
 }
 
@@ -93,7 +111,7 @@ impl Sub for ScalarWitness {
     type Output = ScalarWitness;
 
     fn sub(self, rhs: ScalarWitness) -> ScalarWitness {
-        -self + rhs
+        self + (-rhs)
     }
 }
 
@@ -133,6 +151,17 @@ impl Into<Scalar> for ScalarWitness {
     }
 }
 
+/// Returns the scalar as u64 if it fits in 64 bits.
+fn scalar_to_u64(scalar: &Scalar) -> Option<u64> {
+    let bytes = scalar.as_bytes();
+    if bytes[8..32].iter().any(|b| *b != 0) {
+        return None;
+    }
+    let mut x = [0u8; 8];
+    x.copy_from_slice(&bytes[0..8]);
+    Some(u64::from_le_bytes(x))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -156,9 +185,40 @@ mod tests {
         let x = -ScalarWitness::Integer(24.into());
         assert_eq!((-x.to_integer().unwrap()).to_u64().unwrap(), 24);
 
-        // err
+        // ok small scalar
         let x = ScalarWitness::Scalar(24u64.into());
-        assert_eq!(x.to_integer().unwrap_err(), VMError::TypeNotSignedInteger);
+        assert_eq!(x.to_integer(), Some(SignedInteger::from(24u64)));
+
+        // ok negative small scalar
+        let x = ScalarWitness::Scalar(-Scalar::from(24u64));
+        assert_eq!(x.to_integer(), Some(-SignedInteger::from(24u64)));
+    }
+
+    #[test]
+    fn to_integer_boundary() {
+        let max = Scalar::from(u64::MAX);
+        let two_64 = max + Scalar::one();
+
+        // 2^64 - 1 is the largest integer
+        assert_eq!(
+            ScalarWitness::Scalar(max).to_integer(),
+            Some(SignedInteger::from(u64::MAX))
+        );
+        assert_eq!(
+            ScalarWitness::Scalar(-max).to_integer(),
+            Some(-SignedInteger::from(u64::MAX))
+        );
+
+        // 2^64 is out of range
+        assert_eq!(ScalarWitness::Scalar(two_64).to_integer(), None);
+        assert_eq!(ScalarWitness::Scalar(-two_64).to_integer(), None);
+
+        // to_scalar is the inverse of to_integer
+        let x = ScalarWitness::Scalar(-max);
+        assert_eq!(
+            ScalarWitness::Integer(x.to_integer().unwrap()).to_scalar(),
+            -max
+        );
     }
 
     #[test]
@@ -195,6 +255,15 @@ mod tests {
         );
         assert_eq!(
             ScalarWitness::option_to_integer(Some(ScalarWitness::Scalar(24u64.into()))),
+            Ok(Some(SignedInteger::from(24u64)))
+        );
+        assert_eq!(
+            ScalarWitness::option_to_integer(Some(ScalarWitness::Scalar(-Scalar::from(24u64)))),
+            Ok(Some(-SignedInteger::from(24u64)))
+        );
+        let two_pow_64 = Scalar::from(u64::MAX) + Scalar::one();
+        assert_eq!(
+            ScalarWitness::option_to_integer(Some(ScalarWitness::Scalar(two_pow_64))),
             Err(VMError::TypeNotSignedInteger)
         );
     }
@@ -223,6 +292,58 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sub() {
+        assert_eq!(
+            ScalarWitness::from(7u64) - ScalarWitness::from(5u64),
+            ScalarWitness::from(2u64)
+        );
+
+        assert_eq!(
+            ScalarWitness::from(5u64) - ScalarWitness::from(7u64),
+            -ScalarWitness::from(2u64)
+        );
+
+        assert_eq!(
+            ScalarWitness::from(1000u64) - ScalarWitness::from(Scalar::from(0xffu64)),
+            ScalarWitness::from(Scalar::from(1000u64) - Scalar::from(0xffu64))
+        );
+
+        assert_eq!(
+            ScalarWitness::from(Scalar::from(0xffu64)) - ScalarWitness::from(1000u64),
+            ScalarWitness::from(Scalar::from(0xffu64) - Scalar::from(1000u64))
+        );
+    }
+
+    #[test]
+    fn mixed_variants() {
+        let int = ScalarWitness::from(3u64);
+        let scalar = ScalarWitness::from(Scalar::from(3u64));
+        let is_integer = |x: ScalarWitness| match x {
+            ScalarWitness::Integer(_) => true,
+            ScalarWitness::Scalar(_) => false,
+        };
+
+        // integers stay integers
+        assert!(is_integer(int + int));
+        assert!(is_integer(int - int));
+        assert!(is_integer(int * int));
+        assert!(is_integer(-int));
+
+        // mixing with a scalar promotes to a scalar
+        for &(a, b) in [(int, scalar), (scalar, int), (scalar, scalar)].iter() {
+            assert!(!is_integer(a + b));
+            assert!(!is_integer(a - b));
+            assert!(!is_integer(a * b));
+        }
+        assert!(!is_integer(-scalar));
+
+        // results agree regardless of the variants
+        assert_eq!((int + scalar).to_scalar(), (int + int).to_scalar());
+        assert_eq!((scalar - int).to_scalar(), (int - int).to_scalar());
+        assert_eq!((scalar * int).to_scalar(), (int * int).to_scalar());
+    }
+
     #[test]
     fn mul() {
         assert_eq!(
@@ -262,5 +383,10 @@ mod tests {
             -ScalarWitness::from(u64::MAX) + (-ScalarWitness::from(u64::MAX)),
             ScalarWitness::from(-Scalar::from(u64::MAX) - Scalar::from(u64::MAX))
         );
+
+        assert_eq!(
+            -ScalarWitness::from(u64::MAX) - ScalarWitness::from(u64::MAX),
+            ScalarWitness::from(-Scalar::from(u64::MAX) - Scalar::from(u64::MAX))
+        );
     }
 }
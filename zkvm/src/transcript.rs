@@ -1,4 +1,15 @@
 //! Defines a `TranscriptProtocol` trait for using a Merlin transcript.
+//!
+//! The VM uses these methods with the following domain separation labels:
+//! * `ZkVM.r1cs`: constraint system of the transaction, with the txid appended as `ZkVM.txid`;
+//! * `ZkVM.signtx`, `ZkVM.signid`, `ZkVM.signtag`: messages signed by the corresponding instructions;
+//! * `ZkVM.issue` and `ZkVM.metadata`: asset flavors;
+//! * `ZkVM.taproot`: predicate tree commitments;
+//! * `ZkVM.contractid`, `ZkVM.ratchet-anchor`: contract IDs and anchors;
+//! * `ZkVM.deterministic-value`: blinding factors of values derived from a wallet seed;
+//! * `ZkVM.txid`, `ZkVM.utreexo`, `ZkVM.blockheader`: merkle trees and block headers.
+//!
+//! Custom gadgets should use their own labels to avoid colliding with the ones above.
 
 use curve25519_dalek::ristretto::CompressedRistretto;
 use curve25519_dalek::scalar::Scalar;
@@ -6,13 +17,37 @@ use merlin::Transcript;
 
 /// Extension trait to the Merlin transcript API that allows committing scalars and points and
 /// generating challenges as scalars.
+///
+/// Custom gadgets can use it to derive challenges the same way the VM does:
+///
+/// ```
+/// use curve25519_dalek::scalar::Scalar;
+/// use merlin::Transcript;
+/// use zkvm::{Commitment, TranscriptProtocol};
+///
+/// let commitment = Commitment::blinded_with_factor(42u64, Scalar::from(7u64));
+///
+/// let mut transcript = Transcript::new(b"MyGadget");
+/// transcript.commit_point(b"V", &commitment.to_point());
+/// let challenge = transcript.challenge_scalar(b"c");
+///
+/// // Same sequence of operations yields the same challenge.
+/// let mut transcript = Transcript::new(b"MyGadget");
+/// transcript.commit_point(b"V", &commitment.to_point());
+/// assert_eq!(transcript.challenge_scalar(b"c"), challenge);
+/// ```
 pub trait TranscriptProtocol {
-    /// Commit a `scalar` with the given `label`.
+    /// Commit a `scalar` with the given `label`, as its 32-byte little-endian encoding.
     fn commit_scalar(&mut self, label: &'static [u8], scalar: &Scalar);
-    /// Commit a `point` with the given `label`.
+    /// Commit a `point` with the given `label`, as its 32-byte compressed encoding.
     fn commit_point(&mut self, label: &'static [u8], point: &CompressedRistretto);
-    /// Compute a `label`ed challenge variable.
+    /// Compute a `label`ed challenge variable
+    /// by reducing 64 bytes of the challenge modulo the group order.
     fn challenge_scalar(&mut self, label: &'static [u8]) -> Scalar;
+    /// Binds the transcript to a `network` label, so that signatures and flavors
+    /// produced for one network are not valid on another one.
+    /// The empty label denotes the default network and leaves the transcript unchanged.
+    fn commit_network(&mut self, network: &[u8]);
 }
 
 impl TranscriptProtocol for Transcript {
@@ -30,4 +65,10 @@ impl TranscriptProtocol for Transcript {
 
         Scalar::from_bytes_mod_order_wide(&buf)
     }
+
+    fn commit_network(&mut self, network: &[u8]) {
+        if !network.is_empty() {
+            self.append_message(b"network", network);
+        }
+    }
 }
@@ -2,37 +2,51 @@ use bulletproofs::r1cs::R1CSProof;
 use curve25519_dalek::ristretto::CompressedRistretto;
 use merlin::Transcript;
 use musig::{Signature, VerificationKey};
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 use crate::contract::{Contract, ContractID};
 use crate::encoding;
 use crate::encoding::Encodable;
+use crate::encoding::Sink;
 use crate::encoding::SliceReader;
 use crate::errors::VMError;
 use crate::merkle::{MerkleItem, MerkleTree};
+use crate::program::MAX_PROGRAM_BYTES;
 use crate::transcript::TranscriptProtocol;
 
 /// Transaction log. `TxLog` is a type alias for `Vec<TxEntry>`.
 pub type TxLog = Vec<TxEntry>;
 
 /// Transaction ID is a unique 32-byte identifier of a transaction
-#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TxID(pub [u8; 32]);
 
-/// Entry in a transaction log
-#[allow(missing_docs)]
+/// Entry in a transaction log.
+/// Issuances and retirements carry the quantity and flavor commitments of the value,
+/// so the supply changes can be audited against the entries.
 #[derive(Clone, Debug)]
 pub enum TxEntry {
+    /// Transaction header, always the first entry.
     Header(TxHeader),
+    /// Issued value: commitments to its quantity and flavor, created by `issue`.
     Issue(CompressedRistretto, CompressedRistretto),
+    /// Retired value: commitments to its quantity and flavor, created by `retire`.
     Retire(CompressedRistretto, CompressedRistretto),
+    /// ID of the utxo spent by `input`.
     Input(ContractID),
+    /// Contract added to the utxo set by `output`.
     Output(Contract),
+    /// Arbitrary data logged by `log`.
     Data(Vec<u8>),
+    /// Fee paid by the transaction, logged by `fee`.
+    Fee(u64),
 }
 
 /// Header metadata for the transaction
-#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TxHeader {
     /// Version of the transaction
     pub version: u64,
@@ -44,6 +58,21 @@ pub struct TxHeader {
     pub maxtime_ms: u64,
 }
 
+/// Statistics of the constraint system built by the VM,
+/// e.g. for choosing the capacity of `BulletproofGens`.
+/// Prover and verifier produce identical statistics for the same transaction.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct VmStats {
+    /// Number of R1CS multipliers
+    pub multipliers: usize,
+
+    /// Number of R1CS constraints
+    pub constraints: usize,
+
+    /// Number of instructions executed, including the nested programs
+    pub instructions: usize,
+}
+
 /// Instance of a transaction that is not signed yet.
 #[derive(Clone)]
 pub struct UnsignedTx {
@@ -62,13 +91,20 @@ pub struct UnsignedTx {
     /// Log of tx entries
     pub txlog: TxLog,
 
+    /// Total fee declared by the transaction
+    pub fee: u64,
+
+    /// Statistics of the constraint system
+    pub stats: VmStats,
+
     /// List of (key,contractid) pairs for multi-message signature
     /// TBD: change to some key witness type
     pub signing_instructions: Vec<(VerificationKey, ContractID)>,
 }
 
 /// Instance of a transaction that contains all necessary data to validate it.
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Tx {
     /// Header metadata
     pub header: TxHeader,
@@ -94,10 +130,16 @@ pub struct VerifiedTx {
 
     /// Transaction log: a list of changes to the blockchain state (UTXOs to delete/insert, etc.)
     pub log: TxLog,
+
+    /// Total fee declared by the transaction
+    pub fee: u64,
+
+    /// Statistics of the constraint system
+    pub stats: VmStats,
 }
 
 impl Encodable for TxHeader {
-    fn encode(&self, buf: &mut Vec<u8>) {
+    fn encode<S: Sink>(&self, buf: &mut S) {
         encoding::write_u64(self.version, buf);
         encoding::write_u64(self.mintime_ms, buf);
         encoding::write_u64(self.maxtime_ms, buf);
@@ -107,6 +149,21 @@ impl Encodable for TxHeader {
     }
 }
 impl TxHeader {
+    /// Checks that the time bounds of the transaction are consistent:
+    /// `mintime_ms` must not be greater than `maxtime_ms`.
+    pub fn check_time_bounds(&self) -> Result<(), VMError> {
+        if self.mintime_ms > self.maxtime_ms {
+            return Err(VMError::InvalidTimeBounds);
+        }
+        Ok(())
+    }
+
+    /// Returns true if the transaction can be included in a block with the given timestamp
+    /// (in milliseconds since the Unix epoch). Both bounds are inclusive.
+    pub fn is_valid_at(&self, time_ms: u64) -> bool {
+        self.mintime_ms <= time_ms && time_ms <= self.maxtime_ms
+    }
+
     fn decode<'a>(reader: &mut SliceReader<'a>) -> Result<Self, VMError> {
         Ok(TxHeader {
             version: reader.read_u64()?,
@@ -129,12 +186,12 @@ impl UnsignedTx {
 }
 
 impl Encodable for Tx {
-    fn encode(&self, buf: &mut Vec<u8>) {
+    fn encode<S: Sink>(&self, buf: &mut S) {
         self.header.encode(buf);
         encoding::write_size(self.program.len(), buf);
-        buf.extend(&self.program);
-        buf.extend_from_slice(&self.signature.to_bytes());
-        buf.extend_from_slice(&self.proof.to_bytes());
+        encoding::write_bytes(&self.program, buf);
+        encoding::write_bytes(&self.signature.to_bytes(), buf);
+        encoding::write_bytes(&self.proof.to_bytes(), buf);
     }
 
     /// Returns the size in bytes required to serialize the `Tx`.
@@ -150,7 +207,7 @@ impl Encodable for Tx {
 impl Tx {
     fn decode<'a>(r: &mut SliceReader<'a>) -> Result<Tx, VMError> {
         let header = TxHeader::decode(r)?;
-        let prog_len = r.read_size()?;
+        let prog_len = r.read_size_bounded(MAX_PROGRAM_BYTES)?;
         let program = r.read_bytes(prog_len)?.to_vec();
 
         let signature = Signature::from_bytes(r.read_u8x64()?).map_err(|_| VMError::FormatError)?;
@@ -215,6 +272,9 @@ impl MerkleItem for TxEntry {
             TxEntry::Data(data) => {
                 t.append_message(b"data", data);
             }
+            TxEntry::Fee(fee) => {
+                t.append_u64(b"fee", *fee);
+            }
         }
     }
 }
@@ -240,6 +300,39 @@ mod tests {
         ]
     }
 
+    #[test]
+    fn time_bounds() {
+        let header = TxHeader {
+            version: 1,
+            mintime_ms: 100,
+            maxtime_ms: 200,
+        };
+        assert_eq!(header.check_time_bounds(), Ok(()));
+        assert!(!header.is_valid_at(99));
+        assert!(header.is_valid_at(100));
+        assert!(header.is_valid_at(150));
+        assert!(header.is_valid_at(200));
+        assert!(!header.is_valid_at(201));
+
+        let header = TxHeader {
+            version: 1,
+            mintime_ms: 100,
+            maxtime_ms: 100,
+        };
+        assert_eq!(header.check_time_bounds(), Ok(()));
+        assert!(header.is_valid_at(100));
+
+        let header = TxHeader {
+            version: 1,
+            mintime_ms: 200,
+            maxtime_ms: 100,
+        };
+        assert_eq!(header.check_time_bounds(), Err(VMError::InvalidTimeBounds));
+        assert!(!header.is_valid_at(100));
+        assert!(!header.is_valid_at(150));
+        assert!(!header.is_valid_at(200));
+    }
+
     #[test]
     fn valid_txid_proof() {
         let (entry, txid, proof) = {
@@ -0,0 +1,106 @@
+//! Builder of the programs that spend contracts into new outputs.
+use std::collections::HashMap;
+
+use crate::constraints::Commitment;
+use crate::contract::Contract;
+use crate::errors::VMError;
+use crate::predicate::Predicate;
+use crate::program::Program;
+use crate::types::{ClearValue, Item};
+
+/// Builds a program that unlocks the input contracts, merges and splits
+/// their values with `cloak`, and locks the resulting values in new outputs.
+///
+/// Inputs are unlocked with `signtx`, so their predicates must be signing keys
+/// and their payloads must consist of values with known quantities and flavors.
+///
+/// Pass-through inputs are not merged in `cloak`: their payloads are re-locked unchanged
+/// in new outputs, skipping the constraint system (see `TxBuilder::pass_through`).
+#[derive(Clone, Debug, Default)]
+pub struct TxBuilder {
+    inputs: Vec<Contract>,
+    outputs: Vec<(ClearValue, Predicate)>,
+    pass_through: Vec<(Contract, Predicate)>,
+}
+
+impl TxBuilder {
+    /// Creates an empty builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a contract to be spent.
+    pub fn input(&mut self, contract: Contract) -> &mut Self {
+        self.inputs.push(contract);
+        self
+    }
+
+    /// Adds an output with the given value locked by the predicate.
+    pub fn output(&mut self, value: ClearValue, predicate: Predicate) -> &mut Self {
+        self.outputs.push((value, predicate));
+        self
+    }
+
+    /// Adds a contract to be spent whose values are passed through unchanged
+    /// to a new output locked by the predicate, without being mixed or range-checked.
+    /// The output holds the same commitments as the input, so the values are conserved
+    /// without a `cloak`, and the quantities and flavors need not be known to the builder.
+    /// This suits already-validated values, e.g. when only their owner changes.
+    pub fn pass_through(&mut self, contract: Contract, predicate: Predicate) -> &mut Self {
+        self.pass_through.push((contract, predicate));
+        self
+    }
+
+    /// Emits the program spending the inputs into the outputs.
+    ///
+    /// Fails with `VMError::UnbalancedValues` if the total quantities
+    /// of the inputs and outputs differ for any flavor.
+    /// Pass-through inputs are balanced by construction and are not counted.
+    pub fn build(&self) -> Result<Program, VMError> {
+        let inputs = self.input_values()?;
+
+        let mut totals: HashMap<[u8; 32], i128> = HashMap::new();
+        for value in inputs.iter() {
+            *totals.entry(value.flv.to_bytes()).or_insert(0) += value.qty as i128;
+        }
+        for (value, _) in self.outputs.iter() {
+            *totals.entry(value.flv.to_bytes()).or_insert(0) -= value.qty as i128;
+        }
+        if totals.values().any(|total| *total != 0) {
+            return Err(VMError::UnbalancedValues);
+        }
+
+        let mut program = Program::new();
+        for contract in self.inputs.iter() {
+            program.push(contract.clone()).input().sign_tx();
+        }
+        for (value, _) in self.outputs.iter() {
+            program
+                .push(Commitment::blinded(value.qty))
+                .push(Commitment::blinded(value.flv));
+        }
+        program.cloak(inputs.len(), self.outputs.len());
+        // Cloak leaves the last output on top of the stack.
+        for (_, predicate) in self.outputs.iter().rev() {
+            program.push(predicate.clone()).output(1);
+        }
+        for (contract, predicate) in self.pass_through.iter() {
+            program.relock(contract.clone(), predicate.clone());
+        }
+        Ok(program)
+    }
+
+    /// Collects the cleartext values from the payloads of the inputs.
+    fn input_values(&self) -> Result<Vec<ClearValue>, VMError> {
+        let mut values = Vec::new();
+        for contract in self.inputs.iter() {
+            for item in contract.payload.iter() {
+                let value = Item::from(item.clone()).to_value()?;
+                let (qty, flv) = value.assignment()?.ok_or(VMError::WitnessMissing)?;
+                let qty = qty.to_u64().ok_or(VMError::InconsistentWitness)?;
+                values.push(ClearValue { qty, flv });
+            }
+        }
+        Ok(values)
+    }
+}
@@ -0,0 +1,169 @@
+//! Streaming decoder of length-prefixed transactions.
+use std::io::{self, Read};
+
+use crate::encoding::SliceReader;
+use crate::errors::VMError;
+use crate::program::MAX_PROGRAM_BYTES;
+use crate::tx::Tx;
+
+/// Maximum size of an encoded transaction in a stream:
+/// a program of `MAX_PROGRAM_BYTES` plus a generous allowance
+/// for the header, the signature and the R1CS proof.
+pub const MAX_TX_BYTES: usize = MAX_PROGRAM_BYTES + 4096;
+
+/// Reads transactions one at a time from a stream of
+/// LE32 length-prefixed encoded transactions, e.g. the body of a block,
+/// without buffering more than one transaction.
+///
+/// A malformed or truncated transaction, or a length prefix exceeding
+/// `MAX_TX_BYTES`, yields a `VMError` and stops the iteration.
+/// The iteration ends cleanly at the end of the stream between two transactions.
+#[derive(Debug)]
+pub struct TxStreamReader<R: Read> {
+    reader: R,
+    failed: bool,
+}
+
+impl<R: Read> TxStreamReader<R> {
+    /// Creates a stream reader over the given reader.
+    pub fn new(reader: R) -> Self {
+        TxStreamReader {
+            reader,
+            failed: false,
+        }
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn read_tx(&mut self) -> Result<Option<Tx>, VMError> {
+        let mut prefix = [0u8; 4];
+        match self.read_up_to(&mut prefix)? {
+            0 => return Ok(None),
+            4 => {}
+            _ => return Err(VMError::FormatError),
+        }
+        let len = SliceReader::parse(&prefix, |r| r.read_size())?;
+        if len > MAX_TX_BYTES {
+            return Err(VMError::FormatError);
+        }
+        let mut buf = vec![0u8; len];
+        self.reader
+            .read_exact(&mut buf)
+            .map_err(|_| VMError::FormatError)?;
+        Tx::from_bytes(&buf).map(Some)
+    }
+
+    /// Fills the buffer unless the stream ends, returning the number of bytes read.
+    fn read_up_to(&mut self, buf: &mut [u8]) -> Result<usize, VMError> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.reader.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => return Err(VMError::FormatError),
+            }
+        }
+        Ok(filled)
+    }
+}
+
+impl<R: Read> Iterator for TxStreamReader<R> {
+    type Item = Result<Tx, VMError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.read_tx() {
+            Ok(tx) => tx.map(Ok),
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding;
+    use crate::tx::TxHeader;
+    use bulletproofs::r1cs::Prover;
+    use bulletproofs::{BulletproofGens, PedersenGens};
+    use curve25519_dalek::constants::RISTRETTO_BASEPOINT_COMPRESSED;
+    use curve25519_dalek::scalar::Scalar;
+    use merlin::Transcript;
+    use musig::Signature;
+
+    fn make_tx(version: u64, program: Vec<u8>) -> Tx {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(16, 1);
+        let mut transcript = Transcript::new(b"ZkVM.TxStreamTest");
+        let proof = Prover::new(&pc_gens, &mut transcript)
+            .prove(&bp_gens)
+            .unwrap();
+        Tx {
+            header: TxHeader {
+                version,
+                mintime_ms: 0,
+                maxtime_ms: 0,
+            },
+            program,
+            signature: Signature {
+                R: RISTRETTO_BASEPOINT_COMPRESSED,
+                s: Scalar::zero(),
+            },
+            proof,
+        }
+    }
+
+    #[test]
+    fn concatenated_txs() {
+        let txs = vec![
+            make_tx(1, vec![]),
+            make_tx(2, vec![0x02]),
+            make_tx(3, vec![0x03, 0x04, 0x05]),
+        ];
+        let mut stream = Vec::new();
+        for tx in txs.iter() {
+            let bytes = tx.to_bytes();
+            encoding::write_size(bytes.len(), &mut stream);
+            stream.extend_from_slice(&bytes);
+        }
+
+        let decoded: Vec<_> = TxStreamReader::new(&stream[..]).collect();
+        assert_eq!(decoded.len(), 3);
+        for (tx, decoded) in txs.iter().zip(decoded.into_iter()) {
+            assert_eq!(decoded.unwrap().to_bytes(), tx.to_bytes());
+        }
+
+        // Truncated trailing entry stops the iteration with an error.
+        let trailing = make_tx(4, vec![0x06]).to_bytes();
+        encoding::write_size(trailing.len(), &mut stream);
+        stream.extend_from_slice(&trailing[..trailing.len() - 1]);
+
+        let mut reader = TxStreamReader::new(&stream[..]);
+        for tx in txs.iter() {
+            assert_eq!(reader.next().unwrap().unwrap().to_bytes(), tx.to_bytes());
+        }
+        assert_eq!(reader.next().unwrap().err(), Some(VMError::FormatError));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn oversized_and_truncated_prefix() {
+        let mut stream = Vec::new();
+        encoding::write_size(MAX_TX_BYTES + 1, &mut stream);
+        let mut reader = TxStreamReader::new(&stream[..]);
+        assert_eq!(reader.next().unwrap().err(), Some(VMError::FormatError));
+        assert!(reader.next().is_none());
+
+        let mut reader = TxStreamReader::new(&[0u8, 1][..]);
+        assert_eq!(reader.next().unwrap().err(), Some(VMError::FormatError));
+    }
+}
@@ -3,14 +3,19 @@
 use bulletproofs::r1cs;
 use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
-use spacesuit::SignedInteger;
+use spacesuit::{BitRange, SignedInteger};
+use subtle::{Choice, ConstantTimeEq};
 
 use crate::constraints::{Commitment, Constraint, Expression, Variable};
 use crate::contract::{Contract, PortableItem};
+use crate::encoding;
 use crate::encoding::Encodable;
+use crate::encoding::Sink;
 use crate::encoding::SliceReader;
 use crate::errors::VMError;
+use crate::merkle::MerkleItem;
 use crate::predicate::Predicate;
 use crate::program::ProgramItem;
 use crate::scalar_witness::ScalarWitness;
@@ -87,15 +92,24 @@ pub struct Value {
 /// Represents a cleartext value of an issued asset in the VM.
 /// This is not the same as `spacesuit::Value` since it is guaranteed to be in-range
 /// (negative quantity is not representable with this type).
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+///
+/// Serialized as `{"qty": <u64>, "flv": "<64 hex digits>"}`, where the flavor
+/// must be a canonically encoded scalar.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ClearValue {
     /// Cleartext quantity integer
     pub qty: u64,
     /// Cleartext flavor scalar
+    #[cfg_attr(feature = "serde", serde(with = "hex_scalar"))]
     pub flv: Scalar,
 }
 
 /// A wide value type (for negative values created by `borrow`).
+///
+/// Invariant: `borrow` creates a pair of `-V` and `+V` with the same flavor,
+/// so once the `-V` wide value is merged in `cloak` with positive values of that flavor,
+/// the borrowed quantity nets to zero and the transaction conserves value.
 #[derive(Debug)]
 pub struct WideValue {
     pub(crate) r1cs_qty: r1cs::Variable,
@@ -104,11 +118,25 @@ pub struct WideValue {
 }
 
 impl Item {
+    /// Returns the name of the item's type for use in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Item::String(_) => "String",
+            Item::Program(_) => "Program",
+            Item::Contract(_) => "Contract",
+            Item::Value(_) => "Value",
+            Item::WideValue(_) => "WideValue",
+            Item::Variable(_) => "Variable",
+            Item::Expression(_) => "Expression",
+            Item::Constraint(_) => "Constraint",
+        }
+    }
+
     /// Downcasts item to `String` type.
     pub fn to_string(self) -> Result<String, VMError> {
         match self {
             Item::String(x) => Ok(x),
-            _ => Err(VMError::TypeNotString),
+            item => Err(VMError::TypeNotString(item.type_name())),
         }
     }
 
@@ -116,7 +144,7 @@ impl Item {
     pub fn to_program(self) -> Result<ProgramItem, VMError> {
         match self {
             Item::Program(x) => Ok(x),
-            _ => Err(VMError::TypeNotProgramItem),
+            item => Err(VMError::TypeNotProgramItem(item.type_name())),
         }
     }
 
@@ -124,7 +152,7 @@ impl Item {
     pub fn to_contract(self) -> Result<Contract, VMError> {
         match self {
             Item::Contract(c) => Ok(c),
-            _ => Err(VMError::TypeNotContract),
+            item => Err(VMError::TypeNotContract(item.type_name())),
         }
     }
 
@@ -132,7 +160,7 @@ impl Item {
     pub fn to_value(self) -> Result<Value, VMError> {
         match self {
             Item::Value(v) => Ok(v),
-            _ => Err(VMError::TypeNotValue),
+            item => Err(VMError::TypeNotValue(item.type_name())),
         }
     }
 
@@ -140,7 +168,7 @@ impl Item {
     pub fn to_wide_value(self) -> Result<WideValue, VMError> {
         match self {
             Item::WideValue(w) => Ok(w),
-            _ => Err(VMError::TypeNotWideValue),
+            item => Err(VMError::TypeNotWideValue(item.type_name())),
         }
     }
 
@@ -148,7 +176,7 @@ impl Item {
     pub fn to_variable(self) -> Result<Variable, VMError> {
         match self {
             Item::Variable(v) => Ok(v),
-            _ => Err(VMError::TypeNotVariable),
+            item => Err(VMError::TypeNotVariable(item.type_name())),
         }
     }
 
@@ -156,7 +184,7 @@ impl Item {
     pub fn to_expression(self) -> Result<Expression, VMError> {
         match self {
             Item::Expression(expr) => Ok(expr),
-            _ => Err(VMError::TypeNotExpression),
+            item => Err(VMError::TypeNotExpression(item.type_name())),
         }
     }
 
@@ -164,7 +192,7 @@ impl Item {
     pub fn to_constraint(self) -> Result<Constraint, VMError> {
         match self {
             Item::Constraint(c) => Ok(c),
-            _ => Err(VMError::TypeNotConstraint),
+            item => Err(VMError::TypeNotConstraint(item.type_name())),
         }
     }
 
@@ -174,7 +202,7 @@ impl Item {
             Item::String(x) => Ok(PortableItem::String(x)),
             Item::Program(x) => Ok(PortableItem::Program(x)),
             Item::Value(x) => Ok(PortableItem::Value(x)),
-            _ => Err(VMError::TypeNotPortable),
+            item => Err(VMError::TypeNotPortable(item.type_name())),
         }
     }
 
@@ -183,7 +211,22 @@ impl Item {
         match self {
             Item::String(x) => Ok(CopyableItem::String(x)),
             Item::Variable(x) => Ok(CopyableItem::Variable(x)),
-            _ => Err(VMError::TypeNotCopyable),
+            item => Err(VMError::TypeNotCopyable(item.type_name())),
+        }
+    }
+
+    /// Clones a copyable or portable item, e.g. to prepare the same stack
+    /// for another run of the prover.
+    /// Fails with `VMError::TypeNotCloneable` for the other types
+    /// (contracts, wide values, expressions and constraints),
+    /// which are either linear or bound to a particular constraint system.
+    pub fn try_clone(&self) -> Result<Item, VMError> {
+        match self {
+            Item::String(x) => Ok(Item::String(x.clone())),
+            Item::Program(x) => Ok(Item::Program(x.clone())),
+            Item::Value(x) => Ok(Item::Value(x.clone())),
+            Item::Variable(x) => Ok(Item::Variable(x.clone())),
+            item => Err(VMError::TypeNotCloneable(item.type_name())),
         }
     }
 
@@ -192,7 +235,7 @@ impl Item {
         match self {
             Item::String(x) => Ok(CopyableItem::String(x.clone())),
             Item::Variable(x) => Ok(CopyableItem::Variable(x.clone())),
-            _ => Err(VMError::TypeNotCopyable),
+            item => Err(VMError::TypeNotCopyable(item.type_name())),
         }
     }
 }
@@ -209,9 +252,9 @@ impl Encodable for String {
         }
     }
     /// Encodes the data item to an opaque bytestring.
-    fn encode(&self, buf: &mut Vec<u8>) {
+    fn encode<S: Sink>(&self, buf: &mut S) {
         match self {
-            String::Opaque(x) => buf.extend_from_slice(x),
+            String::Opaque(x) => encoding::write_bytes(x, buf),
             String::Predicate(predicate) => predicate.encode(buf),
             String::Commitment(commitment) => commitment.encode(buf),
             String::Scalar(scalar) => scalar.encode(buf),
@@ -283,15 +326,137 @@ impl Default for String {
     }
 }
 
+impl ConstantTimeEq for Value {
+    /// Compares the quantity and flavor commitment points in constant time,
+    /// regardless of whether the commitments are open or closed.
+    fn ct_eq(&self, other: &Self) -> Choice {
+        let qty_eq = self.qty.to_point().as_bytes()[..].ct_eq(&other.qty.to_point().as_bytes()[..]);
+        let flv_eq = self.flv.to_point().as_bytes()[..].ct_eq(&other.flv.to_point().as_bytes()[..]);
+        qty_eq & flv_eq
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for Value {}
+
+impl MerkleItem for Value {
+    /// Commits the quantity and flavor commitments under a `value` tag,
+    /// so a value never collides with a contract or a program in the same tree.
+    fn commit(&self, t: &mut Transcript) {
+        t.append_message(b"item", b"value");
+        t.commit_point(b"value.qty", &self.qty.to_point());
+        t.commit_point(b"value.flv", &self.flv.to_point());
+    }
+}
+
+impl ClearValue {
+    /// Compares the flavor of the value with `flavor` in constant time.
+    pub fn flavor_ct_eq(&self, flavor: &Scalar) -> Choice {
+        self.flv.ct_eq(flavor)
+    }
+
+    /// Adds up two values of the same flavor.
+    /// Returns None if the flavors are different or the quantity overflows.
+    pub fn checked_add(&self, other: &ClearValue) -> Option<ClearValue> {
+        if !bool::from(self.flavor_ct_eq(&other.flv)) {
+            return None;
+        }
+        Some(ClearValue {
+            qty: self.qty.checked_add(other.qty)?,
+            flv: self.flv,
+        })
+    }
+
+    /// Adds up a non-empty list of values of a single flavor.
+    /// Fails with `VMError::FlavorMismatch` if the flavors are different,
+    /// and with `VMError::QuantityOverflow` if the total quantity overflows.
+    pub fn sum(values: &[ClearValue]) -> Result<ClearValue, VMError> {
+        let (first, rest) = values.split_first().ok_or(VMError::BadArguments)?;
+        rest.iter().try_fold(*first, |total, value| {
+            if !bool::from(total.flavor_ct_eq(&value.flv)) {
+                return Err(VMError::FlavorMismatch);
+            }
+            total.checked_add(value).ok_or(VMError::QuantityOverflow)
+        })
+    }
+}
+
 impl Value {
     /// Computes a flavor as defined by the `issue` instruction from a predicate.
     pub fn issue_flavor(predicate: &Predicate, metadata: String) -> Scalar {
+        Self::issue_flavor_for_network(b"", predicate, metadata)
+    }
+
+    /// Computes a flavor as defined by the `issue` instruction from a predicate
+    /// on a network identified by the `network` label.
+    pub fn issue_flavor_for_network(
+        network: &[u8],
+        predicate: &Predicate,
+        metadata: String,
+    ) -> Scalar {
         let mut t = Transcript::new(b"ZkVM.issue");
+        t.commit_network(network);
         t.append_message(b"predicate", predicate.to_point().as_bytes());
         t.append_message(b"metadata", &metadata.to_bytes());
         t.challenge_scalar(b"flavor")
     }
 
+    /// Computes a flavor from a predicate and structured metadata fields.
+    /// The fields are ordered: reordering them produces a different flavor.
+    /// To issue the asset, push `Value::metadata_from_fields(fields)` as the metadata string.
+    pub fn issue_flavor_with_fields(predicate: &Predicate, fields: &[(&str, &[u8])]) -> Scalar {
+        Self::issue_flavor(predicate, Self::metadata_from_fields(fields))
+    }
+
+    /// Hashes ordered key/value metadata fields into a 32-byte metadata string
+    /// suitable for the `issue` instruction.
+    pub fn metadata_from_fields(fields: &[(&str, &[u8])]) -> String {
+        let mut t = Transcript::new(b"ZkVM.metadata");
+        t.append_u64(b"n", fields.len() as u64);
+        for (key, value) in fields.iter() {
+            t.append_message(b"key", key.as_bytes());
+            t.append_message(b"value", value);
+        }
+        let mut digest = [0u8; 32];
+        t.challenge_bytes(b"digest", &mut digest);
+        String::Opaque(digest.to_vec())
+    }
+
+    /// Creates a value committing to the cleartext value with blinding factors
+    /// derived from the wallet `seed` and the output `index`,
+    /// so the commitments can be reconstructed from the seed for recovery.
+    pub fn deterministic(clear: &ClearValue, seed: &[u8], index: u64) -> Value {
+        let mut t = Transcript::new(b"ZkVM.deterministic-value");
+        t.append_message(b"seed", seed);
+        t.append_u64(b"index", index);
+        let qty_blinding = t.challenge_scalar(b"qty_blinding");
+        let flv_blinding = t.challenge_scalar(b"flv_blinding");
+        Value {
+            qty: Commitment::blinded_with_factor(clear.qty, qty_blinding),
+            flv: Commitment::blinded_with_factor(clear.flv, flv_blinding),
+        }
+    }
+
+    /// Re-randomizes the quantity and flavor commitments by adding the given blinding factors,
+    /// so the value cannot be linked to its previous commitments.
+    /// The cleartext assignment is preserved, so the value still balances in `cloak`.
+    /// Fails with `VMError::InvalidPoint` if a closed commitment is not a valid point.
+    pub fn reblind(
+        &self,
+        added_qty_blinding: Scalar,
+        added_flv_blinding: Scalar,
+    ) -> Result<Value, VMError> {
+        Ok(Value {
+            qty: self.qty.reblind(added_qty_blinding)?,
+            flv: self.flv.reblind(added_flv_blinding)?,
+        })
+    }
+
     /// Returns a (qty,flavor) assignment to a value, or None if both fields are unassigned.
     /// Fails if the assigment is inconsistent.
     pub(crate) fn assignment(&self) -> Result<Option<(SignedInteger, Scalar)>, VMError> {
@@ -303,6 +468,80 @@ impl Value {
     }
 }
 
+impl WideValue {
+    /// Creates a wide value from low-level quantity and flavor variables
+    /// and an optional (quantity, flavor) witness.
+    pub fn new(
+        r1cs_qty: r1cs::Variable,
+        r1cs_flv: r1cs::Variable,
+        witness: Option<(SignedInteger, Scalar)>,
+    ) -> Self {
+        WideValue {
+            r1cs_qty,
+            r1cs_flv,
+            witness,
+        }
+    }
+
+    /// Returns a (qty, flavor) witness of the wide value, or None if it is unassigned.
+    /// The quantity of a wide value created by `borrow` is negative.
+    pub fn witness(&self) -> Option<(SignedInteger, Scalar)> {
+        self.witness
+    }
+
+    /// Narrows the wide value into a non-negative `value`, e.g. to repay a `borrow`.
+    /// The quantity and flavor commitments of `value` are added to the constraint system
+    /// with `commit` and constrained to be equal to those of the wide value,
+    /// and the quantity is constrained to the range [0, 2^64).
+    ///
+    /// The prover fails with `VMError::R1CSInconsistency` if the quantity is negative.
+    pub fn narrow<CS, F>(self, cs: &mut CS, value: Value, mut commit: F) -> Result<Value, VMError>
+    where
+        CS: r1cs::ConstraintSystem,
+        F: FnMut(&mut CS, &Commitment) -> Result<r1cs::Variable, VMError>,
+    {
+        let qty = commit(cs, &value.qty)?;
+        let flv = commit(cs, &value.flv)?;
+        cs.constrain(qty - self.r1cs_qty);
+        cs.constrain(flv - self.r1cs_flv);
+        spacesuit::range_proof(
+            cs,
+            qty.into(),
+            self.witness.map(|(q, _)| q),
+            BitRange::max(),
+        )
+        .map_err(|_| VMError::R1CSInconsistency)?;
+        Ok(value)
+    }
+}
+
+/// Serde helpers encoding a `Scalar` as a hex string of its canonical 32-byte representation.
+#[cfg(feature = "serde")]
+mod hex_scalar {
+    use curve25519_dalek::scalar::Scalar;
+    use serde::{de, ser, Deserialize, Deserializer, Serializer};
+    use subtle_encoding::hex;
+
+    pub fn serialize<S: Serializer>(scalar: &Scalar, serializer: S) -> Result<S::Ok, S::Error> {
+        let encoded = hex::encode(scalar.as_bytes());
+        let encoded = std::str::from_utf8(&encoded).map_err(ser::Error::custom)?;
+        serializer.serialize_str(encoded)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Scalar, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        let bytes = hex::decode(encoded)
+            .map_err(|_| de::Error::custom("flavor is not a valid hex string"))?;
+        if bytes.len() != 32 {
+            return Err(de::Error::invalid_length(bytes.len(), &"32 bytes"));
+        }
+        let mut buf = [0u8; 32];
+        buf.copy_from_slice(&bytes);
+        Scalar::from_canonical_bytes(buf)
+            .ok_or_else(|| de::Error::custom("flavor is not a canonical scalar"))
+    }
+}
+
 // Upcasting all witness data types to String
 
 impl<T> From<T> for String
@@ -402,3 +641,302 @@ impl From<CopyableItem> for Item {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bulletproofs::PedersenGens;
+    use musig::VerificationKey;
+
+    #[test]
+    fn try_clone_items() {
+        // Cloning stacks for re-running the prover is tested in `tests/zkvm.rs`.
+        let item = Item::Constraint(Constraint::Cleartext(true));
+        assert_eq!(
+            item.try_clone().err(),
+            Some(VMError::TypeNotCloneable("Constraint"))
+        );
+        let item = Item::Expression(Expression::constant(1u64));
+        assert_eq!(
+            item.try_clone().err(),
+            Some(VMError::TypeNotCloneable("Expression"))
+        );
+    }
+
+    #[test]
+    fn downcast_reports_found_type() {
+        let item = Item::Constraint(Constraint::Cleartext(true));
+        assert_eq!(item.type_name(), "Constraint");
+
+        let err = item.to_value().unwrap_err();
+        assert_eq!(err, VMError::TypeNotValue("Constraint"));
+        assert_eq!(err.to_string(), "Expected a value, found Constraint.");
+    }
+
+    #[test]
+    fn flavor_from_fields() {
+        let predicate = Predicate::Key(VerificationKey::from_secret(&Scalar::from(1u64)));
+        let name: &[u8] = b"Gold";
+        let decimals: &[u8] = &[2u8];
+
+        let flavor =
+            Value::issue_flavor_with_fields(&predicate, &[("name", name), ("decimals", decimals)]);
+        assert_eq!(
+            flavor,
+            Value::issue_flavor_with_fields(&predicate, &[("name", name), ("decimals", decimals)])
+        );
+        assert_eq!(
+            flavor,
+            Value::issue_flavor(
+                &predicate,
+                Value::metadata_from_fields(&[("name", name), ("decimals", decimals)])
+            )
+        );
+        assert_ne!(
+            flavor,
+            Value::issue_flavor_with_fields(&predicate, &[("decimals", decimals), ("name", name)])
+        );
+        // Keys and values are separated, so moving bytes between them changes the flavor.
+        assert_ne!(
+            flavor,
+            Value::issue_flavor_with_fields(
+                &predicate,
+                &[("nameG", &b"old"[..]), ("decimals", decimals)]
+            )
+        );
+    }
+
+    #[test]
+    fn value_equality() {
+        let blinding = Scalar::from(42u64);
+        let open = Value {
+            qty: Commitment::blinded_with_factor(10u64, blinding),
+            flv: Commitment::unblinded(Scalar::from(3u64)),
+        };
+        let closed = Value {
+            qty: Commitment::Closed(open.qty.to_point()),
+            flv: Commitment::Closed(open.flv.to_point()),
+        };
+        assert_eq!(open, closed);
+        assert_eq!(closed, open);
+
+        let other_blinding = Value {
+            qty: Commitment::blinded_with_factor(10u64, Scalar::from(43u64)),
+            flv: open.flv.clone(),
+        };
+        assert_ne!(open, other_blinding);
+
+        let other_flavor = Value {
+            qty: open.qty.clone(),
+            flv: Commitment::unblinded(Scalar::from(4u64)),
+        };
+        assert_ne!(closed, other_flavor);
+    }
+
+    #[test]
+    fn value_reblind() {
+        let value = Value {
+            qty: Commitment::blinded(10u64),
+            flv: Commitment::blinded(Scalar::from(3u64)),
+        };
+        let (a, b) = (Scalar::from(5u64), Scalar::from(7u64));
+        let reblinded = value.reblind(a, b).unwrap();
+
+        assert_ne!(reblinded.qty.to_point(), value.qty.to_point());
+        assert_ne!(reblinded.flv.to_point(), value.flv.to_point());
+        assert_eq!(reblinded.assignment().unwrap(), value.assignment().unwrap());
+
+        // Closed commitments are shifted by the same amount as the open ones.
+        let closed = Value {
+            qty: Commitment::Closed(value.qty.to_point()),
+            flv: Commitment::Closed(value.flv.to_point()),
+        };
+        let reblinded_closed = closed.reblind(a, b).unwrap();
+        assert_eq!(reblinded_closed.qty.to_point(), reblinded.qty.to_point());
+        assert_eq!(reblinded_closed.flv.to_point(), reblinded.flv.to_point());
+
+        let gens = PedersenGens::default();
+        assert_eq!(
+            reblinded.qty.to_point().decompress().unwrap(),
+            value.qty.to_point().decompress().unwrap() + a * gens.B_blinding
+        );
+    }
+
+    #[test]
+    fn wide_value_witness() {
+        let qty = -SignedInteger::from(5u64);
+        let flv = Scalar::from(1u64);
+        let walue = WideValue::new(
+            r1cs::Variable::Committed(0),
+            r1cs::Variable::Committed(1),
+            Some((qty, flv)),
+        );
+        assert_eq!(walue.witness(), Some((qty, flv)));
+
+        let unassigned = WideValue::new(
+            r1cs::Variable::Committed(0),
+            r1cs::Variable::Committed(1),
+            None,
+        );
+        assert_eq!(unassigned.witness(), None);
+    }
+
+    #[test]
+    fn narrow_wide_value() {
+        use bulletproofs::r1cs::{Prover, Verifier};
+        use bulletproofs::BulletproofGens;
+
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(128, 1);
+        let flv = Scalar::from(7u64);
+        let value = Value {
+            qty: Commitment::blinded(5u64),
+            flv: Commitment::blinded(flv),
+        };
+
+        // Proves that the wide value of quantity `qty` narrows into `value`.
+        let prove = |qty: SignedInteger| -> Result<_, VMError> {
+            let mut transcript = Transcript::new(b"NarrowTest");
+            let mut prover = Prover::new(&pc_gens, &mut transcript);
+            let (qty_com, qty_var) = prover.commit(qty.into(), Scalar::from(1u64));
+            let (flv_com, flv_var) = prover.commit(flv, Scalar::from(2u64));
+            let walue = WideValue::new(qty_var, flv_var, Some((qty, flv)));
+            walue.narrow(&mut prover, value.clone(), |cs, com| {
+                let (v, blinding) = com.witness().ok_or(VMError::WitnessMissing)?;
+                Ok(cs.commit(v.into(), blinding).1)
+            })?;
+            let proof = prover.prove(&bp_gens).map_err(VMError::R1CSError)?;
+            Ok((proof, qty_com, flv_com))
+        };
+
+        let (proof, qty_com, flv_com) = prove(5u64.into()).unwrap();
+        let mut transcript = Transcript::new(b"NarrowTest");
+        let mut verifier = Verifier::new(&mut transcript);
+        let qty_var = verifier.commit(qty_com);
+        let flv_var = verifier.commit(flv_com);
+        let walue = WideValue::new(qty_var, flv_var, None);
+        let closed = Value {
+            qty: Commitment::Closed(value.qty.to_point()),
+            flv: Commitment::Closed(value.flv.to_point()),
+        };
+        walue
+            .narrow(&mut verifier, closed, |cs, com| {
+                Ok(cs.commit(com.to_point()))
+            })
+            .unwrap();
+        assert!(verifier.verify(&proof, &pc_gens, &bp_gens).is_ok());
+
+        assert_eq!(
+            prove(-SignedInteger::from(5u64)).err(),
+            Some(VMError::R1CSInconsistency)
+        );
+    }
+
+    #[test]
+    fn flavor_ct_eq() {
+        let mut rng = rand::thread_rng();
+        for i in 0..100u64 {
+            let value = ClearValue {
+                qty: i,
+                flv: Scalar::random(&mut rng),
+            };
+            let other = if i % 2 == 0 {
+                value.flv
+            } else {
+                Scalar::random(&mut rng)
+            };
+            assert_eq!(bool::from(value.flavor_ct_eq(&other)), value.flv == other);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn clear_value_json() {
+        let value = ClearValue {
+            qty: 42,
+            flv: Scalar::from(1u64),
+        };
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(
+            json,
+            r#"{"qty":42,"flv":"0100000000000000000000000000000000000000000000000000000000000000"}"#
+        );
+
+        let decoded: ClearValue = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.qty, value.qty);
+        assert_eq!(decoded.flv, value.flv);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn clear_value_json_rejects_invalid_flavor() {
+        // Not reduced modulo the group order
+        let non_canonical = format!(r#"{{"qty":1,"flv":"{}"}}"#, "ff".repeat(32));
+        assert!(serde_json::from_str::<ClearValue>(&non_canonical).is_err());
+
+        // Wrong length
+        let short = r#"{"qty":1,"flv":"0100"}"#;
+        assert!(serde_json::from_str::<ClearValue>(short).is_err());
+
+        // Not hex
+        let not_hex = format!(r#"{{"qty":1,"flv":"{}"}}"#, "zz".repeat(32));
+        assert!(serde_json::from_str::<ClearValue>(&not_hex).is_err());
+    }
+
+    #[test]
+    fn deterministic_value() {
+        let clear = ClearValue {
+            qty: 10,
+            flv: Scalar::from(3u64),
+        };
+        let points = |value: Value| (value.qty.to_point(), value.flv.to_point());
+
+        let value = Value::deterministic(&clear, b"wallet seed", 7);
+        assert_eq!(
+            points(value.clone()),
+            points(Value::deterministic(&clear, b"wallet seed", 7))
+        );
+        assert_eq!(value.assignment(), Ok(Some((clear.qty.into(), clear.flv))));
+
+        // Distinct blinding factors for other outputs and seeds.
+        assert_ne!(
+            points(value.clone()),
+            points(Value::deterministic(&clear, b"wallet seed", 8))
+        );
+        assert_ne!(
+            points(value),
+            points(Value::deterministic(&clear, b"other seed", 7))
+        );
+    }
+
+    #[test]
+    fn clear_value_arithmetic() {
+        let value = |qty: u64, flv: u64| ClearValue {
+            qty,
+            flv: Scalar::from(flv),
+        };
+
+        let total = value(3, 1).checked_add(&value(4, 1)).unwrap();
+        assert_eq!((total.qty, total.flv), (7, Scalar::from(1u64)));
+        let total = ClearValue::sum(&[value(1, 1), value(2, 1), value(3, 1)]).unwrap();
+        assert_eq!((total.qty, total.flv), (6, Scalar::from(1u64)));
+
+        // Mismatched flavors
+        assert!(value(3, 1).checked_add(&value(4, 2)).is_none());
+        assert_eq!(
+            ClearValue::sum(&[value(1, 1), value(2, 2)]).err(),
+            Some(VMError::FlavorMismatch)
+        );
+
+        // Quantity overflow
+        assert!(value(u64::max_value(), 1)
+            .checked_add(&value(1, 1))
+            .is_none());
+        assert_eq!(
+            ClearValue::sum(&[value(u64::max_value(), 1), value(1, 1)]).err(),
+            Some(VMError::QuantityOverflow)
+        );
+
+        assert_eq!(ClearValue::sum(&[]).err(), Some(VMError::BadArguments));
+    }
+}
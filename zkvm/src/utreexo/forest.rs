@@ -1,5 +1,5 @@
-use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::mem;
 
 use super::nodes::{Hash, Heap, Node, NodeHasher, NodeIndex};
 use super::path::{Directions, Path, Position, Proof};
@@ -11,15 +11,26 @@ use crate::merkle::MerkleItem;
 pub struct Forest<M: MerkleItem> {
     generation: u64,
     roots: [Option<Hash>; 64], // roots of the trees for levels 0 to 63
+    leaves: Vec<Hash>,         // hashes of the live items, by position
     hasher: NodeHasher<M>,
 }
 
+/// Snapshot of the forest: its generation, the root hashes and the hashes of the items.
+/// Used to roll the forest back to a previous generation, e.g. during a chain reorg.
+#[derive(Clone)]
+pub struct ForestSnapshot {
+    generation: u64,
+    roots: [Option<Hash>; 64],
+    leaves: Vec<Hash>,
+}
+
 /// State of the Utreexo forest during update
 #[derive(Clone)]
 pub struct WorkForest<M: MerkleItem> {
     generation: u64,
     roots: Vec<NodeIndex>, // roots of all the trees including the newly inserted nodes
     heap: Heap,
+    leaves: Vec<Hash>, // hashes of the items of the forest this one was created from
     hasher: NodeHasher<M>,
 }
 
@@ -41,6 +52,10 @@ pub enum UtreexoError {
     /// to which it should.
     #[fail(display = "Merkle proof is invalid")]
     InvalidProof,
+
+    /// This error occurs when the forest is rolled back to a snapshot of a later generation.
+    #[fail(display = "Snapshot does not belong to a previous generation of the forest")]
+    InvalidSnapshot,
 }
 
 impl<M: MerkleItem> Forest<M> {
@@ -49,6 +64,7 @@ impl<M: MerkleItem> Forest<M> {
         Forest {
             generation: 0,
             roots: [None; 64],
+            leaves: Vec::new(),
             hasher: NodeHasher::new(),
         }
     }
@@ -58,6 +74,39 @@ impl<M: MerkleItem> Forest<M> {
         self.generation
     }
 
+    /// Returns the number of generations the proof is behind the forest:
+    /// zero for a current proof, positive for a proof that needs to be updated with `Catchup`.
+    /// A proof of a later generation (e.g. after a `rollback`) also has age zero,
+    /// but is not current (see `Proof::is_current`).
+    pub fn proof_age(&self, proof: &Proof) -> u64 {
+        self.generation.saturating_sub(proof.generation)
+    }
+
+    /// Captures the current generation and the roots of the forest.
+    pub fn snapshot(&self) -> ForestSnapshot {
+        ForestSnapshot {
+            generation: self.generation,
+            roots: self.roots,
+            leaves: self.leaves.clone(),
+        }
+    }
+
+    /// Rolls the forest back to the state captured in the snapshot,
+    /// discarding all insertions and deletions made since then.
+    /// Proofs created for the snapshot's generation become valid again.
+    /// Fails if the snapshot is of a later generation than the forest.
+    pub fn rollback(&self, snapshot: &ForestSnapshot) -> Result<Self, UtreexoError> {
+        if snapshot.generation > self.generation {
+            return Err(UtreexoError::InvalidSnapshot);
+        }
+        Ok(Forest {
+            generation: snapshot.generation,
+            roots: snapshot.roots,
+            leaves: snapshot.leaves.clone(),
+            hasher: self.hasher.clone(),
+        })
+    }
+
     /// Verifies the item's proof of inclusion.
     pub fn verify(&self, item: &M, proof: &Proof) -> Result<(), UtreexoError> {
         if proof.generation != self.generation {
@@ -78,11 +127,8 @@ impl<M: MerkleItem> Forest<M> {
 
         // 3. Now, walk the merkle proof starting with the leaf,
         //    creating the missing nodes until we hit the root.
-        let current_hash = self.hasher.leaf(item);
-        let current_hash = path
-            .walk_up(current_hash, &self.hasher)
-            .take(root_level)
-            .fold(current_hash, |_, (parent, _children)| parent);
+        //    The path has exactly `root_level` neighbors (checked above).
+        let current_hash = path.compute_root_ct(self.hasher.leaf(item), &self.hasher);
 
         // 4. Check if the computed root matches the stored root.
         if Some(current_hash) != self.roots[root_level] {
@@ -108,6 +154,7 @@ impl<M: MerkleItem> Forest<M> {
             generation: self.generation,
             roots,
             heap,
+            leaves: self.leaves.clone(),
             hasher: self.hasher.clone(),
         }
     }
@@ -141,6 +188,17 @@ impl<M: MerkleItem> Forest<M> {
             .unwrap_or(self.hasher.empty())
     }
 
+    /// Returns an iterator over the items of the forest: their positions and hashes,
+    /// in ascending order of position.
+    /// Items deleted in the previous updates are not included, and the positions
+    /// are the ones used by the proofs of this generation (see `Catchup::update_proof`).
+    pub fn leaves<'a>(&'a self) -> impl Iterator<Item = (Position, Hash)> + 'a {
+        self.leaves
+            .iter()
+            .enumerate()
+            .map(|(position, hash)| (position as Position, *hash))
+    }
+
     /// Returns an iterator over roots of the forest,
     /// from the highest to the lowest level.
     fn roots_iter<'a>(&'a self) -> impl DoubleEndedIterator<Item = (usize, Hash)> + 'a {
@@ -261,21 +319,35 @@ impl<M: MerkleItem> WorkForest<M> {
         Ok(())
     }
 
+    /// Returns the hashes of the items under the node at the given offset.
+    /// A subtree of the previous forest is stored by its root hash only,
+    /// so its items are taken from the leaves of the previous forest.
+    /// Its offset is the same as in the previous forest: `delete_transient` removes
+    /// only single-item trees, which come after all the larger ones.
+    fn leaves_under(&self, offset: Position, node: &Node) -> Vec<Hash> {
+        if node.level == 0 {
+            vec![node.hash]
+        } else {
+            let start = offset as usize;
+            self.leaves[start..start + node.capacity() as usize].to_vec()
+        }
+    }
+
     /// Normalizes the forest into minimal number of ordered perfect trees.
     /// Returns a root of the new forst, the forest and a catchup structure.
     pub fn normalize(self) -> (Forest<M>, Catchup<M>) {
         // TBD: what's the best way to estimate the vector capacity from self.heap.len()?
         let estimated_cap = self.heap.len() / 2;
 
-        // Collect all nodes that were not modified.
+        // Collect all nodes that were not modified, along with the hashes of their items.
         // We delay allocation of the nodes so we don't have to mutably borrow `new_heap`
-        // in two iterators, and instead yield pairs `(hash, level)`.
+        // in two iterators, and instead yield triples `(hash, level, leaves)`.
         let non_modified_nodes = self
             .heap
             .traverse(self.roots_iter(), |n| n.modified)
-            .filter_map(|(_offset, node)| {
+            .filter_map(|(offset, node)| {
                 if !node.modified {
-                    Some((node.hash, node.level))
+                    Some((node.hash, node.level, self.leaves_under(offset, &node)))
                 } else {
                     None
                 }
@@ -283,12 +355,14 @@ impl<M: MerkleItem> WorkForest<M> {
 
         // Compute perfect roots for the new tree,
         // joining together same-level nodes into higher-level nodes.
-        let (new_heap, new_roots) = non_modified_nodes.fold(
+        // The items of each new root are joined in the same order as its subtrees.
+        let (new_heap, new_roots, new_root_leaves) = non_modified_nodes.fold(
             (
                 Heap::with_capacity(estimated_cap),
                 [None as Option<NodeIndex>; 64],
+                vec![Vec::<Hash>::new(); 64],
             ),
-            |(mut new_heap, mut roots), (hash, level)| {
+            |(mut new_heap, mut roots, mut root_leaves), (hash, level, mut leaves)| {
                 let mut node = new_heap.allocate(hash, level, None);
                 // If we have a left node at the same level already,
                 // merge it with the current node.
@@ -301,17 +375,25 @@ impl<M: MerkleItem> WorkForest<M> {
                         Some((left.index, node.index)),
                     );
                     roots[left.level] = None;
+                    let mut left_leaves = mem::replace(&mut root_leaves[left.level], Vec::new());
+                    left_leaves.append(&mut leaves);
+                    leaves = left_leaves;
                 }
                 // Place the node in the unoccupied slot.
                 roots[node.level] = Some(node.index);
-                (new_heap, roots)
+                root_leaves[node.level] = leaves;
+                (new_heap, roots, root_leaves)
             },
         );
 
+        // Items are positioned from the highest to the lowest root.
+        let new_leaves: Vec<Hash> = new_root_leaves.into_iter().rev().flatten().collect();
+
         let new_forest = WorkForest {
             generation: self.generation + 1,
             roots: new_roots.iter().rev().filter_map(|r| *r).collect(),
             heap: new_heap,
+            leaves: new_leaves.clone(),
             hasher: self.hasher.clone(),
         };
 
@@ -325,6 +407,7 @@ impl<M: MerkleItem> WorkForest<M> {
         let utreexo = Forest {
             generation: self.generation + 1,
             roots: utreexo_roots,
+            leaves: new_leaves,
             hasher: self.hasher,
         };
 
@@ -348,6 +431,84 @@ impl<M: MerkleItem> WorkForest<M> {
         (utreexo, catchup)
     }
 
+    /// Creates merkle paths for the items at the given positions in a single traversal,
+    /// visiting the nodes shared by several paths only once.
+    /// Paths are returned in the same order as the positions.
+    /// Fails if a position is out of range or the tree above it was trimmed.
+    fn create_paths(&self, positions: &[Position]) -> Result<Vec<Path>, UtreexoError> {
+        let mut queue = positions.iter().cloned().enumerate().collect::<Vec<_>>();
+        queue.sort_by_key(|&(_, position)| position);
+
+        let mut paths: Vec<Option<Path>> = vec![None; positions.len()];
+        let mut neighbors = Vec::with_capacity(64);
+        let mut offset: Position = 0;
+        let mut remaining = &queue[..];
+        for root in self.roots_iter() {
+            let end = offset + root.capacity();
+            let split = remaining
+                .iter()
+                .position(|&(_, position)| position >= end)
+                .unwrap_or(remaining.len());
+            self.fill_paths(
+                root,
+                offset,
+                &remaining[..split],
+                &mut neighbors,
+                &mut paths,
+            )?;
+            remaining = &remaining[split..];
+            offset = end;
+        }
+
+        // Positions beyond the last tree remain without a path.
+        paths
+            .into_iter()
+            .map(|path| path.ok_or(UtreexoError::InvalidProof))
+            .collect()
+    }
+
+    /// Walks down from the `node` located at the `offset` and fills in paths for
+    /// all the positions in the `queue` (sorted by position).
+    /// `neighbors` contains the neighbors above the `node`, from the highest to the lowest.
+    fn fill_paths(
+        &self,
+        node: Node,
+        offset: Position,
+        queue: &[(usize, Position)],
+        neighbors: &mut Vec<Hash>,
+        paths: &mut [Option<Path>],
+    ) -> Result<(), UtreexoError> {
+        if queue.is_empty() {
+            return Ok(());
+        }
+        if node.level == 0 {
+            for &(i, position) in queue.iter() {
+                paths[i] = Some(Path {
+                    position,
+                    neighbors: neighbors.iter().rev().cloned().collect(),
+                });
+            }
+            return Ok(());
+        }
+        let (li, ri) = node.children.ok_or(UtreexoError::InvalidProof)?;
+        let (left, right) = (self.heap.node_at(li), self.heap.node_at(ri));
+        let middle = offset + node.capacity() / 2;
+        let split = queue
+            .iter()
+            .position(|&(_, position)| position >= middle)
+            .unwrap_or(queue.len());
+
+        neighbors.push(right.hash);
+        self.fill_paths(left, offset, &queue[..split], neighbors, paths)?;
+        neighbors.pop();
+
+        neighbors.push(left.hash);
+        self.fill_paths(right, middle, &queue[split..], neighbors, paths)?;
+        neighbors.pop();
+
+        Ok(())
+    }
+
     /// Returns the lowest-available node for a given path and verifies the higher-level
     /// neighbors in the path.
     fn existing_node_for_path(&self, root: Node, path: &Path) -> Result<Node, UtreexoError> {
@@ -376,6 +537,19 @@ impl<M: MerkleItem> WorkForest<M> {
 }
 
 impl<M: MerkleItem> Catchup<M> {
+    /// Creates proofs for the items at the given positions in the new generation of the forest.
+    /// All proofs are produced in one traversal of the forest, so this is cheaper
+    /// than updating the proofs one by one.
+    pub fn create_proofs(&self, positions: &[Position]) -> Result<Vec<Proof>, UtreexoError> {
+        let generation = self.forest.generation;
+        Ok(self
+            .forest
+            .create_paths(positions)?
+            .into_iter()
+            .map(|path| Proof { generation, path })
+            .collect())
+    }
+
     /// Updates the proof if it's slightly out of date
     /// (made against the previous generation of the Utreexo).
     pub fn update_proof(&self, item: &M, proof: Option<Proof>) -> Result<Proof, UtreexoError> {
@@ -83,20 +83,39 @@ struct PackedNode {
     children: (u32, u32),
 }
 
-pub(super) struct NodeHasher<M: MerkleItem> {
+/// Hasher of the utreexo nodes, so that roots of the forest can be reproduced
+/// by external accumulators over the same `MerkleItem` type.
+///
+/// All hashes are computed with a Merlin transcript labeled `ZkVM.utreexo`:
+/// * a leaf commits the item with `MerkleItem::commit` and returns the `merkle.leaf` challenge;
+/// * an intermediate node appends the children hashes as `L` and `R`
+///   and returns the `merkle.node` challenge;
+/// * the root of an empty forest is the `merkle.empty` challenge.
+///
+/// The root of a forest with several trees is computed by hashing the roots of the trees
+/// as intermediate nodes, from the lowest (rightmost) tree to the highest (leftmost) one.
+pub struct NodeHasher<M: MerkleItem> {
     t: Transcript,
     phantom: PhantomData<M>,
 }
 
+impl<M: MerkleItem> Default for NodeHasher<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<M: MerkleItem> NodeHasher<M> {
-    pub(super) fn new() -> Self {
+    /// Creates a new hasher.
+    pub fn new() -> Self {
         NodeHasher {
             t: Transcript::new(b"ZkVM.utreexo"),
             phantom: PhantomData,
         }
     }
 
-    pub(super) fn leaf(&self, item: &M) -> Hash {
+    /// Returns the hash of a leaf containing the `item`.
+    pub fn leaf(&self, item: &M) -> Hash {
         let mut t = self.t.clone();
         item.commit(&mut t);
         let mut hash = [0; 32];
@@ -104,7 +123,8 @@ impl<M: MerkleItem> NodeHasher<M> {
         hash
     }
 
-    pub(super) fn intermediate(&self, left: &Hash, right: &Hash) -> Hash {
+    /// Returns the hash of an intermediate node with the given children hashes.
+    pub fn intermediate(&self, left: &Hash, right: &Hash) -> Hash {
         let mut t = self.t.clone();
         t.append_message(b"L", left);
         t.append_message(b"R", right);
@@ -113,7 +133,8 @@ impl<M: MerkleItem> NodeHasher<M> {
         hash
     }
 
-    pub(super) fn empty(&self) -> Hash {
+    /// Returns the root hash of an empty forest.
+    pub fn empty(&self) -> Hash {
         let mut t = self.t.clone();
         let mut hash = [0; 32];
         t.challenge_bytes(b"merkle.empty", &mut hash);
@@ -1,12 +1,21 @@
 use crate::merkle::MerkleItem;
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
+use subtle::{Choice, ConditionallySelectable};
 
-use super::super::encoding::{self, Encodable};
+use super::super::encoding::{self, Encodable, Sink, SliceReader};
+use super::super::errors::VMError;
+use super::forest::Forest;
 use super::nodes::{Hash, NodeHasher};
+use zkvm_derive::Encodable;
 
 /// Absolute position of an item in the tree.
 pub type Position = u64;
 
+/// Maximum number of neighbors in a `Path`.
+/// Positions are 64-bit, so no tree in the forest can be deeper than that.
+pub const MAX_PATH_NEIGHBORS: usize = 64;
+
 /// Merkle proof of inclusion of a node in a `Forest`.
 /// The exact tree is determined by the `position`, an absolute position of the item
 /// within the set of all items in the forest.
@@ -15,7 +24,8 @@ pub type Position = u64;
 /// (Lowest bit=1 means the first neighbor is to the left of the node.)
 /// `generation` points to the generation of the Forest to which the proof applies.
 /// `path` is None if this proof is for a newly added item that has no merkle path yet.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Encodable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Proof {
     /// Generation of the forest to which the proof applies.
     pub generation: u64,
@@ -25,7 +35,8 @@ pub struct Proof {
 }
 
 /// Merkle path to the item.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Path {
     pub(super) position: Position,
     pub(super) neighbors: Vec<Hash>,
@@ -33,8 +44,8 @@ pub struct Path {
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub(super) enum Side {
-    Left,
-    Right,
+    Left = 0,
+    Right = 1,
 }
 
 impl Side {
@@ -54,6 +65,19 @@ impl Side {
         }
     }
 
+    /// Constant-time version of `order` (and `choose`, which is the same permutation) for hashes:
+    /// the pair is swapped with a conditional select instead of a branch,
+    /// so the timing does not depend on the side.
+    pub(super) fn order_ct(self, node: &Hash, neighbor: &Hash) -> (Hash, Hash) {
+        let swap = Choice::from(self as u8);
+        let mut left = *node;
+        let mut right = *neighbor;
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            u8::conditional_swap(l, r, swap);
+        }
+        (left, right)
+    }
+
     fn from_bit(bit: u8) -> Self {
         match bit {
             0 => Side::Left,
@@ -62,7 +86,43 @@ impl Side {
     }
 }
 
+impl Proof {
+    /// Verifies the proof against the root of the tree containing the item,
+    /// without access to the `Forest` (e.g. by a light client).
+    /// See `Path::verify_root`.
+    pub fn verify_root<M: MerkleItem>(&self, item: &M, root: &Hash) -> bool {
+        self.path.verify_root(item, root)
+    }
+
+    /// Returns the sides of the nodes on the path, from the item up to the root:
+    /// `true` if the node is the right child (and its neighbor is on the left),
+    /// `false` if it is the left child.
+    /// Together with `Proof::neighbors` this allows recomputing the root independently.
+    pub fn directions(&self) -> impl DoubleEndedIterator<Item = bool> + ExactSizeIterator {
+        self.path.directions().map(|side| side == Side::Right)
+    }
+
+    /// Returns the neighbors on the path, from the item up to the root.
+    pub fn neighbors(&self) -> &[Hash] {
+        &self.path.neighbors
+    }
+
+    /// Returns true if the proof applies to the current generation of the forest
+    /// and can be verified against it without updating.
+    pub fn is_current<M: MerkleItem>(&self, forest: &Forest<M>) -> bool {
+        self.generation == forest.generation()
+    }
+}
+
 impl Path {
+    /// Recomputes the root of the tree from the item and the neighbors,
+    /// and checks that it equals the given `root`.
+    /// Note: `root` is the root of the tree containing the item, not the root of the whole forest.
+    pub fn verify_root<M: MerkleItem>(&self, item: &M, root: &Hash) -> bool {
+        let hasher = NodeHasher::<M>::new();
+        &self.compute_root_ct(hasher.leaf(item), &hasher) == root
+    }
+
     pub(super) fn iter(
         &self,
     ) -> impl DoubleEndedIterator<Item = (Side, &Hash)> + ExactSizeIterator {
@@ -74,6 +134,20 @@ impl Path {
             depth: self.neighbors.len(),
         }
     }
+    /// Computes the root hash by walking up the path from the item's hash.
+    /// Neighbors are ordered in constant time, so the timing
+    /// does not reveal the shape of the path.
+    pub(super) fn compute_root_ct<M: MerkleItem>(
+        &self,
+        item_hash: Hash,
+        hasher: &NodeHasher<M>,
+    ) -> Hash {
+        self.iter().fold(item_hash, |hash, (side, neighbor)| {
+            let (l, r) = side.order_ct(&hash, neighbor);
+            hasher.intermediate(&l, &r)
+        })
+    }
+
     /// Returns an iterator that walks up the path
     /// and yields parent hash and children hashes at each step.
     pub(super) fn walk_up<'a, 'b: 'a, M: MerkleItem>(
@@ -91,19 +165,8 @@ impl Path {
     }
 }
 
-impl Encodable for Proof {
-    fn encode(&self, buf: &mut Vec<u8>) {
-        encoding::write_u64(self.generation, buf);
-        self.path.encode(buf);
-    }
-
-    fn serialized_length(&self) -> usize {
-        8 + self.path.serialized_length()
-    }
-}
-
 impl Encodable for Path {
-    fn encode(&self, buf: &mut Vec<u8>) {
+    fn encode<S: Sink>(&self, buf: &mut S) {
         encoding::write_u64(self.position, buf);
         encoding::write_size(self.neighbors.len(), buf);
         for hash in self.neighbors.iter() {
@@ -116,6 +179,33 @@ impl Encodable for Path {
     }
 }
 
+impl Path {
+    /// Decodes the path from the reader.
+    /// Fails with `VMError::FormatError` if the number of neighbors
+    /// exceeds `MAX_PATH_NEIGHBORS`, before allocating any storage for them.
+    pub fn decode<'a>(reader: &mut SliceReader<'a>) -> Result<Self, VMError> {
+        let position = reader.read_u64()?;
+        let n = reader.read_size_bounded(MAX_PATH_NEIGHBORS)?;
+        let mut neighbors = Vec::with_capacity(n);
+        for _ in 0..n {
+            neighbors.push(reader.read_u8x32()?);
+        }
+        Ok(Path {
+            position,
+            neighbors,
+        })
+    }
+}
+
+impl Proof {
+    /// Decodes the proof from the reader.
+    pub fn decode<'a>(reader: &mut SliceReader<'a>) -> Result<Self, VMError> {
+        let generation = reader.read_u64()?;
+        let path = Path::decode(reader)?;
+        Ok(Proof { generation, path })
+    }
+}
+
 /// Simialr to Path, but does not contain neighbors - only left/right directions
 /// as indicated by the bits in the `position`.
 #[derive(Copy, Clone, PartialEq, Debug)]
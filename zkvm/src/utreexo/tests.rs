@@ -1,7 +1,11 @@
 use merlin::Transcript;
 
+use super::path::Side;
 use super::*;
+use crate::encoding::{Encodable, SliceReader};
+use crate::errors::VMError;
 use crate::merkle::*;
+use zkvm_derive::Encodable;
 
 impl MerkleItem for u64 {
     fn commit(&self, t: &mut Transcript) {
@@ -18,6 +22,25 @@ fn empty_utreexo() {
     );
 }
 
+#[test]
+fn reproduce_root_with_hasher() {
+    let hasher = NodeHasher::<u64>::new();
+    assert_eq!(Forest::<u64>::new().root(), hasher.empty());
+
+    let (_, forest, _) = Forest::<u64>::new()
+        .update(|forest| {
+            for i in 0..3 {
+                forest.insert(&i);
+            }
+            Ok(())
+        })
+        .unwrap();
+
+    // Trees of 2 and 1 items, the lower one goes to the right.
+    let tree = hasher.intermediate(&hasher.leaf(&0), &hasher.leaf(&1));
+    assert_eq!(forest.root(), hasher.intermediate(&tree, &hasher.leaf(&2)));
+}
+
 #[test]
 fn transient_items_utreexo() {
     let forest0 = Forest::new();
@@ -226,3 +249,380 @@ fn large_utreexo() {
 
     // TBD: try random changes
 }
+
+#[test]
+fn batch_proofs_utreexo() {
+    let n = 11u64;
+
+    let forest0 = Forest::new();
+    let (_, forest1, catchup1) = forest0
+        .update(|forest| {
+            for i in 0..n {
+                forest.insert(&i);
+            }
+            Ok(())
+        })
+        .expect("cannot fail");
+
+    let positions = [9, 0, 3, 10, 4, 5];
+    let proofs = catchup1
+        .create_proofs(&positions)
+        .expect("all positions are in range");
+
+    for (&i, proof) in positions.iter().zip(proofs.iter()) {
+        forest1
+            .verify(&i, proof)
+            .expect("batch proof should be valid");
+
+        let single = catchup1.update_proof(&i, None).unwrap();
+        assert_eq!(proof.path.position, single.path.position);
+        assert_eq!(proof.path.neighbors, single.path.neighbors);
+    }
+
+    // position outside of the forest
+    assert!(catchup1.create_proofs(&[1, n]).is_err());
+}
+
+#[test]
+fn constant_time_side_ordering() {
+    let a: Hash = [1u8; 32];
+    let mut b: Hash = [2u8; 32];
+    b[31] = 0xff;
+
+    for &side in [Side::Left, Side::Right].iter() {
+        assert_eq!(side.order_ct(&a, &b), side.order(a, b));
+        assert_eq!(side.order_ct(&a, &b), side.choose(a, b));
+    }
+
+    let forest0 = Forest::new();
+    let (_, forest1, catchup1) = forest0
+        .update(|forest| {
+            for i in 0..11 {
+                forest.insert(&i);
+            }
+            Ok(())
+        })
+        .expect("cannot fail");
+
+    let hasher = NodeHasher::<u64>::new();
+    for i in 0..11u64 {
+        let proof = catchup1.update_proof(&i, None).unwrap();
+        let leaf = hasher.leaf(&i);
+        let branching_root = proof
+            .path
+            .walk_up(leaf, &hasher)
+            .fold(leaf, |_, (parent, _)| parent);
+        assert_eq!(proof.path.compute_root_ct(leaf, &hasher), branching_root);
+        forest1.verify(&i, &proof).unwrap();
+    }
+}
+
+#[test]
+fn rollback_utreexo() {
+    let forest0 = Forest::new();
+    let (_, forest1, catchup1) = forest0
+        .update(|forest| {
+            for i in 0..6 {
+                forest.insert(&i);
+            }
+            Ok(())
+        })
+        .expect("cannot fail");
+
+    let proof3 = catchup1.update_proof(&3u64, None).unwrap();
+    forest1.verify(&3, &proof3).unwrap();
+    let snapshot1 = forest1.snapshot();
+
+    let (_, forest2, _catchup2) = forest1
+        .update(|forest| {
+            forest.delete(&3, &proof3)?;
+            for i in 6..9 {
+                forest.insert(&i);
+            }
+            Ok(())
+        })
+        .expect("cannot fail");
+
+    assert_eq!(
+        forest2.verify(&3, &proof3),
+        Err(UtreexoError::OutdatedProof)
+    );
+
+    // Cannot roll forward to a later generation.
+    assert_eq!(
+        forest1.rollback(&forest2.snapshot()).err(),
+        Some(UtreexoError::InvalidSnapshot)
+    );
+
+    let forest3 = forest2.rollback(&snapshot1).unwrap();
+    assert_eq!(forest3.generation(), forest1.generation());
+    assert_eq!(forest3.root(), forest1.root());
+    forest3
+        .verify(&3, &proof3)
+        .expect("proof of the snapshot's generation should be valid again");
+}
+
+#[test]
+fn proof_age_utreexo() {
+    let (_, forest1, catchup1) = Forest::new()
+        .update(|forest| {
+            for i in 0..4u64 {
+                forest.insert(&i);
+            }
+            Ok(())
+        })
+        .expect("cannot fail");
+
+    let proof = catchup1.update_proof(&1, None).unwrap();
+    assert_eq!(forest1.proof_age(&proof), 0);
+    assert!(proof.is_current(&forest1));
+
+    let (_, forest2, _) = forest1.update(|_| Ok(())).unwrap();
+    let (_, forest3, _) = forest2
+        .update(|forest| {
+            forest.insert(&4);
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(forest2.proof_age(&proof), 1);
+    assert_eq!(forest3.proof_age(&proof), 2);
+    assert!(!proof.is_current(&forest3));
+
+    // A proof of a later generation is not current either.
+    let rolled_back = forest3.rollback(&Forest::<u64>::new().snapshot()).unwrap();
+    assert_eq!(rolled_back.proof_age(&proof), 0);
+    assert!(!proof.is_current(&rolled_back));
+}
+
+#[test]
+fn leaves_utreexo() {
+    let hasher = NodeHasher::<u64>::new();
+
+    // The forest yields exactly the given items, at the positions of their proofs.
+    let assert_leaves = |forest: &Forest<u64>, proofs: &[(u64, Proof)]| {
+        let mut expected = proofs
+            .iter()
+            .map(|(item, proof)| (proof.path.position, hasher.leaf(item)))
+            .collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(forest.leaves().collect::<Vec<_>>(), expected);
+        for (item, proof) in proofs.iter() {
+            assert!(forest.verify(item, proof).is_ok());
+        }
+    };
+
+    // Items deleted within the same update are skipped.
+    let (_, forest1, catchup1) = Forest::new()
+        .update(|forest| {
+            for i in 0..8 {
+                forest.insert(&i);
+            }
+            forest.delete_transient(&2)
+        })
+        .unwrap();
+    let proofs1 = [0u64, 1, 3, 4, 5, 6, 7]
+        .iter()
+        .map(|&i| (i, catchup1.update_proof(&i, None).unwrap()))
+        .collect::<Vec<_>>();
+    assert_leaves(&forest1, &proofs1);
+    assert_eq!(forest1.leaves().count(), 7);
+
+    // Items of the subtrees kept from the previous generation are included.
+    let (_, forest2, catchup2) = forest1
+        .update(|forest| {
+            forest.delete(&5, &proofs1[4].1)?;
+            forest.insert(&8);
+            Ok(())
+        })
+        .unwrap();
+    let proofs2 = proofs1
+        .into_iter()
+        .filter(|&(i, _)| i != 5)
+        .map(|(i, proof)| (i, catchup2.update_proof(&i, Some(proof)).unwrap()))
+        .chain(Some((8, catchup2.update_proof(&8, None).unwrap())))
+        .collect::<Vec<_>>();
+    assert_leaves(&forest2, &proofs2);
+
+    // Deleting an item splits the subtree kept from the previous generation,
+    // and normalization reorders the remaining trees.
+    let (_, forest3, catchup3) = forest2
+        .update(|forest| forest.delete(&0, &proofs2[0].1))
+        .unwrap();
+    let proofs3 = proofs2
+        .into_iter()
+        .skip(1)
+        .map(|(i, proof)| (i, catchup3.update_proof(&i, Some(proof)).unwrap()))
+        .collect::<Vec<_>>();
+    assert_leaves(&forest3, &proofs3);
+    assert_eq!(
+        forest3.leaves().map(|(_, hash)| hash).collect::<Vec<_>>(),
+        [3u64, 4, 6, 7, 1, 8]
+            .iter()
+            .map(|i| hasher.leaf(i))
+            .collect::<Vec<_>>()
+    );
+
+    // Rolling back restores the items of the snapshot.
+    let forest = forest3.rollback(&forest1.snapshot()).unwrap();
+    assert_eq!(
+        forest.leaves().collect::<Vec<_>>(),
+        forest1.leaves().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn verify_path_against_root() {
+    let forest0 = Forest::new();
+    let (_, forest1, catchup1) = forest0
+        .update(|forest| {
+            for i in 0..8 {
+                forest.insert(&i);
+            }
+            Ok(())
+        })
+        .expect("cannot fail");
+
+    // 8 items form a single tree, so the forest root is the tree root.
+    let root = forest1.root();
+    assert_eq!(
+        root,
+        MerkleTree::root::<u64>(b"ZkVM.utreexo", &(0..8).collect::<Vec<_>>())
+    );
+
+    for i in 0..8u64 {
+        let proof = catchup1.update_proof(&i, None).unwrap();
+        assert!(proof.verify_root(&i, &root));
+        assert!(proof.path.verify_root(&i, &root));
+
+        // wrong item
+        assert!(!proof.verify_root(&(i + 100), &root));
+
+        // tampered neighbor
+        let mut tampered = proof.clone();
+        tampered.path.neighbors[1][0] ^= 1;
+        assert!(!tampered.verify_root(&i, &root));
+    }
+}
+
+#[test]
+fn proof_directions() {
+    let hasher = NodeHasher::<u64>::new();
+    let (_, forest1, catchup1) = Forest::new()
+        .update(|forest| {
+            for i in 0..8 {
+                forest.insert(&i);
+            }
+            Ok(())
+        })
+        .expect("cannot fail");
+    let root = forest1.root();
+
+    for i in 0..8u64 {
+        let proof = catchup1.update_proof(&i, None).unwrap();
+        let expected: Vec<bool> = (0..3)
+            .map(|bit| (proof.path.position >> bit) & 1 == 1)
+            .collect();
+        assert_eq!(proof.directions().collect::<Vec<_>>(), expected);
+
+        // Recompute the root from the directions and neighbors alone.
+        let computed = proof.directions().zip(proof.neighbors().iter()).fold(
+            hasher.leaf(&i),
+            |hash, (right, neighbor)| {
+                if right {
+                    hasher.intermediate(neighbor, &hash)
+                } else {
+                    hasher.intermediate(&hash, neighbor)
+                }
+            },
+        );
+        assert_eq!(computed, root);
+    }
+}
+
+#[test]
+fn derived_path_encoding() {
+    // Same fields as `Path`, with the encoding derived from the field order.
+    #[derive(Encodable)]
+    struct DerivedPath {
+        position: u64,
+        neighbors: Vec<[u8; 32]>,
+    }
+
+    let hasher = NodeHasher::<u64>::new();
+    let neighbors: Vec<_> = (0..5u64).map(|i| hasher.leaf(&i)).collect();
+    for n in 0..neighbors.len() {
+        let path = Path {
+            position: 0b10110,
+            neighbors: neighbors[..n].to_vec(),
+        };
+        let derived = DerivedPath {
+            position: path.position,
+            neighbors: path.neighbors.clone(),
+        };
+        assert_eq!(derived.encode_to_vec(), path.encode_to_vec());
+        assert_eq!(derived.serialized_length(), path.serialized_length());
+
+        let proof = Proof {
+            generation: 7,
+            path,
+        };
+        assert_eq!(proof.serialized_length(), 8 + derived.serialized_length());
+        assert_eq!(proof.encode_to_vec()[8..], derived.encode_to_vec()[..]);
+    }
+}
+
+#[test]
+fn path_decoding() {
+    let hasher = NodeHasher::<u64>::new();
+    let proof = Proof {
+        generation: 3,
+        path: Path {
+            position: 0b101,
+            neighbors: (0..3u64).map(|i| hasher.leaf(&i)).collect(),
+        },
+    };
+    let bytes = proof.encode_to_vec();
+    let decoded = SliceReader::parse(&bytes, |r| Proof::decode(r)).unwrap();
+    assert_eq!(decoded.generation, proof.generation);
+    assert_eq!(decoded.path.position, proof.path.position);
+    assert_eq!(decoded.path.neighbors, proof.path.neighbors);
+
+    // Too many neighbors
+    let mut bytes = Vec::new();
+    crate::encoding::write_u64(0, &mut bytes);
+    crate::encoding::write_u32(std::u32::MAX, &mut bytes);
+    assert_eq!(
+        SliceReader::parse(&bytes, |r| Path::decode(r)).unwrap_err(),
+        VMError::FormatError
+    );
+
+    // Exactly at the limit is fine if the hashes are present
+    let path = Path {
+        position: 0,
+        neighbors: vec![[0u8; 32]; MAX_PATH_NEIGHBORS],
+    };
+    let bytes = path.encode_to_vec();
+    assert!(SliceReader::parse(&bytes, |r| Path::decode(r)).is_ok());
+}
+
+#[test]
+fn encode_proof_to_writer() {
+    let forest0 = Forest::new();
+    let (_, _forest1, catchup1) = forest0
+        .update(|forest| {
+            for i in 0..6 {
+                forest.insert(&i);
+            }
+            Ok(())
+        })
+        .expect("cannot fail");
+
+    let proof = catchup1.update_proof(&3u64, None).unwrap();
+
+    let mut buf = Vec::new();
+    proof
+        .encode_to_writer(&mut buf)
+        .expect("writing to a vec cannot fail");
+    assert_eq!(buf, proof.encode_to_vec());
+    assert_eq!(buf.len(), proof.serialized_length());
+}
@@ -1,19 +1,22 @@
 use bulletproofs::r1cs;
 use bulletproofs::r1cs::ConstraintSystem;
-use bulletproofs::{BulletproofGens, PedersenGens};
+use bulletproofs::BulletproofGens;
 use curve25519_dalek::ristretto::CompressedRistretto;
 use merlin::Transcript;
 use musig::VerificationKey;
+use std::thread;
 
 use crate::constraints::Commitment;
 use crate::contract::ContractID;
 use crate::encoding::*;
 use crate::errors::VMError;
+use crate::gens::{pedersen_gens, r1cs_proof_error};
 use crate::ops::Instruction;
 use crate::point_ops::PointOp;
 use crate::predicate::Predicate;
-use crate::program::ProgramItem;
-use crate::tx::{Tx, VerifiedTx};
+use crate::program::{ProgramItem, MAX_PROGRAM_BYTES};
+use crate::transcript::TranscriptProtocol;
+use crate::tx::{Tx, VerifiedTx, VmStats};
 use crate::vm::{Delegate, VM};
 
 /// This is the entry point API for verifying a transaction.
@@ -25,6 +28,7 @@ pub struct Verifier<'t> {
     signtx_items: Vec<(VerificationKey, ContractID)>,
     deferred_operations: Vec<PointOp>,
     cs: r1cs::Verifier<'t>,
+    instructions: usize,
 }
 
 pub struct VerifierRun {
@@ -72,6 +76,7 @@ impl<'t> Delegate<r1cs::Verifier<'t>> for Verifier<'t> {
             Ok((Instruction::parse(r)?, r.skip_trailing_bytes()))
         })?;
         run.offset = run.program.len() - remainder;
+        self.instructions += 1;
         Ok(Some(instr))
     }
 
@@ -87,26 +92,77 @@ impl<'t> Delegate<r1cs::Verifier<'t>> for Verifier<'t> {
 impl<'t> Verifier<'t> {
     /// Verifies the `Tx` object by executing the VM and returns the `VerifiedTx`.
     /// Returns an error if the program is malformed or any of the proofs are not valid.
+    /// `bp_gens` should be the canonical generators returned by `zkvm::gens`,
+    /// same as for `Prover::build_tx`.
     pub fn verify_tx(tx: &Tx, bp_gens: &BulletproofGens) -> Result<VerifiedTx, VMError> {
+        Self::verify_tx_for_network(tx, b"", bp_gens)
+    }
+
+    /// Verifies the `Tx` object built for the network identified by the `network` label.
+    /// Returns an error if the transaction was built for a different network.
+    pub fn verify_tx_for_network(
+        tx: &Tx,
+        network: &[u8],
+        bp_gens: &BulletproofGens,
+    ) -> Result<VerifiedTx, VMError> {
+        Self::verify_tx_impl(tx, network, bp_gens, false)
+    }
+
+    /// Same as `verify_tx`, but verifies the deferred point operations
+    /// (including the aggregated signature) on a new thread,
+    /// concurrently with the R1CS proof.
+    /// Accepts and rejects exactly the same transactions as `verify_tx`.
+    ///
+    /// This is not batch verification: the point operations and the R1CS proof
+    /// are still checked with separate multiscalar multiplications, and only the wall-clock
+    /// time of a single transaction is reduced. Since every call spawns a thread,
+    /// prefer `verify_tx` on a fixed pool of threads when verifying many transactions.
+    pub fn verify_tx_concurrent(tx: &Tx, bp_gens: &BulletproofGens) -> Result<VerifiedTx, VMError> {
+        Self::verify_tx_concurrent_for_network(tx, b"", bp_gens)
+    }
+
+    /// Same as `verify_tx_for_network`, but verifies the deferred point operations
+    /// concurrently with the R1CS proof (see `verify_tx_concurrent`).
+    pub fn verify_tx_concurrent_for_network(
+        tx: &Tx,
+        network: &[u8],
+        bp_gens: &BulletproofGens,
+    ) -> Result<VerifiedTx, VMError> {
+        Self::verify_tx_impl(tx, network, bp_gens, true)
+    }
+
+    fn verify_tx_impl(
+        tx: &Tx,
+        network: &[u8],
+        bp_gens: &BulletproofGens,
+        concurrent: bool,
+    ) -> Result<VerifiedTx, VMError> {
+        if tx.program.len() > MAX_PROGRAM_BYTES {
+            return Err(VMError::ProgramTooLarge);
+        }
+
         // TBD: provide this as a precomputed object to avoid
         // creating secondary point per each tx verification
-        let pc_gens = PedersenGens::default();
+        let pc_gens = pedersen_gens();
         let mut r1cs_transcript = Transcript::new(b"ZkVM.r1cs");
+        r1cs_transcript.commit_network(network);
         let cs = r1cs::Verifier::new(&mut r1cs_transcript);
 
         let mut verifier = Verifier {
             signtx_items: Vec::new(),
             deferred_operations: Vec::new(),
             cs,
+            instructions: 0,
         };
 
         let vm = VM::new(
             tx.header,
+            network,
             VerifierRun::new(tx.program.clone()),
             &mut verifier,
         );
 
-        let (txid, txlog) = vm.run()?;
+        let (txid, txlog, fee) = vm.run()?;
 
         // Commit txid so that the proof is bound to the entire transaction, not just the constraint system.
         verifier
@@ -114,14 +170,9 @@ impl<'t> Verifier<'t> {
             .transcript()
             .append_message(b"ZkVM.txid", &txid.0);
 
-        // Verify the R1CS proof
-        verifier
-            .cs
-            .verify(&tx.proof, &pc_gens, bp_gens)
-            .map_err(|_| VMError::InvalidR1CSProof)?;
-
         // Verify the signatures over txid
         let mut signtx_transcript = Transcript::new(b"ZkVM.signtx");
+        signtx_transcript.commit_network(network);
         signtx_transcript.append_message(b"txid", &txid.0);
 
         if verifier.signtx_items.len() != 0 {
@@ -131,14 +182,38 @@ impl<'t> Verifier<'t> {
                     .into(),
             );
         }
+        let deferred_operations = verifier.deferred_operations;
 
-        // Verify all deferred crypto operations.
-        PointOp::verify_batch(&verifier.deferred_operations[..])?;
+        // Verify all deferred crypto operations and the R1CS proof.
+        let verify_r1cs = |cs: r1cs::Verifier| {
+            let multipliers = cs.metrics().multipliers;
+            cs.verify_and_return_metrics(&tx.proof, &pc_gens, bp_gens)
+                .map_err(|e| r1cs_proof_error(e, multipliers, bp_gens))
+        };
+        let metrics = if concurrent {
+            let point_ops = thread::spawn(move || PointOp::verify_batch(&deferred_operations[..]));
+            let metrics = verify_r1cs(verifier.cs)?;
+            point_ops
+                .join()
+                .map_err(|_| VMError::PointOperationsFailed)??;
+            metrics
+        } else {
+            let metrics = verify_r1cs(verifier.cs)?;
+            PointOp::verify_batch(&deferred_operations[..])?;
+            metrics
+        };
+        let stats = VmStats {
+            multipliers: metrics.multipliers,
+            constraints: metrics.constraints,
+            instructions: verifier.instructions,
+        };
 
         Ok(VerifiedTx {
             header: tx.header,
             id: txid,
             log: txlog,
+            fee,
+            stats,
         })
     }
 }
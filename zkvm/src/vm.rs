@@ -10,13 +10,14 @@ use std::mem;
 
 use crate::constraints::{Commitment, Constraint, Expression, Variable};
 use crate::contract::{Anchor, Contract, ContractID, PortableItem};
-use crate::encoding::SliceReader;
+use crate::encoding::{Encodable, SliceReader};
 use crate::errors::VMError;
 use crate::ops::Instruction;
 use crate::point_ops::PointOp;
 use crate::predicate::{CallProof, Predicate};
-use crate::program::ProgramItem;
+use crate::program::{ProgramItem, MAX_NESTING_DEPTH, MAX_PROGRAM_BYTES};
 use crate::scalar_witness::ScalarWitness;
+use crate::transcript::TranscriptProtocol;
 use crate::tx::{TxEntry, TxHeader, TxID, TxLog};
 use crate::types::*;
 
@@ -31,6 +32,12 @@ where
     mintime_ms: u64,
     maxtime_ms: u64,
 
+    // label of the network for which the transaction is built
+    network: &'d [u8],
+
+    // total fee declared by the fee instructions
+    fee: u64,
+
     // is true when tx version is in the future and
     // we allow treating unassigned opcodes as no-ops.
     extension: bool,
@@ -89,10 +96,12 @@ where
     D: Delegate<CS>,
 {
     /// Instantiates a new VM instance.
-    pub fn new(header: TxHeader, run: D::RunType, delegate: &'d mut D) -> Self {
+    pub fn new(header: TxHeader, network: &'d [u8], run: D::RunType, delegate: &'d mut D) -> Self {
         VM {
             mintime_ms: header.mintime_ms,
             maxtime_ms: header.maxtime_ms,
+            network,
+            fee: 0,
             extension: header.version > CURRENT_VERSION,
             last_anchor: None,
             delegate,
@@ -104,7 +113,12 @@ where
     }
 
     /// Runs through the entire program and nested programs until completion.
-    pub fn run(mut self) -> Result<(TxID, TxLog), VMError> {
+    /// Returns the txid, the transaction log and the total fee declared by the program.
+    pub fn run(mut self) -> Result<(TxID, TxLog, u64), VMError> {
+        if self.mintime_ms > self.maxtime_ms {
+            return Err(VMError::InvalidTimeBounds);
+        }
+
         loop {
             if !self.step()? {
                 break;
@@ -121,7 +135,7 @@ where
 
         let txid = TxID::from_log(&self.txlog[..]);
 
-        Ok((txid, self.txlog))
+        Ok((txid, self.txlog, self.fee))
     }
 
     fn finish_run(&mut self) -> bool {
@@ -155,7 +169,7 @@ where
                 Instruction::Add => self.add()?,
                 Instruction::Mul => self.mul()?,
                 Instruction::Eq => self.eq()?,
-                Instruction::Range => self.range()?,
+                Instruction::Range(n) => self.range(n)?,
                 Instruction::And => self.and()?,
                 Instruction::Or => self.or()?,
                 Instruction::Not => self.not()?,
@@ -173,6 +187,7 @@ where
                 Instruction::Signtx => self.signtx()?,
                 Instruction::Signid => self.signid()?,
                 Instruction::Signtag => self.signtag()?,
+                Instruction::Fee => self.fee()?,
                 Instruction::Ext(opcode) => self.ext(opcode)?,
             }
             return Ok(true);
@@ -196,9 +211,7 @@ where
     }
 
     fn dup(&mut self, i: usize) -> Result<(), VMError> {
-        if i >= self.stack.len() {
-            return Err(VMError::StackUnderflow);
-        }
+        self.check_stack(i.saturating_add(1))?;
         let item_idx = self.stack.len() - i - 1;
         let copied = self.stack[item_idx].dup_copyable()?;
         self.push_item(copied);
@@ -206,9 +219,7 @@ where
     }
 
     fn roll(&mut self, i: usize) -> Result<(), VMError> {
-        if i >= self.stack.len() {
-            return Err(VMError::StackUnderflow);
-        }
+        self.check_stack(i.saturating_add(1))?;
         let item = self.stack.remove(self.stack.len() - i - 1);
         self.push_item(item);
         Ok(())
@@ -251,9 +262,9 @@ where
         Ok(())
     }
 
-    fn range(&mut self) -> Result<(), VMError> {
+    fn range(&mut self, n: BitRange) -> Result<(), VMError> {
         let expr = self.pop_item()?.to_expression()?;
-        self.add_range_proof(expr.clone())?;
+        self.add_range_proof(expr.clone(), n)?;
         self.push_item(expr);
         Ok(())
     }
@@ -290,7 +301,15 @@ where
     fn unblind(&mut self) -> Result<(), VMError> {
         // Pop scalar `v` and commitment `V`
         let v_scalar = self.pop_item()?.to_string()?.to_scalar()?.to_scalar();
-        let v_point = self.pop_item()?.to_string()?.to_commitment()?.to_point();
+        let v_commitment = self.pop_item()?.to_string()?.to_commitment()?;
+        let v_point = v_commitment.to_point();
+
+        // Fail early if the prover knows the commitment does not open to `v`.
+        if let Some((value, blinding)) = v_commitment.witness() {
+            if value.to_scalar() != v_scalar || blinding != Scalar::zero() {
+                return Err(VMError::InconsistentWitness);
+            }
+        }
 
         self.delegate.verify_point_op(|| {
             // Check V = vB => V-vB = 0
@@ -340,6 +359,17 @@ where
         Ok(())
     }
 
+    fn fee(&mut self) -> Result<(), VMError> {
+        let qty = self.pop_item()?.to_string()?.to_scalar()?;
+        self.add_range_proof(Expression::constant(qty), BitRange::max())?;
+        let mut qty_bytes = [0u8; 8];
+        qty_bytes.copy_from_slice(&qty.to_scalar().as_bytes()[..8]);
+        let fee = u64::from_le_bytes(qty_bytes);
+        self.fee = self.fee.checked_add(fee).ok_or(VMError::FeeTooHigh)?;
+        self.txlog.push(TxEntry::Fee(fee));
+        Ok(())
+    }
+
     fn log(&mut self) -> Result<(), VMError> {
         let data = self.pop_item()?.to_string()?;
         self.txlog.push(TxEntry::Data(data.to_bytes()));
@@ -356,8 +386,9 @@ where
         let (flv_point, _) = self.delegate.commit_variable(&flv.commitment)?;
         let (qty_point, _) = self.delegate.commit_variable(&qty.commitment)?;
 
+        let network = self.network;
         self.delegate.verify_point_op(|| {
-            let flv_scalar = Value::issue_flavor(&predicate, metadata);
+            let flv_scalar = Value::issue_flavor_for_network(network, &predicate, metadata);
             // flv_point == flavor·B    ->   0 == -flv_point + flv_scalar·B
             PointOp {
                 primary: Some(flv_scalar),
@@ -372,7 +403,7 @@ where
         };
 
         let qty_expr = self.variable_to_expression(qty)?;
-        self.add_range_proof(qty_expr)?;
+        self.add_range_proof(qty_expr, BitRange::max())?;
 
         self.txlog.push(TxEntry::Issue(qty_point, flv_point));
 
@@ -460,9 +491,7 @@ where
     fn pop_contract(&mut self, k: usize) -> Result<Contract, VMError> {
         let predicate = self.pop_item()?.to_string()?.to_predicate()?;
 
-        if k > self.stack.len() {
-            return Err(VMError::StackUnderflow);
-        }
+        self.check_stack(k)?;
 
         let payload = self
             .stack
@@ -477,16 +506,7 @@ where
         // _widevalues commitments_ **cloak:_m_:_n_** → _values_
         // Merges and splits `m` [wide values](#wide-value-type) into `n` [values](#values).
 
-        if m > self.stack.len() || n > self.stack.len() {
-            return Err(VMError::StackUnderflow);
-        }
-        // Now that individual m and n are bounded by the (not even close to overflow) stack size,
-        // we can add them together.
-        // This does not overflow if the stack size is below 2^30 items.
-        assert!(self.stack.len() < (1usize << 30));
-        if (m + 2 * n) > self.stack.len() {
-            return Err(VMError::StackUnderflow);
-        }
+        self.check_stack(m.saturating_add(n.saturating_mul(2)))?;
 
         let mut output_values: Vec<Value> = Vec::with_capacity(n);
 
@@ -590,6 +610,7 @@ where
 
         // Verify signature using Verification key, over the message `program`
         let mut t = Transcript::new(b"ZkVM.signid");
+        t.commit_network(self.network);
         t.append_message(b"contract", contract_id.as_ref());
         t.append_message(b"prog", &prog.to_bytes());
         self.delegate
@@ -626,6 +647,7 @@ where
 
         // Verify signature using Verification key, over the message `program`
         let mut t = Transcript::new(b"ZkVM.signtag");
+        t.commit_network(self.network);
         t.append_message(b"tag", &tag.to_bytes());
         t.append_message(b"prog", &prog.to_bytes());
         self.delegate
@@ -654,7 +676,21 @@ where
     D: Delegate<CS>,
 {
     fn pop_item(&mut self) -> Result<Item, VMError> {
-        self.stack.pop().ok_or(VMError::StackUnderflow)
+        self.stack.pop().ok_or(VMError::StackUnderflow {
+            requested: 1,
+            available: 0,
+        })
+    }
+
+    /// Fails with `VMError::StackUnderflow` if the stack has fewer than `requested` items.
+    fn check_stack(&self, requested: usize) -> Result<(), VMError> {
+        if requested > self.stack.len() {
+            return Err(VMError::StackUnderflow {
+                requested,
+                available: self.stack.len(),
+            });
+        }
+        Ok(())
     }
 
     fn push_item<T>(&mut self, item: T)
@@ -694,7 +730,7 @@ where
                 witness: value.assignment()?,
             }),
             Item::WideValue(w) => Ok(w),
-            _ => Err(VMError::TypeNotWideValue),
+            item => Err(VMError::TypeNotWideValue(item.type_name())),
         }
     }
 
@@ -708,16 +744,22 @@ where
     }
 
     fn continue_with_program(&mut self, prog: ProgramItem) -> Result<(), VMError> {
+        if self.run_stack.len() >= MAX_NESTING_DEPTH {
+            return Err(VMError::NestingTooDeep);
+        }
+        if prog.serialized_length() > MAX_PROGRAM_BYTES {
+            return Err(VMError::ProgramTooLarge);
+        }
         let new_run = self.delegate.new_run(prog)?;
         let paused_run = mem::replace(&mut self.current_run, new_run);
         self.run_stack.push(paused_run);
         Ok(())
     }
 
-    fn add_range_proof(&mut self, expr: Expression) -> Result<(), VMError> {
+    fn add_range_proof(&mut self, expr: Expression, n: BitRange) -> Result<(), VMError> {
         match expr {
             Expression::Constant(x) => {
-                if x.in_range() {
+                if x.in_bit_range(n) {
                     Ok(())
                 } else {
                     Err(VMError::InvalidBitrange)
@@ -727,7 +769,7 @@ where
                 self.delegate.cs(),
                 r1cs::LinearCombination::from_iter(terms),
                 ScalarWitness::option_to_integer(assignment)?,
-                BitRange::max(),
+                n,
             )
             .map_err(|_| VMError::R1CSInconsistency),
         }
@@ -1,13 +1,17 @@
+use bulletproofs::r1cs::R1CSProof;
 use bulletproofs::{BulletproofGens, PedersenGens};
 use curve25519_dalek::constants::RISTRETTO_BASEPOINT_COMPRESSED;
 use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
-use musig::{Signature, VerificationKey};
+use musig::{Multikey, Multimessage, MusigContext, Signature, Signer, VerificationKey};
 use rand::Rng;
+use spacesuit::cloak_multiplier_count;
 
 use zkvm::{
-    Anchor, Commitment, Contract, PortableItem, Predicate, PredicateTree, Program, Prover, String,
-    TxHeader, TxID, VMError, Value, Verifier,
+    Anchor, BurnProof, ClearValue, Commitment, Contract, ContractID, Item, PortableItem, Predicate,
+    PredicateTree, Program, ProgramItem, Prover, String, TranscriptProtocol, Tx, TxBuilder,
+    TxEntry, TxHeader, TxID, UnsignedTx, VMError, Value, Verifier, MAX_NESTING_DEPTH,
+    MAX_PROGRAM_BYTES, MAX_PUSH_BYTES,
 };
 
 // TODO(vniu): move builder convenience functions into separate crate,
@@ -109,49 +113,7 @@ fn make_output(qty: u64, flv: Scalar, predicate: Predicate) -> Contract {
 }
 
 fn build_and_verify(program: Program, keys: &Vec<Scalar>) -> Result<TxID, VMError> {
-    let tx = {
-        // Build tx
-        let bp_gens = BulletproofGens::new(256, 1);
-        let header = TxHeader {
-            version: 0u64,
-            mintime_ms: 0u64,
-            maxtime_ms: 0u64,
-        };
-        let gens = PedersenGens::default();
-        let utx = Prover::build_tx(program, header, &bp_gens)?;
-
-        let sig = if utx.signing_instructions.len() == 0 {
-            Signature {
-                R: RISTRETTO_BASEPOINT_COMPRESSED,
-                s: Scalar::zero(),
-            }
-        } else {
-            // find all the secret scalars for the pubkeys used in the VM
-            let privkeys: Vec<Scalar> = utx
-                .signing_instructions
-                .iter()
-                .filter_map(|(pubkey, _msg)| {
-                    for k in keys {
-                        if (k * gens.B).compress() == *pubkey.as_compressed() {
-                            return Some(*k);
-                        }
-                    }
-                    None
-                })
-                .collect();
-
-            let mut signtx_transcript = Transcript::new(b"ZkVM.signtx");
-            signtx_transcript.append_message(b"txid", &utx.txid.0);
-            Signature::sign_multi(
-                privkeys,
-                utx.signing_instructions.clone(),
-                &mut signtx_transcript,
-            )
-            .unwrap()
-        };
-
-        utx.sign(sig)
-    };
+    let tx = build_tx(program, keys)?;
 
     // Verify tx
     let bp_gens = BulletproofGens::new(256, 1);
@@ -160,6 +122,64 @@ fn build_and_verify(program: Program, keys: &Vec<Scalar>) -> Result<TxID, VMErro
     Ok(vtx.id)
 }
 
+fn default_header() -> TxHeader {
+    TxHeader {
+        version: 0u64,
+        mintime_ms: 0u64,
+        maxtime_ms: 0u64,
+    }
+}
+
+fn build_tx(program: Program, keys: &Vec<Scalar>) -> Result<Tx, VMError> {
+    // Build tx
+    let bp_gens = BulletproofGens::new(256, 1);
+    let gens = PedersenGens::default();
+    let utx = Prover::build_tx(program, default_header(), &bp_gens)?;
+
+    // find all the secret scalars for the pubkeys used in the VM
+    let privkeys: Vec<Scalar> = utx
+        .signing_instructions
+        .iter()
+        .filter_map(|(pubkey, _msg)| {
+            for k in keys {
+                if (k * gens.B).compress() == *pubkey.as_compressed() {
+                    return Some(*k);
+                }
+            }
+            None
+        })
+        .collect();
+
+    Ok(sign_tx(utx, privkeys, b""))
+}
+
+/// Returns the transcript for signing the txid of a transaction built for the `network`.
+fn signtx_transcript(txid: &TxID, network: &[u8]) -> Transcript {
+    let mut transcript = Transcript::new(b"ZkVM.signtx");
+    transcript.commit_network(network);
+    transcript.append_message(b"txid", &txid.0);
+    transcript
+}
+
+/// Signs the transaction built for the `network` with the secret keys
+/// of its `signtx` instructions, in the same order.
+fn sign_tx(utx: UnsignedTx, privkeys: Vec<Scalar>, network: &[u8]) -> Tx {
+    let sig = if utx.signing_instructions.len() == 0 {
+        Signature {
+            R: RISTRETTO_BASEPOINT_COMPRESSED,
+            s: Scalar::zero(),
+        }
+    } else {
+        Signature::sign_multi(
+            privkeys,
+            utx.signing_instructions.clone(),
+            &mut signtx_transcript(&utx.txid, network),
+        )
+        .unwrap()
+    };
+    utx.sign(sig)
+}
+
 fn spend_1_1_contract(
     input: u64,
     output: u64,
@@ -312,6 +332,344 @@ fn spend_2_1() {
     }
 }
 
+/// Spends a contract and unblinds the given commitment
+/// to the claimed quantity in the same transaction.
+fn unblind_contract(commitment: Commitment, claimed: u64, predicates: &[Predicate]) -> Program {
+    let flavor = Scalar::from(1u64);
+    Program::build(|p| {
+        p.input_helper(1u64, flavor, predicates[0].clone())
+            .cloak_helper(1, vec![(1u64, flavor)])
+            .output_helper(predicates[1].clone())
+            .push(commitment) // stack: V
+            .push(claimed) // stack: V, v
+            .unblind() // stack: V
+            .drop() // stack: empty
+    })
+}
+
+#[test]
+fn unblind() {
+    let (predicates, scalars) = generate_predicates(2);
+
+    let correct_program = unblind_contract(Commitment::unblinded(5u64), 5u64, &predicates);
+    match build_and_verify(correct_program, &scalars) {
+        Err(err) => assert!(false, err.to_string()),
+        _ => (),
+    }
+
+    // The prover knows the witness and refuses to prove a wrong value.
+    let wrong_value = unblind_contract(Commitment::unblinded(5u64), 6u64, &predicates);
+    assert_eq!(
+        build_and_verify(wrong_value, &scalars),
+        Err(VMError::InconsistentWitness)
+    );
+
+    // A commitment with a non-zero blinding factor cannot be unblinded.
+    let blinded = unblind_contract(Commitment::blinded(5u64), 5u64, &predicates);
+    assert_eq!(
+        build_and_verify(blinded, &scalars),
+        Err(VMError::InconsistentWitness)
+    );
+
+    // Without the witness the mismatch is caught by the verifier.
+    let closed = Commitment::Closed(Commitment::unblinded(5u64).to_point());
+    let wrong_closed = unblind_contract(closed, 6u64, &predicates);
+    assert_eq!(
+        build_and_verify(wrong_closed, &scalars),
+        Err(VMError::PointOperationFailed)
+    );
+}
+
+#[test]
+fn verifier_view_has_same_txid() {
+    let (predicates, scalars) = generate_predicates(2);
+    let program = spend_1_1_contract(
+        5u64,
+        5u64,
+        Scalar::from(1u64),
+        predicates[0].clone(),
+        predicates[1].clone(),
+    );
+    let bytecode = program.clone().to_verifier().to_bytecode().unwrap();
+
+    let utx = Prover::build_tx(
+        program.clone(),
+        default_header(),
+        &BulletproofGens::new(256, 1),
+    )
+    .unwrap();
+    assert_eq!(utx.program, bytecode);
+    assert_eq!(build_and_verify(program, &scalars), Ok(utx.txid));
+}
+
+#[test]
+fn verify_concurrent() {
+    let (predicates, scalars) = generate_predicates(4);
+    let bp_gens = BulletproofGens::new(256, 1);
+    let flavor = Scalar::from(1u64);
+    let program = |output_1| {
+        spend_2_2_contract(
+            6u64,
+            4u64,
+            output_1,
+            1u64,
+            flavor,
+            predicates[0].clone(),
+            predicates[1].clone(),
+            predicates[2].clone(),
+            predicates[3].clone(),
+        )
+    };
+
+    let tx = build_tx(program(9u64), &scalars).unwrap();
+    let vtx = Verifier::verify_tx_concurrent(&tx, &bp_gens).unwrap();
+    assert_eq!(vtx.id, Verifier::verify_tx(&tx, &bp_gens).unwrap().id);
+
+    // Bad signature
+    let mut bad_sig = tx.clone();
+    bad_sig.signature = Signature {
+        R: RISTRETTO_BASEPOINT_COMPRESSED,
+        s: Scalar::zero(),
+    };
+    assert_eq!(
+        Verifier::verify_tx_concurrent(&bad_sig, &bp_gens).err(),
+        Some(VMError::PointOperationFailed)
+    );
+
+    // Bad proof: the last scalar of the proof is modified
+    let mut bad_proof = tx.clone();
+    let mut proof_bytes = bad_proof.proof.to_bytes();
+    let last_scalar = proof_bytes.len() - 32;
+    proof_bytes[last_scalar] ^= 1;
+    bad_proof.proof = R1CSProof::from_bytes(&proof_bytes).unwrap();
+    assert_eq!(
+        Verifier::verify_tx_concurrent(&bad_proof, &bp_gens).err(),
+        Some(VMError::InvalidR1CSProof)
+    );
+}
+
+#[test]
+fn rerun_with_cloned_stack() {
+    let (predicate, key) = generate_predicate();
+    let stack = vec![
+        Item::String(String::Opaque(b"data".to_vec())),
+        Item::String(String::Commitment(Box::new(Commitment::blinded(3u64)))),
+        Item::Value(Value {
+            qty: Commitment::blinded(10u64),
+            flv: Commitment::blinded(Scalar::from(1u64)),
+        }),
+        Item::Program(ProgramItem::Bytecode(vec![0x02])),
+    ];
+    let cloned = stack
+        .iter()
+        .map(|item| item.try_clone())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    // The stack is unlocked from an input contract and locked again in an output.
+    let program = |stack: Vec<Item>| {
+        let k = stack.len();
+        let prev_output = Contract {
+            predicate: predicate.clone(),
+            payload: stack
+                .into_iter()
+                .map(|item| item.to_portable().unwrap())
+                .collect(),
+            anchor: Anchor::from_raw_bytes([0u8; 32]),
+        };
+        Program::build(|p| {
+            p.push(prev_output)
+                .input()
+                .sign_tx() // stack: items...
+                .push(predicate.clone())
+                .output(k)
+        })
+    };
+
+    // Both runs of the prover produce the same transaction.
+    let txid = build_and_verify(program(stack), &vec![key]).unwrap();
+    assert_eq!(build_and_verify(program(cloned), &vec![key]), Ok(txid));
+}
+
+#[test]
+fn dup_and_roll_bounds() {
+    let prog = Program::build(|p| p.push(String::default()).push(String::default()).dup(5));
+    assert_eq!(
+        build_and_verify(prog, &vec![]),
+        Err(VMError::StackUnderflow {
+            requested: 6,
+            available: 2
+        })
+    );
+
+    let prog = Program::build(|p| p.push(String::default()).roll(2));
+    assert_eq!(
+        build_and_verify(prog, &vec![]),
+        Err(VMError::StackUnderflow {
+            requested: 3,
+            available: 1
+        })
+    );
+
+    let prog = Program::build(|p| p.roll(0));
+    assert_eq!(
+        build_and_verify(prog, &vec![]),
+        Err(VMError::StackUnderflow {
+            requested: 1,
+            available: 0
+        })
+    );
+
+    // `roll 0` on a non-empty stack is a no-op.
+    let (predicates, scalars) = generate_predicates(2);
+    let flavor = Scalar::from(1u64);
+    let prog = Program::build(|p| {
+        p.input_helper(5u64, flavor, predicates[0].clone())
+            .roll(0)
+            .cloak_helper(1, vec![(5u64, flavor)])
+            .roll(0)
+            .output_helper(predicates[1].clone())
+    });
+    assert!(build_and_verify(prog, &scalars).is_ok());
+}
+
+#[test]
+fn tx_builder_2_2() {
+    let (predicates, scalars) = generate_predicates(4);
+    let flavor = Scalar::from(1u64);
+
+    let mut builder = TxBuilder::new();
+    builder
+        .input(make_output(6u64, flavor, predicates[0].clone()))
+        .input(make_output(4u64, flavor, predicates[1].clone()))
+        .output(
+            ClearValue {
+                qty: 9u64,
+                flv: flavor,
+            },
+            predicates[2].clone(),
+        )
+        .output(
+            ClearValue {
+                qty: 1u64,
+                flv: flavor,
+            },
+            predicates[3].clone(),
+        );
+    let program = builder.build().expect("values should balance");
+
+    match build_and_verify(program, &scalars) {
+        Err(err) => assert!(false, err.to_string()),
+        _ => (),
+    }
+
+    // Output quantities exceed the inputs.
+    let mut builder = TxBuilder::new();
+    builder
+        .input(make_output(6u64, flavor, predicates[0].clone()))
+        .input(make_output(4u64, flavor, predicates[1].clone()))
+        .output(
+            ClearValue {
+                qty: 11u64,
+                flv: flavor,
+            },
+            predicates[2].clone(),
+        )
+        .output(
+            ClearValue {
+                qty: 1u64,
+                flv: flavor,
+            },
+            predicates[3].clone(),
+        );
+    assert_eq!(builder.build().err(), Some(VMError::UnbalancedValues));
+
+    // Total quantity matches, but the flavors differ.
+    let other_flavor = Scalar::from(2u64);
+    let mut builder = TxBuilder::new();
+    builder
+        .input(make_output(6u64, flavor, predicates[0].clone()))
+        .input(make_output(4u64, other_flavor, predicates[1].clone()))
+        .output(
+            ClearValue {
+                qty: 10u64,
+                flv: flavor,
+            },
+            predicates[2].clone(),
+        );
+    assert_eq!(builder.build().err(), Some(VMError::UnbalancedValues));
+}
+
+#[test]
+fn tx_builder_pass_through() {
+    let (predicates, scalars) = generate_predicates(4);
+    let flavor = Scalar::from(1u64);
+    let passed = make_output(5u64, Scalar::from(2u64), predicates[1].clone());
+    let passed_value = match &passed.payload[0] {
+        PortableItem::Value(value) => value.clone(),
+        _ => unreachable!(),
+    };
+
+    let mut builder = TxBuilder::new();
+    builder
+        .input(make_output(6u64, flavor, predicates[0].clone()))
+        .output(
+            ClearValue {
+                qty: 6u64,
+                flv: flavor,
+            },
+            predicates[2].clone(),
+        )
+        .pass_through(passed.clone(), predicates[3].clone());
+    let program = builder.build().expect("values should balance");
+
+    let tx = build_tx(program, &scalars).unwrap();
+    let vtx = Verifier::verify_tx(&tx, &BulletproofGens::new(256, 1)).unwrap();
+
+    // The pass-through value is reproduced exactly in its own output.
+    let outputs: Vec<&Contract> = vtx
+        .log
+        .iter()
+        .filter_map(|entry| match entry {
+            TxEntry::Output(output) => Some(output),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(outputs.len(), 2);
+    let output = outputs
+        .iter()
+        .find(|output| output.predicate.to_point() == predicates[3].to_point())
+        .expect("pass-through output should be present");
+    match &output.payload[..] {
+        [PortableItem::Value(v)] => {
+            assert_eq!(v.qty.to_point(), passed_value.qty.to_point());
+            assert_eq!(v.flv.to_point(), passed_value.flv.to_point());
+        }
+        payload => panic!("unexpected payload {:?}", payload),
+    }
+    assert!(vtx.log.iter().any(|entry| match entry {
+        TxEntry::Input(id) => id == &passed.id(),
+        _ => false,
+    }));
+
+    // Pass-through values do not count towards the balance of the cloaked ones.
+    let mut builder = TxBuilder::new();
+    builder
+        .input(make_output(6u64, flavor, predicates[0].clone()))
+        .output(
+            ClearValue {
+                qty: 11u64,
+                flv: flavor,
+            },
+            predicates[2].clone(),
+        )
+        .pass_through(
+            make_output(5u64, flavor, predicates[1].clone()),
+            predicates[3].clone(),
+        );
+    assert_eq!(builder.build().err(), Some(VMError::UnbalancedValues));
+}
+
 fn spend_2_2_contract(
     input_1: u64,
     input_2: u64,
@@ -465,6 +823,51 @@ fn taproot_happy_path() {
     build_and_verify(prog, &vec![sk + factor]).unwrap();
 }
 
+#[test]
+fn contract_deadline() {
+    let (qty, flavor) = (101u64, Scalar::from(1u64));
+    let deadline_ms = 1000u64;
+    let owner_sk = Scalar::from(24u64);
+    let owner = Predicate::Key(VerificationKey::from_secret(&owner_sk));
+    let (output_pred, _) = generate_predicate();
+
+    // Locking a value with a deadline.
+    let lock = Program::build(|p| {
+        p.input_helper(qty, flavor, output_pred.clone())
+            .output_with_deadline(1, owner.clone(), deadline_ms, [0u8; 32])
+            .unwrap()
+    });
+    assert!(build_and_verify(lock, &vec![Scalar::from(0u64)]).is_ok());
+
+    // Spending the value: the deadline program re-locks it under the owner's key.
+    let tree = PredicateTree::with_deadline(owner, deadline_ms, 1, [0u8; 32]).unwrap();
+    let prev_output = make_output(qty, flavor, Predicate::Tree(tree.clone()));
+    let spend = Program::build(|p| {
+        p.push(prev_output)
+            .input()
+            .or_call(&tree, 0)
+            .unwrap()
+            .sign_tx()
+            .push(output_pred)
+            .output(1)
+    });
+    let header = |maxtime_ms| TxHeader {
+        maxtime_ms,
+        ..default_header()
+    };
+    let bp_gens = BulletproofGens::new(256, 1);
+
+    // Spend before the deadline succeeds.
+    assert!(build_and_verify(spend.clone(), &vec![owner_sk]).is_ok());
+    assert!(Prover::build_tx(spend.clone(), header(deadline_ms), &bp_gens).is_ok());
+
+    // Spend past the deadline fails.
+    assert_eq!(
+        Prover::build_tx(spend, header(deadline_ms + 1), &bp_gens).err(),
+        Some(VMError::InvalidBitrange)
+    );
+}
+
 #[test]
 fn taproot_program_path() {
     let sk = Scalar::from(24u64);
@@ -505,6 +908,44 @@ fn taproot_program_path() {
     }
 }
 
+#[test]
+fn disjunction_call() {
+    let (qty, flavor) = (101u64, Scalar::from(1u64));
+    let (output_pred, _) = generate_predicate();
+
+    let secrets: Vec<Scalar> = (0..3u64).map(|i| Scalar::from(1000 + i)).collect();
+    let branches = secrets
+        .iter()
+        .map(|s| spend_with_secret_scalar(qty, flavor, output_pred.clone(), *s))
+        .collect();
+    let tree = PredicateTree::disjunction(branches).unwrap();
+    let prev_output = make_output(qty, flavor, Predicate::Tree(tree.clone()));
+
+    let prog = Program::build(|p| {
+        p.push(secrets[1])
+            .push(prev_output.clone())
+            .input()
+            .or_call(&tree, 1)
+            .unwrap()
+    });
+    build_and_verify(prog, &vec![]).unwrap();
+
+    // Call proof for branch 1 does not match a program that is not in the tree.
+    let (call_proof, _) = tree.create_callproof(1).unwrap();
+    let forged_branch = spend_with_secret_scalar(qty, flavor, output_pred.clone(), Scalar::zero());
+    let forged_prog = Program::build(|p| {
+        p.push(Scalar::zero())
+            .push(prev_output.clone())
+            .input()
+            .push(String::Opaque(call_proof.to_bytes()))
+            .program(forged_branch)
+            .call()
+    });
+    if build_and_verify(forged_prog, &vec![]).is_ok() {
+        panic!("Calling a program outside of the predicate tree should have failed but didn't");
+    }
+}
+
 #[test]
 fn programs_cannot_be_copied_or_dropped() {
     let prog = Program::build(|p| {
@@ -514,7 +955,7 @@ fn programs_cannot_be_copied_or_dropped() {
 
     assert_eq!(
         build_and_verify(prog, &vec![]),
-        Err(VMError::TypeNotCopyable)
+        Err(VMError::TypeNotCopyable("Program"))
     );
 
     let prog = Program::build(|p| {
@@ -524,7 +965,7 @@ fn programs_cannot_be_copied_or_dropped() {
 
     assert_eq!(
         build_and_verify(prog, &vec![]),
-        Err(VMError::TypeNotCopyable)
+        Err(VMError::TypeNotCopyable("Program"))
     );
 }
 
@@ -537,7 +978,7 @@ fn expressions_cannot_be_copied_or_dropped() {
 
     assert_eq!(
         build_and_verify(prog, &vec![]),
-        Err(VMError::TypeNotCopyable)
+        Err(VMError::TypeNotCopyable("Expression"))
     );
 
     let prog = Program::build(|p| {
@@ -547,7 +988,7 @@ fn expressions_cannot_be_copied_or_dropped() {
 
     assert_eq!(
         build_and_verify(prog, &vec![]),
-        Err(VMError::TypeNotCopyable)
+        Err(VMError::TypeNotCopyable("Expression"))
     );
 }
 
@@ -562,7 +1003,7 @@ fn constraints_cannot_be_copied_or_dropped() {
 
     assert_eq!(
         build_and_verify(prog, &vec![]),
-        Err(VMError::TypeNotCopyable)
+        Err(VMError::TypeNotCopyable("Constraint"))
     );
 
     let prog = Program::build(|p| {
@@ -574,7 +1015,7 @@ fn constraints_cannot_be_copied_or_dropped() {
 
     assert_eq!(
         build_and_verify(prog, &vec![]),
-        Err(VMError::TypeNotCopyable)
+        Err(VMError::TypeNotCopyable("Constraint"))
     );
 }
 
@@ -596,3 +1037,624 @@ fn borrow_output() {
     });
     build_and_verify(borrow_prog, &vec![scalars[1].clone()]).unwrap();
 }
+
+#[test]
+fn borrow_cancels_in_cloak() {
+    // borrows 5 units and merges them back, netting to zero
+    let flv = Scalar::from(1u64);
+    let (preds, scalars) = generate_predicates(1);
+    let borrow_and_cancel = |output_qty: u64| {
+        Program::build(|p| {
+            // Spending an input provides an anchor for the output
+            p.input_helper(10, flv, preds[0].clone())
+                .output_helper(preds[0].clone())
+                .push(Commitment::blinded(5u64)) // stack: qty(5)
+                .var() // stack: qty-var(5)
+                .push(Commitment::blinded(flv)) // stack: qty-var(5), flv(1)
+                .var() // stack: qty-var(5), flv-var(1)
+                .borrow() // stack: Value(-5,1), Value(5,1)
+                .cloak_helper(2, vec![(output_qty, flv)]) // stack: Value(output_qty,1)
+                .output_helper(preds[0].clone()) // outputs (output_qty,1)
+        })
+    };
+    build_and_verify(borrow_and_cancel(0), &scalars).unwrap();
+    assert!(build_and_verify(borrow_and_cancel(1), &scalars).is_err());
+}
+
+#[test]
+fn anchor_required() {
+    let flv = Scalar::from(1u64);
+    let (preds, scalars) = generate_predicates(2);
+    let (issuance_scalar, issuance_pred, flavor) = make_flavor();
+
+    // A transaction that spends nothing cannot be made unique.
+    assert_eq!(
+        build_tx(Program::new(), &vec![]).err(),
+        Some(VMError::AnchorMissing)
+    );
+
+    // Issued and borrowed values cannot be output without an input providing the anchor.
+    let issue_prog = Program::build(|p| {
+        p.issue_helper(5, flavor, issuance_pred.clone())
+            .output_helper(preds[0].clone())
+    });
+    assert_eq!(
+        build_tx(issue_prog, &vec![issuance_scalar]).err(),
+        Some(VMError::AnchorMissing)
+    );
+    let borrow_prog = Program::build(|p| {
+        p.push(Commitment::blinded(5u64))
+            .var()
+            .push(Commitment::blinded(flv))
+            .var()
+            .borrow() // stack: Value(-5,1), Value(5,1)
+            .output_helper(preds[0].clone())
+    });
+    assert_eq!(
+        build_tx(borrow_prog, &vec![]).err(),
+        Some(VMError::AnchorMissing)
+    );
+
+    // With an input, each output gets a fresh anchor ratcheted from the previous one.
+    let prog = Program::build(|p| {
+        p.input_helper(10, flv, preds[0].clone())
+            .issue_helper(5, flavor, issuance_pred)
+            .output_helper(preds[1].clone())
+            .output_helper(preds[1].clone())
+    });
+    let tx = build_tx(prog, &vec![scalars[0], issuance_scalar]).unwrap();
+    let vtx = Verifier::verify_tx(&tx, &BulletproofGens::new(256, 1)).unwrap();
+    let anchors: Vec<[u8; 32]> = vtx
+        .log
+        .iter()
+        .filter_map(|entry| match entry {
+            TxEntry::Output(contract) => Some(contract.anchor.0),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(anchors.len(), 2);
+    assert_ne!(anchors[0], anchors[1]);
+}
+
+#[test]
+fn fee_entries() {
+    let flv = Scalar::from(1u64);
+    let (preds, scalars) = generate_predicates(2);
+    let fee_prog = |fee1: Scalar, fee2: Scalar| {
+        Program::build(|p| {
+            p.input_helper(10, flv, preds[0].clone())
+                .output_helper(preds[1].clone())
+                .push(fee1)
+                .fee()
+                .push(fee2)
+                .fee()
+        })
+    };
+
+    let bp_gens = BulletproofGens::new(256, 1);
+    let utx = Prover::build_tx(
+        fee_prog(Scalar::from(3u64), Scalar::from(4u64)),
+        default_header(),
+        &bp_gens,
+    )
+    .unwrap();
+    let fees: Vec<u64> = utx
+        .txlog
+        .iter()
+        .filter_map(|entry| match entry {
+            TxEntry::Fee(fee) => Some(*fee),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(fees, vec![3, 4]);
+    assert_eq!(utx.fee, 7);
+
+    build_and_verify(
+        fee_prog(Scalar::from(3u64), Scalar::from(4u64)),
+        &vec![scalars[0]],
+    )
+    .unwrap();
+
+    // Negative fee does not pass the range check.
+    assert_eq!(
+        build_and_verify(
+            fee_prog(Scalar::from(3u64), -Scalar::from(4u64)),
+            &vec![scalars[0]],
+        ),
+        Err(VMError::InvalidBitrange)
+    );
+
+    // Total fee must fit in 64 bits.
+    assert_eq!(
+        build_and_verify(
+            fee_prog(Scalar::from(u64::max_value()), Scalar::from(1u64)),
+            &vec![scalars[0]],
+        ),
+        Err(VMError::FeeTooHigh)
+    );
+}
+
+#[test]
+fn network_labels() {
+    let (issuance_scalar, issuance_pred, _) = make_flavor();
+    let (output_pred, output_key) = generate_predicate();
+
+    assert_eq!(
+        Value::issue_flavor(&issuance_pred, String::default()),
+        Value::issue_flavor_for_network(b"", &issuance_pred, String::default())
+    );
+    let testnet_flavor =
+        Value::issue_flavor_for_network(b"testnet", &issuance_pred, String::default());
+    let mainnet_flavor =
+        Value::issue_flavor_for_network(b"mainnet", &issuance_pred, String::default());
+    assert_ne!(testnet_flavor, mainnet_flavor);
+
+    // Build and sign the issuance tx for the testnet
+    let bp_gens = BulletproofGens::new(256, 1);
+    let program = Program::build(|p| {
+        // Spending an input provides an anchor for the issuance contract
+        p.input_helper(1u64, Scalar::from(1u64), output_pred.clone())
+            .output_helper(output_pred.clone())
+            .issue_helper(5u64, testnet_flavor, issuance_pred.clone())
+            .output_helper(output_pred.clone())
+    });
+    let utx =
+        Prover::build_tx_for_network(program, default_header(), b"testnet", &bp_gens).unwrap();
+    let tx = sign_tx(utx, vec![output_key, issuance_scalar], b"testnet");
+
+    assert!(Verifier::verify_tx_for_network(&tx, b"testnet", &bp_gens).is_ok());
+    assert!(Verifier::verify_tx_for_network(&tx, b"mainnet", &bp_gens).is_err());
+    assert!(Verifier::verify_tx(&tx, &bp_gens).is_err());
+    assert!(Verifier::verify_tx_concurrent_for_network(&tx, b"testnet", &bp_gens).is_ok());
+    assert!(Verifier::verify_tx_concurrent_for_network(&tx, b"mainnet", &bp_gens).is_err());
+}
+
+/// Context for signing a transaction spending a contract locked with a MuSig multikey:
+/// the parties of the multikey jointly sign the `signtx` multimessage for the aggregated key.
+struct MultikeySigntx {
+    multikey: Multikey,
+    signtx: Multimessage<ContractID>,
+}
+
+impl MusigContext for MultikeySigntx {
+    fn commit(&self, transcript: &mut Transcript) {
+        self.signtx.commit(transcript);
+    }
+
+    fn challenge(&self, i: usize, transcript: &mut Transcript) -> Scalar {
+        // The aggregated key is the only key in the multimessage, so its challenge
+        // is split between the parties by their delinearization factors.
+        self.signtx.challenge(0, transcript) * self.multikey.factor(i)
+    }
+
+    fn len(&self) -> usize {
+        self.multikey.len()
+    }
+
+    fn key(&self, index: usize) -> VerificationKey {
+        self.multikey.key(index)
+    }
+}
+
+#[test]
+fn spend_multikey_contract() {
+    // Two parties guard the contract with their MuSig aggregated key.
+    let mut privkeys = vec![Scalar::from(10u64), Scalar::from(20u64)];
+    let multikey = Multikey::new(
+        privkeys
+            .iter()
+            .map(|x| VerificationKey::from_secret(x))
+            .collect(),
+    )
+    .unwrap();
+    let (output_pred, _) = generate_predicate();
+    let flavor = Scalar::from(1u64);
+    let build_utx = || {
+        let program = spend_1_1_contract(
+            10u64,
+            10u64,
+            flavor,
+            Predicate::key(multikey.aggregated_key()),
+            output_pred.clone(),
+        );
+        Prover::build_tx(program, default_header(), &BulletproofGens::new(256, 1)).unwrap()
+    };
+
+    // Each party signs the txid in the MuSig rounds,
+    // indexed by the position of its key in the (sorted) multikey.
+    privkeys.sort_by_key(|x| multikey.position(&VerificationKey::from_secret(x)).unwrap());
+    let utx = build_utx();
+    let transcript = signtx_transcript(&utx.txid, b"");
+    let mut transcripts: Vec<_> = privkeys.iter().map(|_| transcript.clone()).collect();
+    let (parties, precomms): (Vec<_>, Vec<_>) = privkeys
+        .iter()
+        .zip(transcripts.iter_mut())
+        .enumerate()
+        .map(|(i, (x_i, transcript))| {
+            let context = MultikeySigntx {
+                multikey: multikey.clone(),
+                signtx: Multimessage::new(utx.signing_instructions.clone()),
+            };
+            Signer::new(transcript, i, *x_i, context)
+        })
+        .unzip();
+    let (parties, comms): (Vec<_>, Vec<_>) = parties
+        .into_iter()
+        .map(|p| p.receive_precommitments(precomms.clone()))
+        .unzip();
+    let (parties, shares): (Vec<_>, Vec<_>) = parties
+        .into_iter()
+        .map(|p| p.receive_commitments(comms.clone()).unwrap())
+        .unzip();
+
+    // Any party can aggregate the shares into the transaction signature.
+    let mut aggregator = parties
+        .into_iter()
+        .next()
+        .unwrap()
+        .into_aggregating_signature();
+    for (i, share) in shares.into_iter().enumerate() {
+        aggregator.add_share(i, share).unwrap();
+    }
+    let tx = utx.sign(aggregator.finalize().unwrap());
+    assert!(Verifier::verify_tx(&tx, &BulletproofGens::new(256, 1)).is_ok());
+
+    // Either of the individual keys cannot sign for the contract.
+    let tx = sign_tx(build_utx(), vec![privkeys[0]], b"");
+    assert!(Verifier::verify_tx(&tx, &BulletproofGens::new(256, 1)).is_err());
+}
+
+/// Creates a program that makes `depth` nested calls, each into a contract
+/// locked with a predicate tree committing to the next nested program.
+fn nested_calls(depth: usize, input_pred: Predicate, output_pred: Predicate) -> Program {
+    let flv = Scalar::from(1u64);
+    let mut tree = PredicateTree::disjunction(vec![Program::new()]).unwrap();
+    for _ in 1..depth {
+        let inner = Program::build(|p| {
+            p.push(Predicate::Tree(tree.clone()))
+                .contract(0)
+                .or_call(&tree, 0)
+                .unwrap()
+        });
+        tree = PredicateTree::disjunction(vec![inner]).unwrap();
+    }
+    Program::build(|p| {
+        // Spending an input provides an anchor for the nested contracts
+        p.input_helper(1, flv, input_pred)
+            .output_helper(output_pred)
+            .push(Predicate::Tree(tree.clone()))
+            .contract(0)
+            .or_call(&tree, 0)
+            .unwrap()
+    })
+}
+
+#[test]
+fn nesting_depth_limit() {
+    let (preds, scalars) = generate_predicates(2);
+
+    let prog = nested_calls(MAX_NESTING_DEPTH, preds[0].clone(), preds[1].clone());
+    build_and_verify(prog, &scalars).unwrap();
+
+    let prog = nested_calls(MAX_NESTING_DEPTH + 1, preds[0].clone(), preds[1].clone());
+    assert_eq!(
+        build_and_verify(prog, &scalars).unwrap_err(),
+        VMError::NestingTooDeep
+    );
+}
+
+#[test]
+fn program_size_limit() {
+    // Size is checked before the program is executed
+    let prog = Program::build(|p| p.push(String::Opaque(vec![0u8; MAX_PROGRAM_BYTES])));
+    assert_eq!(
+        build_and_verify(prog, &vec![]).unwrap_err(),
+        VMError::ProgramTooLarge
+    );
+}
+
+#[test]
+fn push_size_limit() {
+    // Push size is checked before the program is executed
+    let prog = Program::build(|p| p.push(String::Opaque(vec![0u8; MAX_PUSH_BYTES + 1])));
+    assert_eq!(
+        build_and_verify(prog, &vec![]).unwrap_err(),
+        VMError::PushTooLarge
+    );
+}
+
+#[test]
+fn vm_stats() {
+    let (preds, scalars) = generate_predicates(4);
+    let flv = Scalar::from(1u64);
+    let program = Program::build(|p| {
+        p.input_helper(5, flv, preds[0].clone())
+            .input_helper(7, flv, preds[1].clone())
+            .cloak_helper(2, vec![(4, flv), (8, flv)])
+            .output_helper(preds[2].clone())
+            .output_helper(preds[3].clone())
+    });
+    let instructions = program.clone().to_vec().len();
+
+    let bp_gens = BulletproofGens::new(256, 1);
+    let utx = Prover::build_tx(program, default_header(), &bp_gens).unwrap();
+
+    assert_eq!(utx.stats.multipliers, cloak_multiplier_count(2, 2));
+    assert_eq!(utx.stats.instructions, instructions);
+
+    let stats = utx.stats;
+    let tx = sign_tx(utx, vec![scalars[0], scalars[1]], b"");
+
+    // Verifier rebuilds the same constraint system
+    let vtx = Verifier::verify_tx(&tx, &bp_gens).unwrap();
+    assert_eq!(vtx.stats, stats);
+}
+
+#[test]
+fn range_bits() {
+    let (preds, scalars) = generate_predicates(1);
+    let flv = Scalar::from(1u64);
+    // Spending an input provides an anchor for the transaction,
+    // and the range-checked expression is consumed by an equality check.
+    let range_var = |qty: u64| {
+        Program::build(|p| {
+            p.input_helper(1, flv, preds[0].clone())
+                .output_helper(preds[0].clone())
+                .push(Commitment::blinded(qty))
+                .var() // stack: var
+                .dup(0)
+                .expr()
+                .range_bits(8) // stack: var, expr
+                .roll(1)
+                .expr()
+                .eq()
+                .verify()
+        })
+    };
+    let range_const = |qty: u64| {
+        Program::build(|p| {
+            p.input_helper(1, flv, preds[0].clone())
+                .output_helper(preds[0].clone())
+                .push(Scalar::from(qty))
+                .r#const()
+                .range_bits(8)
+                .push(Scalar::from(qty))
+                .r#const()
+                .eq()
+                .verify()
+        })
+    };
+
+    build_and_verify(range_var(255), &scalars).unwrap();
+    build_and_verify(range_const(255), &scalars).unwrap();
+
+    assert!(build_and_verify(range_var(256), &scalars).is_err());
+    assert_eq!(
+        build_and_verify(range_const(256), &scalars),
+        Err(VMError::InvalidBitrange)
+    );
+}
+
+#[test]
+fn mismatched_gens() {
+    let (preds, scalars) = generate_predicates(1);
+    let flv = Scalar::from(1u64);
+    let program = || {
+        Program::build(|p| {
+            p.input_helper(1, flv, preds[0].clone())
+                .output_helper(preds[0].clone())
+                .push(Commitment::blinded(1u64))
+                .var() // stack: var
+                .dup(0)
+                .expr()
+                .range() // stack: var, expr
+                .roll(1)
+                .expr()
+                .eq()
+                .verify()
+        })
+    };
+    let (_, bp_gens) = zkvm::gens();
+    let small_gens = BulletproofGens::new(16, 1);
+    let multipliers = Prover::build_tx(program(), default_header(), &bp_gens)
+        .unwrap()
+        .stats
+        .multipliers;
+    let insufficient = Some(VMError::InvalidGenerators {
+        needed: multipliers.next_power_of_two(),
+        available: 16,
+    });
+
+    // Prover with the generators of insufficient capacity.
+    assert_eq!(
+        Prover::build_tx(program(), default_header(), &small_gens).err(),
+        insufficient
+    );
+
+    // Verifier with the generators of insufficient capacity.
+    // Generators of a smaller, but sufficient capacity begin with the same points
+    // as the canonical ones, so the transaction built with them is valid.
+    let tx = build_tx(program(), &scalars).unwrap();
+    assert_eq!(Verifier::verify_tx(&tx, &small_gens).err(), insufficient);
+    assert!(Verifier::verify_tx(&tx, &bp_gens).is_ok());
+}
+
+#[test]
+fn insufficient_gens_for_cloak() {
+    // The check happens before the program is executed,
+    // so the stack does not need to contain the cloaked values.
+    let program = Program::build(|p| p.cloak(16, 16));
+    assert_eq!(
+        program.cloak_multiplier_count(),
+        cloak_multiplier_count(16, 16)
+    );
+    assert_eq!(
+        Prover::build_tx(program, default_header(), &BulletproofGens::new(64, 1)).err(),
+        Some(VMError::InsufficientGens {
+            needed: cloak_multiplier_count(16, 16).next_power_of_two(),
+            available: 64,
+        })
+    );
+}
+
+#[test]
+fn identical_outputs_have_distinct_ids() {
+    let (predicate, _) = generate_predicate();
+    let flavor = Scalar::from(1u64);
+    let program = Program::build(|p| {
+        p.input_helper(1, flavor, predicate.clone()) // stack: input-value
+            .output_helper(predicate.clone())
+            .push(String::Opaque(b"payload".to_vec()))
+            .push(predicate.clone())
+            .output(1)
+            .push(String::Opaque(b"payload".to_vec()))
+            .push(predicate.clone())
+            .output(1)
+    });
+    let utx = Prover::build_tx(program, default_header(), &BulletproofGens::new(256, 1)).unwrap();
+    let outputs: Vec<Contract> = utx
+        .txlog
+        .into_iter()
+        .filter_map(|entry| match entry {
+            TxEntry::Output(contract) => Some(contract),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(outputs.len(), 3);
+
+    // Each output consumes the anchor left by the previous one,
+    // so otherwise identical contracts get distinct IDs.
+    let (a, b) = (&outputs[1], &outputs[2]);
+    assert_eq!(a.predicate.to_point(), b.predicate.to_point());
+    assert_ne!(a.anchor.as_bytes(), b.anchor.as_bytes());
+    assert_ne!(a.id(), b.id());
+}
+
+#[test]
+fn relock_contract() {
+    let (predicates, scalars) = generate_predicates(2);
+    let flavor = Scalar::from(1u64);
+    let contract = make_output(10, flavor, predicates[0].clone());
+    let value = match &contract.payload[0] {
+        PortableItem::Value(value) => value.clone(),
+        _ => unreachable!(),
+    };
+
+    let program = Program::build(|p| p.relock(contract.clone(), predicates[1].clone()));
+    let tx = build_tx(program, &vec![scalars[0]]).unwrap();
+    let vtx = Verifier::verify_tx(&tx, &BulletproofGens::new(256, 1)).unwrap();
+
+    // The contract is spent and re-locked with the same value under the new predicate.
+    assert_eq!(vtx.log.len(), 3);
+    match &vtx.log[1] {
+        TxEntry::Input(id) => assert_eq!(id, &contract.id()),
+        entry => panic!("expected input entry, got {:?}", entry),
+    }
+    let output = match &vtx.log[2] {
+        TxEntry::Output(output) => output,
+        entry => panic!("expected output entry, got {:?}", entry),
+    };
+    assert_eq!(output.predicate.to_point(), predicates[1].to_point());
+    match &output.payload[..] {
+        [PortableItem::Value(v)] => {
+            assert_eq!(v.qty.to_point(), value.qty.to_point());
+            assert_eq!(v.flv.to_point(), value.flv.to_point());
+        }
+        _ => panic!("expected a single value in the payload"),
+    }
+}
+
+#[test]
+fn issue_and_retire_log() {
+    let (issuance_scalar, issuance_pred, flavor) = make_flavor();
+    let (input_pred, input_key) = generate_predicate();
+    let program = Program::build(|p| {
+        // Spending an input provides an anchor for the issuance contract
+        p.input_helper(1u64, Scalar::from(1u64), input_pred.clone())
+            .output_helper(input_pred.clone())
+            .issue_helper(5u64, flavor, issuance_pred.clone()) // stack: issued-value
+            .retire()
+    });
+
+    let tx = build_tx(program, &vec![input_key, issuance_scalar]).unwrap();
+    let vtx = Verifier::verify_tx(&tx, &BulletproofGens::new(256, 1)).unwrap();
+
+    // After the header, input and output, the log records the issuance
+    // and then the retirement of the same value.
+    assert_eq!(vtx.log.len(), 5);
+    let (issued_qty, issued_flv) = match &vtx.log[3] {
+        TxEntry::Issue(qty, flv) => (qty, flv),
+        entry => panic!("expected issue entry, got {:?}", entry),
+    };
+    let (retired_qty, retired_flv) = match &vtx.log[4] {
+        TxEntry::Retire(qty, flv) => (qty, flv),
+        entry => panic!("expected retire entry, got {:?}", entry),
+    };
+    assert_eq!(issued_qty, retired_qty);
+    assert_eq!(issued_flv, retired_flv);
+    assert_eq!(*issued_flv, Commitment::unblinded(flavor).to_point());
+}
+
+#[test]
+fn burn_proof() {
+    let (issuance_scalar, issuance_pred, flavor) = make_flavor();
+    let (input_pred, input_key) = generate_predicate();
+    let program = Program::build(|p| {
+        // Spending an input provides an anchor for the issuance contract
+        p.input_helper(1u64, Scalar::from(1u64), input_pred.clone())
+            .output_helper(input_pred.clone())
+            .issue_helper(100u64, flavor, issuance_pred.clone()) // stack: issued-value
+            .retire()
+    });
+
+    let tx = build_tx(program, &vec![input_key, issuance_scalar]).unwrap();
+    let vtx = Verifier::verify_tx(&tx, &BulletproofGens::new(256, 1)).unwrap();
+
+    // `issue_helper` blinds the quantity with the factor 1 and leaves the flavor unblinded.
+    let value = ClearValue {
+        qty: 100,
+        flv: flavor,
+    };
+    let proof = BurnProof::new(&vtx.log, value, Scalar::from(1u64), Scalar::zero()).unwrap();
+    proof.verify(&vtx.id).unwrap();
+
+    let decoded = BurnProof::from_bytes(&proof.to_bytes()).unwrap();
+    decoded.verify(&vtx.id).unwrap();
+
+    let mut tampered = decoded;
+    tampered.value.qty = 101;
+    assert_eq!(tampered.verify(&vtx.id), Err(VMError::InvalidMerkleProof));
+    assert_eq!(
+        BurnProof::new(&vtx.log, tampered.value, Scalar::from(1u64), Scalar::zero()).err(),
+        Some(VMError::InvalidMerkleProof)
+    );
+}
+
+#[test]
+fn optimized_program_has_same_txid() {
+    let (input_pred, input_key) = generate_predicate();
+    let (output_pred, _) = generate_predicate();
+    let flv = Scalar::from(1u64);
+
+    // Each program contains one of the redundant sequences removed by the optimizer.
+    let patterns: Vec<fn(&mut Program) -> &mut Program> = vec![
+        |p| p.push(String::Opaque(vec![1])).dup(0).drop().drop(),
+        |p| p.roll(0),
+        |p| p.push(String::Opaque(vec![1, 2, 3])).drop(),
+    ];
+    for pattern in patterns {
+        let program = Program::build(|p| {
+            p.input_helper(10u64, flv, input_pred.clone()); // stack: input-value
+            pattern(p)
+                .cloak_helper(1, vec![(10u64, flv)])
+                .output_helper(output_pred.clone())
+        });
+        let optimized = program.clone().optimize();
+        assert!(optimized.to_bytes().len() < program.to_bytes().len());
+
+        assert_eq!(
+            build_and_verify(program, &vec![input_key]).unwrap(),
+            build_and_verify(optimized, &vec![input_key]).unwrap()
+        );
+    }
+}